@@ -0,0 +1,569 @@
+// Plays parser-style text adventures, such as the one aboard Santa's ship.
+//
+// The game describes each room it enters like this, then waits for a line of
+// input:
+//
+//     == Hull Breach ==
+//     You got in through a hole in the floor here.
+//
+//     Doors here lead:
+//     - north
+//     - west
+//
+//     Items here:
+//     - mug
+//
+//     Command?
+//
+// Commands are `north`, `south`, `east`, `west`, `take <item>`,
+// `drop <item>` and `inv`. The `Driver` keeps track of the rooms and doors it
+// has seen and what it is carrying. Some items are deadly, so it can save the
+// machine state and roll back; what it has learned about the ship is kept
+// across a rollback.
+
+use crate::computer::{decode_ascii, Computer, Fault, Status};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// Instructions a single command may take before the game is assumed to be
+// stuck in a loop.
+const DEFAULT_BUDGET: u64 = 10_000_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Room {
+    pub name: String,
+    pub description: String,
+    pub doors: Vec<String>,
+    pub items: Vec<String>,
+}
+
+// Parses every room described in `text`, in order.
+pub fn parse_rooms(text: &str) -> Vec<Room> {
+    let mut rooms = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("== ") {
+        let end = rest[start + 3..]
+            .find("\n== ")
+            .map_or(rest.len(), |end| start + 4 + end);
+        rooms.extend(parse_room(&rest[start..end]));
+        rest = &rest[end..];
+    }
+    rooms
+}
+
+// Parses the last room described in `text`.
+pub fn parse_room(text: &str) -> Option<Room> {
+    let start = text.rfind("== ")?;
+    let mut lines = text[start..].lines();
+    let name = lines.next()?.trim_matches(|c| c == '=' || c == ' ');
+    let mut room = Room {
+        name: name.to_string(),
+        description: String::new(),
+        doors: Vec::new(),
+        items: Vec::new(),
+    };
+    let mut list = None;
+    for line in lines {
+        match line {
+            "Doors here lead:" => list = Some(&mut room.doors),
+            "Items here:" => list = Some(&mut room.items),
+            "" => list = None,
+            _ => match (line.strip_prefix("- "), &mut list) {
+                (Some(entry), Some(list)) => list.push(entry.to_string()),
+                _ if room.description.is_empty() => room.description = line.to_string(),
+                _ => {}
+            },
+        }
+    }
+    Some(room)
+}
+
+pub fn opposite(direction: &str) -> Option<&'static str> {
+    match direction {
+        "north" => Some("south"),
+        "south" => Some("north"),
+        "east" => Some("west"),
+        "west" => Some("east"),
+        _ => None,
+    }
+}
+
+// The parts of a driver that a rollback restores.
+#[derive(Clone)]
+pub struct Snapshot {
+    computer: Computer,
+    room: Option<String>,
+    inventory: Vec<String>,
+    halted: bool,
+}
+
+pub struct Driver {
+    state: Snapshot,
+    budget: u64,
+    rooms: HashMap<String, Room>,
+    doors: HashMap<(String, String), String>,
+    dangerous: HashSet<String>,
+    // The room and door where the game sent us back instead of letting us
+    // through.
+    checkpoint: Option<(String, String)>,
+}
+
+impl Driver {
+    pub fn new(program: &[i64]) -> Driver {
+        Driver {
+            state: Snapshot {
+                computer: Computer::new(program),
+                room: None,
+                inventory: Vec::new(),
+                halted: false,
+            },
+            budget: DEFAULT_BUDGET,
+            rooms: HashMap::new(),
+            doors: HashMap::new(),
+            dangerous: HashSet::new(),
+            checkpoint: None,
+        }
+    }
+
+    pub fn with_budget(mut self, budget: u64) -> Driver {
+        self.budget = budget;
+        self
+    }
+
+    pub fn room(&self) -> Option<&Room> {
+        self.rooms.get(self.state.room.as_ref()?)
+    }
+
+    pub fn rooms(&self) -> &HashMap<String, Room> {
+        &self.rooms
+    }
+
+    pub fn inventory(&self) -> &[String] {
+        &self.state.inventory
+    }
+
+    pub fn dangerous(&self) -> &HashSet<String> {
+        &self.dangerous
+    }
+
+    pub fn checkpoint(&self) -> Option<&(String, String)> {
+        self.checkpoint.as_ref()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.state.halted
+    }
+
+    pub fn save(&self) -> Snapshot {
+        self.state.clone()
+    }
+
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.state = snapshot;
+    }
+
+    // Reads the game's introduction, up to the first prompt.
+    pub fn start(&mut self) -> Result<String, Fault> {
+        self.read()
+    }
+
+    // Sends one command and returns the game's answer.
+    pub fn send(&mut self, command: &str) -> Result<String, Fault> {
+        self.state.computer.push_ascii(command);
+        self.state.computer.push_input(10);
+        let from = self.state.room.clone();
+        let text = self.read()?;
+
+        if let Some(item) = text.lines().find_map(|l| l.strip_prefix("You take the ")) {
+            self.state
+                .inventory
+                .push(item.trim_end_matches('.').to_string());
+        }
+        if let Some(item) = text.lines().find_map(|l| l.strip_prefix("You drop the ")) {
+            let item = item.trim_end_matches('.');
+            self.state.inventory.retain(|i| i != item);
+        }
+        if let (Some(from), Some(to)) = (from, &self.state.room) {
+            if opposite(command).is_some() && text.contains("== ") {
+                if from == *to {
+                    self.checkpoint = Some((from, command.to_string()));
+                } else {
+                    // doors are assumed to work both ways
+                    let back = opposite(command).unwrap().to_string();
+                    self.doors.insert((to.clone(), back), from.clone());
+                    self.doors.insert((from, command.to_string()), to.clone());
+                }
+            }
+        }
+        Ok(text)
+    }
+
+    // Sends every line of `script`, skipping blank lines and `#` comments,
+    // and returns everything the game printed.
+    pub fn run_script(&mut self, script: &str) -> Result<String, Fault> {
+        let mut transcript = String::new();
+        for line in script.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            transcript.push_str(&self.send(line)?);
+            if self.state.halted {
+                break;
+            }
+        }
+        Ok(transcript)
+    }
+
+    // Shortest sequence of doors from the current room to `room`.
+    pub fn path_to(&self, room: &str) -> Option<Vec<String>> {
+        let start = self.state.room.clone()?;
+        let mut previous: HashMap<String, (String, String)> = HashMap::new();
+        let mut queue = VecDeque::from(vec![start.clone()]);
+        while let Some(current) = queue.pop_front() {
+            if current == room {
+                let mut path = Vec::new();
+                let mut at = current;
+                while at != start {
+                    let (from, door) = previous[&at].clone();
+                    path.push(door);
+                    at = from;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for ((from, door), to) in &self.doors {
+                if *from == current && *to != start && !previous.contains_key(to) {
+                    previous.insert(to.clone(), (current.clone(), door.clone()));
+                    queue.push_back(to.clone());
+                }
+            }
+        }
+        None
+    }
+
+    pub fn go_to(&mut self, room: &str) -> Result<bool, Fault> {
+        let path = match self.path_to(room) {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+        for door in path {
+            self.send(&door)?;
+        }
+        Ok(self.state.room.as_deref() == Some(room))
+    }
+
+    // Visits every room reachable from the current one, picking up every item
+    // that is safe to carry, and comes back.
+    pub fn explore(&mut self) -> Result<(), Fault> {
+        if self.state.room.is_none() {
+            self.read()?;
+        }
+        let mut visited = HashSet::new();
+        self.visit(&mut visited)
+    }
+
+    fn visit(&mut self, visited: &mut HashSet<String>) -> Result<(), Fault> {
+        let room = match self.room() {
+            Some(room) => room.clone(),
+            None => return Ok(()),
+        };
+        visited.insert(room.name.clone());
+        for item in &room.items {
+            if !self.dangerous.contains(item) && self.is_safe(item, &room)? {
+                self.send(&format!("take {}", item))?;
+            }
+        }
+        for door in &room.doors {
+            let back = match opposite(door) {
+                Some(back) => back,
+                None => continue,
+            };
+            if self.doors.contains_key(&(room.name.clone(), door.clone())) {
+                continue;
+            }
+            self.send(door)?;
+            let here = self.state.room.clone();
+            if here.as_ref() == Some(&room.name) {
+                continue;
+            }
+            if !visited.contains(here.as_ref().unwrap()) {
+                self.visit(visited)?;
+            }
+            self.send(back)?;
+        }
+        Ok(())
+    }
+
+    // Takes `item` on trial and checks the droid can still move, then rolls
+    // back.
+    fn is_safe(&mut self, item: &str, room: &Room) -> Result<bool, Fault> {
+        let snapshot = self.save();
+        let mut safe = self.send(&format!("take {}", item)).is_ok() && !self.state.halted;
+        if safe {
+            if let Some(door) = room.doors.first() {
+                safe = match self.send(door) {
+                    Ok(text) => text.contains("== ") && !self.state.halted,
+                    Err(_) => false,
+                };
+            }
+        }
+        self.restore(snapshot);
+        if !safe {
+            self.dangerous.insert(item.to_string());
+        }
+        Ok(safe)
+    }
+
+    // Goes to the checkpoint found while exploring and tries combinations of
+    // the items carried until the game lets the droid through. Returns what
+    // the game printed on success.
+    pub fn solve_checkpoint(&mut self) -> Result<Option<String>, Fault> {
+        let (room, door) = match self.checkpoint.clone() {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(None),
+        };
+        if !self.go_to(&room)? {
+            return Ok(None);
+        }
+        let snapshot = self.save();
+        let items = self.state.inventory.clone();
+        for mask in 0..1u64 << items.len() {
+            self.restore(snapshot.clone());
+            for (i, item) in items.iter().enumerate() {
+                if mask & (1 << i) == 0 {
+                    self.send(&format!("drop {}", item))?;
+                }
+            }
+            let text = self.send(&door)?;
+            if self.state.halted || self.state.room.as_ref() != Some(&room) {
+                return Ok(Some(text));
+            }
+        }
+        self.restore(snapshot);
+        Ok(None)
+    }
+
+    fn read(&mut self) -> Result<String, Fault> {
+        let computer = &mut self.state.computer;
+        let mut limits = computer.limits();
+        limits.max_steps = Some(computer.steps() + self.budget);
+        computer.set_limits(limits);
+        let (outputs, status) = computer.run_until_blocked()?;
+        let text = decode_ascii(&outputs);
+        self.state.halted = status == Status::Halted;
+        for room in parse_rooms(&text) {
+            self.state.room = Some(room.name.clone());
+            self.rooms.insert(room.name.clone(), room);
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_room, parse_rooms, Driver, Room};
+    use crate::compiler::compile;
+
+    fn say(text: &str) -> String {
+        text.bytes().map(|b| format!("output({});", b)).collect()
+    }
+
+    fn hash(command: &str) -> i64 {
+        command
+            .bytes()
+            .fold(0, |h, b| (h * 31 + b as i64) % 1000003)
+    }
+
+    fn room(name: &str, doors: &[&str]) -> String {
+        let mut text = format!(
+            "\n\n\n== {} ==\nA room aboard the ship.\n\nDoors here lead:\n",
+            name
+        );
+        for door in doors {
+            text += &format!("- {}\n", door);
+        }
+        say(&(text + "\n"))
+    }
+
+    // Four rooms in an L, with the pressure plate north of the checkpoint.
+    // The plate wants the mug and the book; the lava is deadly.
+    fn game() -> Vec<i64> {
+        let items = ["mug", "book", "coin", "lava"];
+        let mut describe = String::new();
+        for (i, (name, doors)) in [
+            ("Hull Breach", &["north"][..]),
+            ("Kitchen", &["south", "east"][..]),
+            ("Lab", &["west", "north"][..]),
+            ("Security Checkpoint", &["south", "north"][..]),
+        ]
+        .iter()
+        .enumerate()
+        {
+            describe += &format!("if (room == {}) {{ {} }}", i, room(name, doors));
+        }
+        let any_item = items
+            .iter()
+            .map(|i| format!("{} == room", i))
+            .collect::<Vec<_>>()
+            .join(" || ");
+        describe += &format!("if ({}) {{ {} }}", any_item, say("Items here:\n"));
+        for item in &items {
+            describe += &format!(
+                "if ({} == room) {{ {} }}",
+                item,
+                say(&format!("- {}\n", item))
+            );
+        }
+        describe += &format!("if ({}) {{ {} }}", any_item, say("\n"));
+
+        let mut commands = String::new();
+        for item in &items {
+            commands += &format!(
+                "if (h == {}) {{ if ({} == room) {{ {} = -1; {} }} }}
+                 if (h == {}) {{ if ({} == -1) {{ {} = room; {} }} }}",
+                hash(&format!("take {}", item)),
+                item,
+                item,
+                say(&format!("\nYou take the {}.\n\n", item)),
+                hash(&format!("drop {}", item)),
+                item,
+                item,
+                say(&format!("\nYou drop the {}.\n\n", item)),
+            );
+        }
+        let moves = [
+            ("north", 0, 1),
+            ("south", 1, 0),
+            ("east", 1, 2),
+            ("west", 2, 1),
+            ("north", 2, 3),
+            ("south", 3, 2),
+        ];
+        for (direction, from, to) in &moves {
+            commands += &format!(
+                "if (h == {} && room == {}) {{ room = {}; moved = 1; }}",
+                hash(direction),
+                from,
+                to
+            );
+        }
+
+        let source = format!(
+            "fn describe(room, mug, book, coin, lava) {{
+                {describe}
+                {prompt}
+            }}
+
+            fn main() {{
+                let room = 0;
+                let mug = 1;
+                let book = 1;
+                let coin = 3;
+                let lava = 2;
+                describe(room, mug, book, coin, lava);
+                while (1) {{
+                    let h = 0;
+                    let c = input();
+                    while (c != 10) {{
+                        h = (h * 31 + c) % 1000003;
+                        c = input();
+                    }}
+                    let moved = 0;
+                    if (h == {north} && room == 3) {{
+                        {plate}
+                        if ((mug == -1) + 4 * (book == -1) + 2 * (coin == -1) == 5) {{
+                            {proceed}
+                            halt();
+                        }}
+                        {ejected}
+                        moved = 1;
+                    }}
+                    {commands}
+                    if (lava == -1) {{
+                        {melt}
+                        halt();
+                    }}
+                    if (moved) {{
+                        describe(room, mug, book, coin, lava);
+                    }} else {{
+                        {prompt}
+                    }}
+                }}
+            }}",
+            describe = describe,
+            prompt = say("Command?\n"),
+            melt = say("\nThe molten lava is way too hot! You melt!\n"),
+            north = hash("north"),
+            plate = room("Pressure-Sensitive Floor", &["south"]),
+            proceed = say("You may proceed. The password is 1234.\n"),
+            ejected = say("Alert! You are ejected back to the checkpoint.\n"),
+            commands = commands,
+        );
+        compile(&source).unwrap()
+    }
+
+    #[test]
+    fn test_parse_room() {
+        let text = "\n\n\n== Hull Breach ==\nYou got in through a hole in the floor here.\n\n\
+                    Doors here lead:\n- north\n- west\n\nItems here:\n- mug\n\nCommand?\n";
+        assert_eq!(
+            parse_room(text),
+            Some(Room {
+                name: "Hull Breach".to_string(),
+                description: "You got in through a hole in the floor here.".to_string(),
+                doors: vec!["north".to_string(), "west".to_string()],
+                items: vec!["mug".to_string()],
+            })
+        );
+        assert_eq!(parse_rooms(&text.repeat(2)).len(), 2);
+        assert_eq!(parse_room("Command?\n"), None);
+    }
+
+    #[test]
+    fn test_script_and_rollback() {
+        let mut driver = Driver::new(&game());
+        assert!(driver.start().unwrap().contains("== Hull Breach =="));
+        let transcript = driver
+            .run_script("# fetch the mug\nnorth\n\ntake mug\neast\n")
+            .unwrap();
+        assert!(transcript.contains("You take the mug."));
+        assert_eq!(driver.room().unwrap().name, "Lab");
+        assert_eq!(driver.room().unwrap().items, vec!["lava"]);
+        assert_eq!(driver.inventory(), ["mug"]);
+        assert_eq!(
+            driver.path_to("Hull Breach").unwrap(),
+            vec!["west", "south"]
+        );
+
+        let snapshot = driver.save();
+        driver.send("take lava").unwrap();
+        assert!(driver.is_halted());
+        driver.restore(snapshot);
+        assert!(!driver.is_halted());
+        assert!(driver
+            .send("north")
+            .unwrap()
+            .contains("== Security Checkpoint =="));
+    }
+
+    #[test]
+    fn test_explore_and_solve() {
+        let mut driver = Driver::new(&game());
+        driver.explore().unwrap();
+        assert_eq!(driver.rooms().len(), 5);
+        assert_eq!(driver.room().unwrap().name, "Hull Breach");
+        let mut inventory = driver.inventory().to_vec();
+        inventory.sort();
+        assert_eq!(inventory, vec!["book", "coin", "mug"]);
+        assert!(driver.dangerous().contains("lava"));
+        assert_eq!(
+            driver.checkpoint(),
+            Some(&("Security Checkpoint".to_string(), "north".to_string()))
+        );
+
+        let text = driver.solve_checkpoint().unwrap().unwrap();
+        assert!(text.contains("The password is 1234."));
+        let mut inventory = driver.inventory().to_vec();
+        inventory.sort();
+        assert_eq!(inventory, vec!["book", "mug"]);
+    }
+}
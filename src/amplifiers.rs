@@ -0,0 +1,364 @@
+// Searches for the phase settings that give the strongest signal out of a
+// network of amplifiers, each running a copy of the same Intcode program.
+//
+// Every amplifier is first given its phase setting, then its input signals.
+// Phase settings are picked from an alphabet without repetition, so the
+// alphabet needs at least as many entries as there are amplifiers.
+
+use crate::engine::{self, IntcodeEngine};
+use itertools::Itertools;
+use std::fmt;
+use std::thread;
+
+// Instructions a single amplifier may take before its program is assumed to
+// be stuck in a loop.
+const DEFAULT_STEP_LIMIT: u64 = 10_000_000;
+
+pub enum Topology {
+    // Each amplifier feeds the next; the last one produces the signal.
+    Chain,
+    // Like `Chain`, but the last amplifier feeds the first until one halts.
+    Ring,
+    // Directed acyclic graph given as (from, to) edges. Amplifiers without
+    // inputs receive the initial signal, the others one signal per incoming
+    // edge in order of the sending amplifier. There must be exactly one
+    // amplifier without outgoing edges; its output is the signal.
+    Graph(Vec<(usize, usize)>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SearchError {
+    NotEnoughPhases,
+    InvalidEdge(usize, usize),
+    Cycle,
+    SinkCount(usize),
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchError::NotEnoughPhases => write!(f, "fewer phase settings than amplifiers"),
+            SearchError::InvalidEdge(from, to) => write!(f, "invalid edge {} -> {}", from, to),
+            SearchError::Cycle => write!(f, "amplifier graph has a cycle"),
+            SearchError::SinkCount(count) => {
+                write!(f, "expected one final amplifier, found {}", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+#[derive(Debug, PartialEq)]
+pub struct Best {
+    pub phases: Vec<i64>,
+    pub signal: i64,
+}
+
+pub struct Search {
+    pub amplifiers: usize,
+    pub phases: Vec<i64>,
+    pub topology: Topology,
+    pub initial_signal: i64,
+    pub threads: usize,
+    pub step_limit: u64,
+}
+
+impl Search {
+    pub fn new(
+        amplifiers: usize,
+        phases: impl IntoIterator<Item = i64>,
+        topology: Topology,
+    ) -> Search {
+        Search {
+            amplifiers,
+            phases: phases.into_iter().collect(),
+            topology,
+            initial_signal: 0,
+            threads: 1,
+            step_limit: DEFAULT_STEP_LIMIT,
+        }
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Search {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn with_step_limit(mut self, step_limit: u64) -> Search {
+        self.step_limit = step_limit;
+        self
+    }
+
+    // Tries every phase assignment and returns the best one. Assignments for
+    // which the program faults, runs out of steps or stops without output are
+    // skipped; `None` means none of them produced a signal.
+    pub fn run(&self, program: &[i64]) -> Result<Option<Best>, SearchError> {
+        if self.phases.len() < self.amplifiers {
+            return Err(SearchError::NotEnoughPhases);
+        }
+        let network = Network::new(self.amplifiers, &self.topology)?;
+        let candidates: Vec<Vec<i64>> = self
+            .phases
+            .iter()
+            .cloned()
+            .permutations(self.amplifiers)
+            .collect();
+
+        let sweep = |thread: usize| {
+            let mut best: Option<(i64, usize)> = None;
+            for (i, phases) in candidates
+                .iter()
+                .enumerate()
+                .skip(thread)
+                .step_by(self.threads)
+            {
+                if let Some(signal) =
+                    network.signal(program, phases, self.initial_signal, self.step_limit)
+                {
+                    if best.is_none_or(|(best_signal, _)| signal > best_signal) {
+                        best = Some((signal, i));
+                    }
+                }
+            }
+            best
+        };
+        let results: Vec<Option<(i64, usize)>> = if self.threads == 1 {
+            vec![sweep(0)]
+        } else {
+            thread::scope(|scope| {
+                let sweep = &sweep;
+                let handles: Vec<_> = (0..self.threads)
+                    .map(|thread| scope.spawn(move || sweep(thread)))
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            })
+        };
+
+        // Ties go to the assignment that comes first in permutation order, so
+        // the answer doesn't depend on the number of threads.
+        let best = results
+            .into_iter()
+            .flatten()
+            .max_by(|(a, i), (b, j)| a.cmp(b).then(j.cmp(i)));
+        Ok(best.map(|(signal, i)| Best {
+            phases: candidates[i].clone(),
+            signal,
+        }))
+    }
+}
+
+enum Network {
+    Chain(usize),
+    Ring(usize),
+    Graph {
+        order: Vec<usize>,
+        inputs: Vec<Vec<usize>>,
+        sink: usize,
+    },
+}
+
+impl Network {
+    fn new(amplifiers: usize, topology: &Topology) -> Result<Network, SearchError> {
+        let edges = match topology {
+            Topology::Chain => return Ok(Network::Chain(amplifiers)),
+            Topology::Ring => return Ok(Network::Ring(amplifiers)),
+            Topology::Graph(edges) => edges,
+        };
+        let mut inputs = vec![Vec::new(); amplifiers];
+        let mut outputs = vec![0; amplifiers];
+        for &(from, to) in edges {
+            if from >= amplifiers || to >= amplifiers || from == to {
+                return Err(SearchError::InvalidEdge(from, to));
+            }
+            inputs[to].push(from);
+            outputs[from] += 1;
+        }
+        for sources in inputs.iter_mut() {
+            sources.sort_unstable();
+        }
+        let sinks: Vec<usize> = (0..amplifiers).filter(|&a| outputs[a] == 0).collect();
+        if sinks.len() != 1 {
+            return Err(SearchError::SinkCount(sinks.len()));
+        }
+
+        let mut remaining: Vec<usize> = inputs.iter().map(|sources| sources.len()).collect();
+        let mut ready: Vec<usize> = (0..amplifiers).filter(|&a| remaining[a] == 0).collect();
+        let mut order = Vec::with_capacity(amplifiers);
+        while let Some(amplifier) = ready.pop() {
+            order.push(amplifier);
+            for &(from, to) in edges {
+                if from == amplifier {
+                    remaining[to] -= 1;
+                    if remaining[to] == 0 {
+                        ready.push(to);
+                    }
+                }
+            }
+        }
+        if order.len() != amplifiers {
+            return Err(SearchError::Cycle);
+        }
+        Ok(Network::Graph {
+            order,
+            inputs,
+            sink: sinks[0],
+        })
+    }
+
+    fn signal(
+        &self,
+        program: &[i64],
+        phases: &[i64],
+        initial_signal: i64,
+        step_limit: u64,
+    ) -> Option<i64> {
+        let boot = || {
+            let mut engine = engine::boot(program);
+            engine.set_step_limit(Some(step_limit));
+            engine
+        };
+        match self {
+            Network::Chain(amplifiers) => {
+                let mut signal = initial_signal;
+                for &phase in &phases[..*amplifiers] {
+                    let mut engine = boot();
+                    engine.provide_inputs(&[phase, signal]);
+                    signal = engine.take_output().ok()??;
+                }
+                Some(signal)
+            }
+            Network::Ring(amplifiers) => {
+                let mut signal = initial_signal;
+                let mut engines = Vec::with_capacity(*amplifiers);
+                for &phase in &phases[..*amplifiers] {
+                    let mut engine = boot();
+                    engine.provide_inputs(&[phase, signal]);
+                    signal = engine.take_output().ok()??;
+                    engines.push(engine);
+                }
+                if engines.is_empty() {
+                    return Some(signal);
+                }
+                for i in 0.. {
+                    let engine = &mut engines[i % amplifiers];
+                    engine.provide_input(signal);
+                    match engine.take_output().ok()? {
+                        Some(next) => signal = next,
+                        None => break,
+                    }
+                }
+                Some(signal)
+            }
+            Network::Graph {
+                order,
+                inputs,
+                sink,
+            } => {
+                let mut signals = vec![0; phases.len()];
+                for &amplifier in order {
+                    let mut input = vec![phases[amplifier]];
+                    if inputs[amplifier].is_empty() {
+                        input.push(initial_signal);
+                    }
+                    input.extend(inputs[amplifier].iter().map(|&from| signals[from]));
+                    let mut engine = boot();
+                    engine.provide_inputs(&input);
+                    signals[amplifier] = engine.take_output().ok()??;
+                }
+                Some(signals[*sink])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Best, Search, SearchError, Topology};
+    use crate::computer::parse_program;
+
+    #[test]
+    fn test_chain_and_ring() {
+        let program = parse_program("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0").unwrap();
+        let best = Search::new(5, 0..5, Topology::Chain).run(&program);
+        assert_eq!(
+            best,
+            Ok(Some(Best {
+                phases: vec![4, 3, 2, 1, 0],
+                signal: 43210
+            }))
+        );
+
+        let program = parse_program(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        )
+        .unwrap();
+        let best = Search::new(5, 5..10, Topology::Ring)
+            .with_threads(4)
+            .run(&program);
+        assert_eq!(
+            best,
+            Ok(Some(Best {
+                phases: vec![9, 8, 7, 6, 5],
+                signal: 139629729
+            }))
+        );
+
+        // echoes its input forever, so the ring never stops on its own
+        let program = parse_program("3,9,3,9,4,9,1105,1,2,0").unwrap();
+        let best = Search::new(2, 0..2, Topology::Ring)
+            .with_step_limit(1000)
+            .run(&program);
+        assert_eq!(best, Ok(None));
+    }
+
+    #[test]
+    fn test_graph() {
+        // outputs 10 * signal + phase
+        let program = parse_program("3,15,3,16,1002,16,10,16,1,15,16,16,4,16,99,0,0").unwrap();
+        let chain = Search::new(3, vec![1, 2, 3, 7], Topology::Chain)
+            .run(&program)
+            .unwrap();
+        let graph = Search::new(3, vec![1, 2, 3, 7], Topology::Graph(vec![(0, 1), (1, 2)]))
+            .with_threads(3)
+            .run(&program)
+            .unwrap();
+        assert_eq!(chain, graph);
+        assert_eq!(graph.unwrap().signal, 732);
+
+        // 0 feeds 1 and 2, which both feed 3; 3 only reads its first input
+        let edges = vec![(0, 1), (0, 2), (1, 3), (2, 3)];
+        let best = Search::new(4, 1..5, Topology::Graph(edges))
+            .run(&program)
+            .unwrap()
+            .unwrap();
+        assert_eq!(best.signal, 432);
+        assert_eq!(best.phases[3], 2);
+    }
+
+    #[test]
+    fn test_invalid_search() {
+        let program = vec![99];
+        let search = |amplifiers, edges| Search::new(amplifiers, 0..5, Topology::Graph(edges));
+        assert_eq!(
+            search(3, vec![(0, 1), (1, 0), (1, 2)]).run(&program),
+            Err(SearchError::Cycle)
+        );
+        assert_eq!(
+            search(3, vec![(0, 1)]).run(&program),
+            Err(SearchError::SinkCount(2))
+        );
+        assert_eq!(
+            search(2, vec![(0, 4)]).run(&program),
+            Err(SearchError::InvalidEdge(0, 4))
+        );
+        assert_eq!(
+            Search::new(6, 0..5, Topology::Chain).run(&program),
+            Err(SearchError::NotEnoughPhases)
+        );
+        assert_eq!(
+            Search::new(2, 0..5, Topology::Chain).run(&program),
+            Ok(None)
+        );
+    }
+}
@@ -0,0 +1,264 @@
+// An arcade cabinet for Intcode games. The program draws by outputting
+// `(x, y, tile)` triples, and reports the score as `(-1, 0, score)`. Whenever
+// it asks for input, the frame is complete and the joystick is read:
+// -1 tilts left, 0 stays neutral and 1 tilts right.
+
+use crate::computer::{Computer, Fault, Status};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tile {
+    Empty = 0,
+    Wall = 1,
+    Block = 2,
+    Paddle = 3,
+    Ball = 4,
+}
+
+impl Tile {
+    fn new(id: i64) -> Tile {
+        match id {
+            1 => Tile::Wall,
+            2 => Tile::Block,
+            3 => Tile::Paddle,
+            4 => Tile::Ball,
+            _ => Tile::Empty,
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Tile::Empty => ' ',
+            Tile::Wall => '#',
+            Tile::Block => '=',
+            Tile::Paddle => '_',
+            Tile::Ball => 'o',
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Screen {
+    tiles: HashMap<(i64, i64), Tile>,
+    score: i64,
+}
+
+impl Screen {
+    pub fn new() -> Screen {
+        Screen::default()
+    }
+
+    // Applies one output triple. Unknown tile ids draw as empty.
+    pub fn update(&mut self, x: i64, y: i64, value: i64) {
+        if (x, y) == (-1, 0) {
+            self.score = value;
+            return;
+        }
+        self.tiles.insert((x, y), Tile::new(value));
+    }
+
+    pub fn score(&self) -> i64 {
+        self.score
+    }
+
+    pub fn tile(&self, x: i64, y: i64) -> Tile {
+        *self.tiles.get(&(x, y)).unwrap_or(&Tile::Empty)
+    }
+
+    pub fn count(&self, tile: Tile) -> usize {
+        self.tiles.values().filter(|&&t| t == tile).count()
+    }
+
+    pub fn find(&self, tile: Tile) -> Option<(i64, i64)> {
+        self.tiles
+            .iter()
+            .find(|(_, &t)| t == tile)
+            .map(|(&position, _)| position)
+    }
+
+    pub fn render(&self) -> String {
+        let mut result = format!("Score: {}\n", self.score);
+        if self.tiles.is_empty() {
+            return result;
+        }
+        let min_x = self.tiles.keys().map(|p| p.0).min().unwrap();
+        let max_x = self.tiles.keys().map(|p| p.0).max().unwrap();
+        let min_y = self.tiles.keys().map(|p| p.1).min().unwrap();
+        let max_y = self.tiles.keys().map(|p| p.1).max().unwrap();
+        for y in min_y..=max_y {
+            let line: String = (min_x..=max_x).map(|x| self.tile(x, y).symbol()).collect();
+            result.push_str(line.trim_end());
+            result.push('\n');
+        }
+        result
+    }
+}
+
+pub trait Joystick {
+    fn tilt(&mut self, screen: &Screen) -> i64;
+}
+
+impl<F: FnMut(&Screen) -> i64> Joystick for F {
+    fn tilt(&mut self, screen: &Screen) -> i64 {
+        self(screen)
+    }
+}
+
+// Keeps the paddle under the ball.
+pub struct AutoPlayer;
+
+impl Joystick for AutoPlayer {
+    fn tilt(&mut self, screen: &Screen) -> i64 {
+        match (screen.find(Tile::Ball), screen.find(Tile::Paddle)) {
+            (Some(ball), Some(paddle)) => (ball.0 - paddle.0).signum(),
+            _ => 0,
+        }
+    }
+}
+
+// Games run in demo mode unless address 0 is set to 2. An empty program is
+// left empty, to fault when it runs.
+pub fn free_play(program: &[i64]) -> Vec<i64> {
+    let mut program = program.to_vec();
+    if let Some(mode) = program.get_mut(0) {
+        *mode = 2;
+    }
+    program
+}
+
+pub struct Arcade<J: Joystick> {
+    computer: Computer,
+    screen: Screen,
+    joystick: J,
+    pending: Vec<i64>,
+}
+
+impl<J: Joystick> Arcade<J> {
+    pub fn new(program: &[i64], joystick: J) -> Arcade<J> {
+        Arcade {
+            computer: Computer::new(program),
+            screen: Screen::new(),
+            joystick,
+            pending: Vec::with_capacity(3),
+        }
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    // Runs until the next frame is complete, then moves the joystick.
+    // Returns false once the game is over.
+    pub fn step(&mut self) -> Result<bool, Fault> {
+        loop {
+            match self.computer.resume()? {
+                Status::Output(value) => {
+                    self.pending.push(value);
+                    if self.pending.len() == 3 {
+                        self.screen
+                            .update(self.pending[0], self.pending[1], self.pending[2]);
+                        self.pending.clear();
+                    }
+                }
+                Status::NeedsInput => {
+                    let tilt = self.joystick.tilt(&self.screen);
+                    self.computer.push_input(tilt);
+                    return Ok(true);
+                }
+                Status::Halted => return Ok(false),
+            }
+        }
+    }
+
+    // Plays until the game is over and returns the final score.
+    pub fn run(&mut self) -> Result<i64, Fault> {
+        while self.step()? {}
+        Ok(self.screen.score())
+    }
+
+    // Like `run`, drawing every frame to `out` with ANSI escapes.
+    pub fn play<W: Write>(&mut self, out: &mut W) -> io::Result<i64> {
+        loop {
+            let running = self.step().map_err(io::Error::other)?;
+            write!(out, "\x1b[2J\x1b[H{}", self.screen.render())?;
+            out.flush()?;
+            if !running {
+                return Ok(self.screen.score());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arcade, AutoPlayer, Screen, Tile};
+    use crate::compiler::compile;
+
+    const GAME: &str = "
+        fn draw(x, y, tile) {
+            output(x);
+            output(y);
+            output(tile);
+        }
+
+        fn main() {
+            draw(0, 0, 1);
+            draw(1, 0, 2);
+            draw(3, 0, 1);
+            draw(2, 1, 4);
+            draw(0, 2, 3);
+            let paddle = 0;
+            let moves = 0;
+            while (paddle != 2) {
+                paddle = paddle + input();
+                moves = moves + 1;
+                draw(paddle - 1, 2, 0);
+                draw(paddle, 2, 3);
+            }
+            draw(1, 0, 0);
+            draw(-1, 0, 100 - moves);
+        }";
+
+    #[test]
+    fn test_screen() {
+        let mut screen = Screen::new();
+        screen.update(0, 0, 1);
+        screen.update(2, 1, 4);
+        screen.update(1, 1, 3);
+        screen.update(-1, 0, 1234);
+        assert_eq!(screen.score(), 1234);
+        assert_eq!(screen.find(Tile::Ball), Some((2, 1)));
+        assert_eq!(screen.count(Tile::Wall), 1);
+        assert_eq!(screen.render(), "Score: 1234\n#\n _o\n");
+    }
+
+    #[test]
+    fn test_free_play() {
+        assert_eq!(super::free_play(&[1, 0, 0, 0, 99]), vec![2, 0, 0, 0, 99]);
+        assert_eq!(super::free_play(&[]), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn test_auto_player() {
+        let program = compile(GAME).unwrap();
+        let mut arcade = Arcade::new(&program, AutoPlayer);
+        assert_eq!(arcade.run(), Ok(98));
+        assert_eq!(arcade.screen().count(Tile::Block), 0);
+        assert_eq!(arcade.screen().find(Tile::Paddle), Some((2, 2)));
+
+        let mut frames = Vec::new();
+        let mut arcade = Arcade::new(&program, AutoPlayer);
+        assert_eq!(arcade.play(&mut frames).unwrap(), 98);
+        let frames = String::from_utf8(frames).unwrap();
+        assert!(frames.ends_with("\x1b[2J\x1b[HScore: 98\n#  #\n  o\n  _\n"));
+    }
+
+    #[test]
+    fn test_scripted_joystick() {
+        let program = compile(GAME).unwrap();
+        let mut moves = vec![1, 0, 0, 1].into_iter();
+        let mut arcade = Arcade::new(&program, |_: &Screen| moves.next().unwrap());
+        assert_eq!(arcade.run(), Ok(96));
+    }
+}
@@ -0,0 +1,354 @@
+// An async façade over `Computer` with a small single-threaded executor, so
+// Intcode machines can be embedded in event driven code without a runtime.
+//
+// A `Machine` is a future that runs a program. It reads its input from a
+// channel `Receiver` and writes its output to a channel `Sender`; when the
+// input channel is empty the machine suspends until a value is sent. Since a
+// `Receiver` is also a `Stream`, the outputs of one machine can be awaited
+// one at a time, or wired straight into the input of another machine.
+
+use crate::computer::{Computer, Fault, NoObserver, Observer, Status};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+pub trait Stream {
+    type Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>>;
+}
+
+struct Channel<T> {
+    values: VecDeque<T>,
+    waker: Option<Waker>,
+    senders: usize,
+}
+
+pub struct Sender<T> {
+    channel: Rc<RefCell<Channel<T>>>,
+}
+
+pub struct Receiver<T> {
+    channel: Rc<RefCell<Channel<T>>>,
+}
+
+// An unbounded channel. The receiver sees the end of the stream once every
+// sender has been dropped.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let channel = Rc::new(RefCell::new(Channel {
+        values: VecDeque::new(),
+        waker: None,
+        senders: 1,
+    }));
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver { channel },
+    )
+}
+
+impl<T> Sender<T> {
+    pub fn send(&self, value: T) {
+        let mut channel = self.channel.borrow_mut();
+        channel.values.push_back(value);
+        if let Some(waker) = channel.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        self.channel.borrow_mut().senders += 1;
+        Sender {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut channel = self.channel.borrow_mut();
+        channel.senders -= 1;
+        if channel.senders == 0 {
+            if let Some(waker) = channel.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&self) -> Option<T> {
+        self.channel.borrow_mut().values.pop_front()
+    }
+
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        let mut channel = self.channel.borrow_mut();
+        if let Some(value) = channel.values.pop_front() {
+            return Poll::Ready(Some(value));
+        }
+        if channel.senders == 0 {
+            return Poll::Ready(None);
+        }
+        channel.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<'a, T> Future for Recv<'a, T> {
+    type Output = Option<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        Pin::new(&mut *self.receiver).poll_next(cx)
+    }
+}
+
+// Runs a program to completion, suspending whenever it needs input that has
+// not arrived yet. Dropping the machine's output `Sender` when it halts ends
+// the output stream. If the input channel is closed while the program still
+// wants input, the machine fails with `Fault::MissingInput`.
+pub struct Machine<O: Observer = NoObserver> {
+    computer: Computer<O>,
+    input: Receiver<i64>,
+    output: Option<Sender<i64>>,
+}
+
+impl Machine {
+    pub fn new(program: &[i64], input: Receiver<i64>, output: Sender<i64>) -> Machine {
+        Machine::with_computer(Computer::new(program), input, output)
+    }
+}
+
+impl<O: Observer> Machine<O> {
+    pub fn with_computer(
+        computer: Computer<O>,
+        input: Receiver<i64>,
+        output: Sender<i64>,
+    ) -> Machine<O> {
+        Machine {
+            computer,
+            input,
+            output: Some(output),
+        }
+    }
+}
+
+impl<O: Observer + Unpin> Future for Machine<O> {
+    type Output = Result<(), Fault>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Fault>> {
+        let machine = &mut *self;
+        loop {
+            match machine.computer.resume() {
+                Ok(Status::Output(value)) => {
+                    if let Some(output) = &machine.output {
+                        output.send(value);
+                    }
+                }
+                Ok(Status::NeedsInput) => match Pin::new(&mut machine.input).poll_next(cx) {
+                    Poll::Ready(Some(value)) => machine.computer.push_input(value),
+                    Poll::Ready(None) => {
+                        machine.output = None;
+                        let address = machine.computer.counter();
+                        return Poll::Ready(Err(Fault::MissingInput { address }));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                Ok(Status::Halted) => {
+                    machine.output = None;
+                    return Poll::Ready(Ok(()));
+                }
+                Err(fault) => {
+                    machine.output = None;
+                    return Poll::Ready(Err(fault));
+                }
+            }
+        }
+    }
+}
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+struct TaskWaker {
+    id: usize,
+    ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.ready.lock().unwrap().push_back(self.id);
+    }
+}
+
+// A single-threaded executor. Tasks only run inside `run`, which returns once
+// no task can make progress.
+#[derive(Default)]
+pub struct Executor {
+    tasks: Vec<Option<Task>>,
+    ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl Executor {
+    pub fn new() -> Executor {
+        Executor::default()
+    }
+
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + 'static) {
+        self.ready.lock().unwrap().push_back(self.tasks.len());
+        self.tasks.push(Some(Box::pin(future)));
+    }
+
+    // Polls tasks until every one has finished or is waiting on something
+    // that no other task will provide. Returns the number of unfinished tasks.
+    pub fn run(&mut self) -> usize {
+        loop {
+            let id = match self.ready.lock().unwrap().pop_front() {
+                Some(id) => id,
+                None => break,
+            };
+            let task = match &mut self.tasks[id] {
+                Some(task) => task,
+                None => continue,
+            };
+            let waker = Waker::from(Arc::new(TaskWaker {
+                id,
+                ready: self.ready.clone(),
+            }));
+            if task
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_ready()
+            {
+                self.tasks[id] = None;
+            }
+        }
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// Runs a single future on the current thread until it completes.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{channel, Executor, Machine};
+    use crate::computer::Fault;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_stream_of_outputs() {
+        let program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        let (input, machine_input) = channel();
+        let (machine_output, mut output) = channel();
+        let mut executor = Executor::new();
+        executor.spawn(async move {
+            Machine::new(&program, machine_input, machine_output)
+                .await
+                .unwrap();
+        });
+        let results = Rc::new(RefCell::new(Vec::new()));
+        let collected = results.clone();
+        executor.spawn(async move {
+            while let Some(value) = output.recv().await {
+                collected.borrow_mut().push(value);
+            }
+        });
+
+        assert_eq!(executor.run(), 2);
+        input.send(8);
+        assert_eq!(executor.run(), 0);
+        assert_eq!(*results.borrow(), vec![1]);
+    }
+
+    #[test]
+    fn test_feedback_loop() {
+        let program = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let phases = [9, 8, 7, 6, 5];
+        let mut senders = Vec::new();
+        let mut receivers = Vec::new();
+        for &phase in &phases {
+            let (sender, receiver) = channel();
+            sender.send(phase);
+            senders.push(sender);
+            receivers.push(receiver);
+        }
+        senders[0].send(0);
+
+        let mut executor = Executor::new();
+        let last_output = Rc::new(RefCell::new(0));
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            let output = match senders.get(i + 1) {
+                Some(sender) => sender.clone(),
+                None => {
+                    // the last amplifier feeds the first, remembering each signal
+                    let (tap, mut tapped) = channel();
+                    let first = senders[0].clone();
+                    let last_output = last_output.clone();
+                    executor.spawn(async move {
+                        while let Some(value) = tapped.recv().await {
+                            *last_output.borrow_mut() = value;
+                            first.send(value);
+                        }
+                    });
+                    tap
+                }
+            };
+            let machine = Machine::new(&program, receiver, output);
+            executor.spawn(async move { machine.await.unwrap() });
+        }
+        drop(senders);
+
+        assert_eq!(executor.run(), 0);
+        assert_eq!(*last_output.borrow(), 139629729);
+    }
+
+    #[test]
+    fn test_closed_input() {
+        let (input, machine_input) = channel();
+        let (machine_output, _output) = channel();
+        drop(input);
+        let result = super::block_on(Machine::new(&[3, 0, 99], machine_input, machine_output));
+        assert_eq!(result, Err(Fault::MissingInput { address: 0 }));
+    }
+}
@@ -0,0 +1,274 @@
+// Runs an Intcode program file, connecting it to standard input and output.
+//
+//     intcode [--ascii] [--input VALUES]... [--trace] [--max-steps N] <program file>
+//
+// In numeric mode every output is printed on its own line, and input is read
+// as integers separated by commas or whitespace. In ASCII mode output is
+// printed as text and each line read is queued as characters, newline
+// included; outputs that are not ASCII characters are printed as numbers on
+// their own line, starting a new one if the text did not end with a newline,
+// which is how the puzzles report their answers. Values
+// given with `--input` are queued before anything is read, as numbers or as a
+// line of text depending on the mode.
+//
+// `--trace` reports every fetch, read, write, input and output on standard
+// error, and `--max-steps` stops the program after that many instructions.
+//
+// The exit code says how the run ended.
+
+use advent_2019::computer::{parse_program, Computer, Fault, Limits, Observer, Status};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+const HALTED: i32 = 0;
+const FAULTED: i32 = 1;
+const BUDGET_EXHAUSTED: i32 = 2;
+// Bad arguments, an unreadable program or input that is not a number.
+const NOT_RUN: i32 = 3;
+// Reading standard input or writing standard output failed.
+const IO_ERROR: i32 = 4;
+
+const USAGE: &str =
+    "usage: intcode [--ascii] [--input VALUES]... [--trace] [--max-steps N] <program file>";
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    path: String,
+    ascii: bool,
+    inputs: Vec<String>,
+    trace: bool,
+    max_steps: Option<u64>,
+}
+
+fn parse_arguments<I: Iterator<Item = String>>(mut arguments: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut path = None;
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--ascii" => options.ascii = true,
+            "--trace" => options.trace = true,
+            "--input" => options
+                .inputs
+                .push(arguments.next().ok_or("--input needs a value")?),
+            "--max-steps" => {
+                let steps = arguments.next().ok_or("--max-steps needs a value")?;
+                let steps = steps
+                    .parse()
+                    .map_err(|_| format!("invalid step count '{}'", steps))?;
+                options.max_steps = Some(steps);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{}'", flag)),
+            _ if path.is_some() => return Err("more than one program file".to_string()),
+            _ => path = Some(argument),
+        }
+    }
+    options.path = path.ok_or("no program file")?;
+    Ok(options)
+}
+
+fn parse_numbers(text: &str) -> Result<Vec<i64>, String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            token
+                .parse()
+                .map_err(|_| format!("invalid input '{}'", token))
+        })
+        .collect()
+}
+
+// Why the program did not run to the end, and the exit code that says so.
+struct Failure {
+    code: i32,
+    message: String,
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure {
+            code: NOT_RUN,
+            message,
+        }
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(error: io::Error) -> Failure {
+        Failure {
+            code: IO_ERROR,
+            message: error.to_string(),
+        }
+    }
+}
+
+// Reports everything the machine does on standard error, when enabled.
+struct Tracer {
+    enabled: bool,
+}
+
+impl Tracer {
+    fn report(&self, event: std::fmt::Arguments) {
+        if self.enabled {
+            let _ = writeln!(io::stderr(), "{}", event);
+        }
+    }
+}
+
+impl Observer for Tracer {
+    fn on_fetch(&mut self, address: i64, word: i64) {
+        self.report(format_args!("fetch {}: {}", address, word));
+    }
+
+    fn on_read(&mut self, address: i64, value: i64) {
+        self.report(format_args!("read {}: {}", address, value));
+    }
+
+    fn on_write(&mut self, address: i64, value: i64) {
+        self.report(format_args!("write {}: {}", address, value));
+    }
+
+    fn on_input(&mut self, value: i64) {
+        self.report(format_args!("input {}", value));
+    }
+
+    fn on_output(&mut self, value: i64) {
+        self.report(format_args!("output {}", value));
+    }
+}
+
+// Runs the program to the end and returns the exit code.
+fn run(options: &Options) -> Result<i32, Failure> {
+    let text = fs::read_to_string(&options.path)
+        .map_err(|error| format!("{}: {}", options.path, error))?;
+    let program = parse_program(&text).map_err(|error| format!("{}: {}", options.path, error))?;
+    let limits = Limits {
+        max_steps: options.max_steps,
+        ..Limits::default()
+    };
+    let tracer = Tracer {
+        enabled: options.trace,
+    };
+    let mut computer = Computer::with_observer(&program, tracer).with_limits(limits);
+    for input in &options.inputs {
+        if options.ascii {
+            computer.push_ascii(&format!("{}\n", input));
+        } else {
+            for value in parse_numbers(input)? {
+                computer.push_input(value);
+            }
+        }
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut line_started = false;
+    let result = loop {
+        let status = match computer.resume() {
+            Ok(status) => status,
+            Err(fault) => break Err(fault),
+        };
+        match status {
+            Status::Output(value) if options.ascii && (0..128).contains(&value) => {
+                stdout.write_all(&[value as u8])?;
+                line_started = value != i64::from(b'\n');
+            }
+            Status::Output(value) => {
+                if line_started {
+                    writeln!(stdout)?;
+                    line_started = false;
+                }
+                writeln!(stdout, "{}", value)?;
+            }
+            Status::Halted => break Ok(()),
+            Status::NeedsInput => {
+                stdout.flush()?;
+                // Numeric lines may hold no values at all, so keep reading
+                // until one does.
+                let mut queued = false;
+                while !queued {
+                    let line = match lines.next() {
+                        Some(line) => line?,
+                        None => break,
+                    };
+                    if options.ascii {
+                        computer.push_ascii(&format!("{}\n", line));
+                        queued = true;
+                    } else {
+                        for value in parse_numbers(&line)? {
+                            computer.push_input(value);
+                            queued = true;
+                        }
+                    }
+                }
+                if !queued {
+                    break Err(Fault::MissingInput {
+                        address: computer.counter(),
+                    });
+                }
+            }
+        }
+    };
+    stdout.flush()?;
+    match result {
+        Ok(()) => Ok(HALTED),
+        Err(fault) => {
+            eprintln!("intcode: {}", fault);
+            match fault {
+                Fault::StepLimit(_) => Ok(BUDGET_EXHAUSTED),
+                _ => Ok(FAULTED),
+            }
+        }
+    }
+}
+
+fn main() {
+    let code = parse_arguments(env::args().skip(1))
+        .map_err(|error| Failure::from(format!("{}\n{}", error, USAGE)))
+        .and_then(|options| run(&options))
+        .unwrap_or_else(|failure| {
+            eprintln!("intcode: {}", failure.message);
+            failure.code
+        });
+    process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+
+    fn parse(arguments: &str) -> Result<Options, String> {
+        super::parse_arguments(arguments.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_arguments() {
+        assert_eq!(
+            parse("--input 1,2 --trace day9.txt --max-steps 100 --input 3"),
+            Ok(Options {
+                path: "day9.txt".to_string(),
+                ascii: false,
+                inputs: vec!["1,2".to_string(), "3".to_string()],
+                trace: true,
+                max_steps: Some(100),
+            })
+        );
+        assert!(parse("--ascii day25.txt").unwrap().ascii);
+        assert_eq!(parse("--trace"), Err("no program file".to_string()));
+        assert_eq!(parse("a b"), Err("more than one program file".to_string()));
+        assert_eq!(parse("a --input"), Err("--input needs a value".to_string()));
+        assert_eq!(
+            parse("a --max-steps lots"),
+            Err("invalid step count 'lots'".to_string())
+        );
+        assert_eq!(
+            parse("a --fast"),
+            Err("unknown option '--fast'".to_string())
+        );
+        assert_eq!(super::parse_numbers(" 1, -2\n3 "), Ok(vec![1, -2, 3]));
+        assert!(super::parse_numbers("1,x").is_err());
+    }
+}
@@ -0,0 +1,22 @@
+// Watches an Intcode program run in the terminal:
+//
+//     monitor <program file> [input,input,...]
+
+use advent_2019::computer::parse_program;
+use std::env;
+use std::error::Error;
+use std::fs;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut arguments = env::args().skip(1);
+    let path = arguments
+        .next()
+        .ok_or("usage: monitor <program file> [input,input,...]")?;
+    let program = parse_program(&fs::read_to_string(&path)?)?;
+    let inputs = match arguments.next() {
+        Some(inputs) => parse_program(&inputs)?,
+        None => Vec::new(),
+    };
+    advent_2019::monitor::watch(&program, &inputs)?;
+    Ok(())
+}
@@ -0,0 +1,1112 @@
+// Compiles a small imperative language to Intcode.
+//
+//     fn square(x) { return x * x; }
+//     fn main() {
+//         let n = input();
+//         while (n > 0) {
+//             output(square(n));
+//             n = n - 1;
+//         }
+//     }
+//
+// Values are i64. Every variable lives in a stack frame addressed through the
+// relative base, so recursion works. A frame looks like
+//
+//     rb + 0       return address
+//     rb + 1       return value
+//     rb + 2..     parameters, then locals, then temporaries
+//
+// A caller with frame size F builds the callee frame at rb + F, moves the
+// relative base up by F, jumps, and moves it back down by F on return.
+// `/` and `%` have no opcode, so they are compiled to calls into a prelude
+// written in the language itself. Division by zero halts the machine.
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+fn error<T>(line: usize, message: impl Into<String>) -> Result<T> {
+    Err(CompileError {
+        line,
+        message: message.into(),
+    })
+}
+
+const PRELUDE: &str = "
+fn __udiv(a, b) {
+    let q = 0;
+    while (a >= b) {
+        let d = b;
+        let m = 1;
+        while (a - d >= d) {
+            d = d + d;
+            m = m + m;
+        }
+        a = a - d;
+        q = q + m;
+    }
+    return q;
+}
+
+fn __div(a, b) {
+    if (b == 0) {
+        halt();
+    }
+    let negative = 0;
+    if (a < 0) {
+        a = -a;
+        negative = !negative;
+    }
+    if (b < 0) {
+        b = -b;
+        negative = !negative;
+    }
+    let q = __udiv(a, b);
+    if (negative) {
+        return -q;
+    }
+    return q;
+}
+
+fn __mod(a, b) {
+    return a - __div(a, b) * b;
+}
+";
+
+pub fn compile(source: &str) -> Result<Vec<i64>> {
+    let functions = Parser::new(tokenize(source)?).program()?;
+    let prelude = Parser::new(tokenize(PRELUDE)?).program()?;
+    Generator::new(functions, prelude).generate()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Symbol(&'static str),
+    End,
+}
+
+const SYMBOLS: [&str; 21] = [
+    "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "=", "!", "(", ")", "{",
+    "}", ",", ";",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split("//").next().unwrap();
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            let c = rest.chars().next().unwrap();
+            if c.is_ascii_digit() {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                match rest[..end].parse() {
+                    Ok(n) => tokens.push((Token::Number(n), line_number)),
+                    Err(_) => {
+                        return error(line_number, format!("number too large: {}", &rest[..end]))
+                    }
+                }
+                rest = &rest[end..];
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                tokens.push((Token::Identifier(rest[..end].to_string()), line_number));
+                rest = &rest[end..];
+            } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                tokens.push((Token::Symbol(symbol), line_number));
+                rest = &rest[symbol.len()..];
+            } else {
+                return error(line_number, format!("unexpected character '{}'", c));
+            }
+            rest = rest.trim_start();
+        }
+    }
+    let last_line = source.lines().count().max(1);
+    tokens.push((Token::End, last_line));
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Variable(String, usize),
+    Unary(UnaryOp, Box<Expr>, usize),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>, usize),
+    Call(String, Vec<Expr>, usize),
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Let(String, Expr, usize),
+    Assign(String, Expr, usize),
+    If(Expr, Vec<Statement>, Vec<Statement>),
+    While(Expr, Vec<Statement>),
+    Return(Option<Expr>),
+    Expression(Expr),
+}
+
+#[derive(Debug, Clone)]
+struct Function {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Statement>,
+    line: usize,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(tokens: Vec<(Token, usize)>) -> Parser {
+        Parser {
+            tokens,
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.position].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.position].0.clone();
+        if token != Token::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Token::Symbol(s) if *s == symbol)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Identifier(s) if s == keyword)
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if self.is_symbol(symbol) {
+            self.advance();
+            return Ok(());
+        }
+        error(
+            self.line(),
+            format!("expected '{}', found {:?}", symbol, self.peek()),
+        )
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.is_keyword(keyword) {
+            self.advance();
+            return Ok(());
+        }
+        error(
+            self.line(),
+            format!("expected '{}', found {:?}", keyword, self.peek()),
+        )
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        match self.peek().clone() {
+            Token::Identifier(name) if !is_keyword(&name) => {
+                self.advance();
+                Ok(name)
+            }
+            token => error(
+                self.line(),
+                format!("expected identifier, found {:?}", token),
+            ),
+        }
+    }
+
+    fn program(&mut self) -> Result<Vec<Function>> {
+        let mut functions = Vec::new();
+        while *self.peek() != Token::End {
+            functions.push(self.function()?);
+        }
+        Ok(functions)
+    }
+
+    fn function(&mut self) -> Result<Function> {
+        let line = self.line();
+        self.expect_keyword("fn")?;
+        let name = self.identifier()?;
+        self.expect_symbol("(")?;
+        let mut params = Vec::new();
+        while !self.is_symbol(")") {
+            params.push(self.identifier()?);
+            if !self.is_symbol(")") {
+                self.expect_symbol(",")?;
+            }
+        }
+        self.expect_symbol(")")?;
+        let body = self.block()?;
+        Ok(Function {
+            name,
+            params,
+            body,
+            line,
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>> {
+        self.expect_symbol("{")?;
+        let mut statements = Vec::new();
+        while !self.is_symbol("}") {
+            if *self.peek() == Token::End {
+                return error(self.line(), "unterminated block");
+            }
+            statements.push(self.statement()?);
+        }
+        self.expect_symbol("}")?;
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        let line = self.line();
+        if self.is_keyword("let") {
+            self.advance();
+            let name = self.identifier()?;
+            self.expect_symbol("=")?;
+            let value = self.expression()?;
+            self.expect_symbol(";")?;
+            return Ok(Statement::Let(name, value, line));
+        }
+        if self.is_keyword("if") {
+            self.advance();
+            let condition = self.condition()?;
+            let then_branch = self.block()?;
+            let mut else_branch = Vec::new();
+            if self.is_keyword("else") {
+                self.advance();
+                if self.is_keyword("if") {
+                    else_branch.push(self.statement()?);
+                } else {
+                    else_branch = self.block()?;
+                }
+            }
+            return Ok(Statement::If(condition, then_branch, else_branch));
+        }
+        if self.is_keyword("while") {
+            self.advance();
+            let condition = self.condition()?;
+            let body = self.block()?;
+            return Ok(Statement::While(condition, body));
+        }
+        if self.is_keyword("return") {
+            self.advance();
+            let value = if self.is_symbol(";") {
+                None
+            } else {
+                Some(self.expression()?)
+            };
+            self.expect_symbol(";")?;
+            return Ok(Statement::Return(value));
+        }
+        if let Token::Identifier(name) = self.peek().clone() {
+            if !is_keyword(&name) && self.tokens[self.position + 1].0 == Token::Symbol("=") {
+                self.advance();
+                self.advance();
+                let value = self.expression()?;
+                self.expect_symbol(";")?;
+                return Ok(Statement::Assign(name, value, line));
+            }
+        }
+        let expression = self.expression()?;
+        self.expect_symbol(";")?;
+        Ok(Statement::Expression(expression))
+    }
+
+    fn condition(&mut self) -> Result<Expr> {
+        self.expect_symbol("(")?;
+        let condition = self.expression()?;
+        self.expect_symbol(")")?;
+        Ok(condition)
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        const LEVELS: [&[(&str, BinaryOp)]; 5] = [
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[("==", BinaryOp::Equal), ("!=", BinaryOp::NotEqual)],
+            &[
+                ("<", BinaryOp::Less),
+                ("<=", BinaryOp::LessEqual),
+                (">", BinaryOp::Greater),
+                (">=", BinaryOp::GreaterEqual),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Subtract)],
+        ];
+        if level == LEVELS.len() {
+            return self.term();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(&(_, op)) = LEVELS[level].iter().find(|(s, _)| self.is_symbol(s)) {
+            let line = self.line();
+            self.advance();
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right), line);
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        const OPS: [(&str, BinaryOp); 3] = [
+            ("*", BinaryOp::Multiply),
+            ("/", BinaryOp::Divide),
+            ("%", BinaryOp::Remainder),
+        ];
+        let mut left = self.unary()?;
+        while let Some(&(_, op)) = OPS.iter().find(|(s, _)| self.is_symbol(s)) {
+            let line = self.line();
+            self.advance();
+            let right = self.unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right), line);
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        let line = self.line();
+        if self.is_symbol("-") {
+            self.advance();
+            return Ok(Expr::Unary(UnaryOp::Negate, Box::new(self.unary()?), line));
+        }
+        if self.is_symbol("!") {
+            self.advance();
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(self.unary()?), line));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let line = self.line();
+        match self.peek().clone() {
+            Token::Number(n) => {
+                self.advance();
+                Ok(Expr::Number(n))
+            }
+            Token::Symbol("(") => {
+                self.advance();
+                let expression = self.expression()?;
+                self.expect_symbol(")")?;
+                Ok(expression)
+            }
+            Token::Identifier(_) => {
+                let name = self.identifier()?;
+                if !self.is_symbol("(") {
+                    return Ok(Expr::Variable(name, line));
+                }
+                self.advance();
+                let mut args = Vec::new();
+                while !self.is_symbol(")") {
+                    args.push(self.expression()?);
+                    if !self.is_symbol(")") {
+                        self.expect_symbol(",")?;
+                    }
+                }
+                self.expect_symbol(")")?;
+                Ok(Expr::Call(name, args, line))
+            }
+            token => error(line, format!("expected expression, found {:?}", token)),
+        }
+    }
+}
+
+fn is_keyword(name: &str) -> bool {
+    ["fn", "let", "if", "else", "while", "return"].contains(&name)
+}
+
+// Where an instruction parameter comes from. `Local` is an offset from the
+// relative base; `Frame` is an offset from the end of the current frame,
+// which is only known once the whole function has been generated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Immediate(i64),
+    Local(i64),
+    Frame(i64),
+    FrameSize(i64),
+    Label(usize),
+    StackBase,
+}
+
+impl Operand {
+    fn mode(self) -> i64 {
+        match self {
+            Operand::Local(_) | Operand::Frame(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+enum Fixup {
+    Label(usize),
+    Frame(i64),
+    FrameSize(i64),
+    StackBase,
+}
+
+const ADD: i64 = 1;
+const MULTIPLY: i64 = 2;
+const INPUT: i64 = 3;
+const OUTPUT: i64 = 4;
+const JUMP_IF_TRUE: i64 = 5;
+const JUMP_IF_FALSE: i64 = 6;
+const LESS_THAN: i64 = 7;
+const EQUALS: i64 = 8;
+const ADJUST_RELATIVE_BASE: i64 = 9;
+const STOP: i64 = 99;
+
+const RETURN_ADDRESS: Operand = Operand::Local(0);
+const RETURN_VALUE: Operand = Operand::Local(1);
+
+struct Generator {
+    code: Vec<i64>,
+    fixups: Vec<(usize, Fixup)>,
+    frame_fixups: Vec<(usize, Fixup)>,
+    labels: Vec<Option<i64>>,
+    functions: HashMap<String, (usize, usize)>,
+    pending: Vec<Function>,
+    prelude: HashMap<String, Function>,
+    variables: HashMap<String, i64>,
+    next_slot: i64,
+    locals_end: i64,
+    frame_size: i64,
+}
+
+impl Generator {
+    fn new(functions: Vec<Function>, prelude: Vec<Function>) -> Generator {
+        Generator {
+            code: Vec::new(),
+            fixups: Vec::new(),
+            frame_fixups: Vec::new(),
+            labels: Vec::new(),
+            functions: HashMap::new(),
+            pending: functions,
+            prelude: prelude.into_iter().map(|f| (f.name.clone(), f)).collect(),
+            variables: HashMap::new(),
+            next_slot: 0,
+            locals_end: 0,
+            frame_size: 0,
+        }
+    }
+
+    fn generate(mut self) -> Result<Vec<i64>> {
+        for function in &self.pending {
+            let label = self.labels.len();
+            self.labels.push(None);
+            if self
+                .functions
+                .insert(function.name.clone(), (label, function.params.len()))
+                .is_some()
+            {
+                return error(
+                    function.line,
+                    format!("duplicate function '{}'", function.name),
+                );
+            }
+        }
+        let main = match self.functions.get("main") {
+            Some(&(label, 0)) => label,
+            Some(_) => return error(1, "main must not take parameters"),
+            None => return error(1, "missing function 'main'"),
+        };
+
+        let halt = self.new_label();
+        self.emit(ADJUST_RELATIVE_BASE, &[Operand::StackBase]);
+        self.emit(
+            ADD,
+            &[Operand::Label(halt), Operand::Immediate(0), RETURN_ADDRESS],
+        );
+        self.emit(JUMP_IF_TRUE, &[Operand::Immediate(1), Operand::Label(main)]);
+        self.place_label(halt);
+        self.code.push(STOP);
+
+        while let Some(function) = self.pending.pop() {
+            self.function(&function)?;
+        }
+
+        let stack_base = self.code.len() as i64;
+        for (address, fixup) in std::mem::take(&mut self.fixups) {
+            self.code[address] = match fixup {
+                Fixup::Label(label) => self.labels[label].unwrap(),
+                Fixup::StackBase => stack_base,
+                _ => unreachable!(),
+            };
+        }
+        Ok(self.code)
+    }
+
+    fn function(&mut self, function: &Function) -> Result<()> {
+        let label = self.functions[&function.name].0;
+        self.place_label(label);
+        self.variables.clear();
+        self.next_slot = 2;
+        for param in &function.params {
+            if self.variables.contains_key(param) {
+                return error(function.line, format!("duplicate parameter '{}'", param));
+            }
+            self.variables.insert(param.clone(), self.next_slot);
+            self.next_slot += 1;
+        }
+        self.locals_end = self.next_slot;
+        self.frame_size = self.next_slot;
+
+        self.block(&function.body)?;
+        self.statement(&Statement::Return(None))?;
+
+        let frame_size = self.frame_size;
+        for (address, fixup) in std::mem::take(&mut self.frame_fixups) {
+            self.code[address] = match fixup {
+                Fixup::Frame(offset) => frame_size + offset,
+                Fixup::FrameSize(sign) => sign * frame_size,
+                _ => unreachable!(),
+            };
+        }
+        Ok(())
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place_label(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len() as i64);
+    }
+
+    fn emit(&mut self, opcode: i64, operands: &[Operand]) {
+        let mut instruction = opcode;
+        let mut scale = 100;
+        for operand in operands {
+            instruction += operand.mode() * scale;
+            scale *= 10;
+        }
+        self.code.push(instruction);
+        for &operand in operands {
+            let address = self.code.len();
+            self.code.push(match operand {
+                Operand::Immediate(value) | Operand::Local(value) => value,
+                Operand::Frame(offset) => {
+                    self.frame_fixups.push((address, Fixup::Frame(offset)));
+                    0
+                }
+                Operand::FrameSize(sign) => {
+                    self.frame_fixups.push((address, Fixup::FrameSize(sign)));
+                    0
+                }
+                Operand::Label(label) => {
+                    self.fixups.push((address, Fixup::Label(label)));
+                    0
+                }
+                Operand::StackBase => {
+                    self.fixups.push((address, Fixup::StackBase));
+                    0
+                }
+            });
+        }
+    }
+
+    fn copy(&mut self, from: Operand, to: Operand) {
+        if from != to {
+            self.emit(ADD, &[from, Operand::Immediate(0), to]);
+        }
+    }
+
+    fn temporary(&mut self) -> Operand {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        self.frame_size = self.frame_size.max(self.next_slot);
+        Operand::Local(slot)
+    }
+
+    fn block(&mut self, statements: &[Statement]) -> Result<()> {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<()> {
+        let mark = self.next_slot;
+        match statement {
+            Statement::Let(name, value, line) => {
+                if self.variables.contains_key(name) {
+                    return error(*line, format!("variable '{}' already declared", name));
+                }
+                let slot = self.temporary();
+                self.locals_end = self.next_slot;
+                self.expression(value, Some(slot))?;
+                if let Operand::Local(offset) = slot {
+                    self.variables.insert(name.clone(), offset);
+                }
+            }
+            Statement::Assign(name, value, line) => {
+                let slot = self.variable(name, *line)?;
+                self.expression(value, Some(slot))?;
+            }
+            Statement::If(condition, then_branch, else_branch) => {
+                let else_label = self.new_label();
+                let end_label = self.new_label();
+                let condition = self.expression(condition, None)?;
+                self.next_slot = mark;
+                self.emit(JUMP_IF_FALSE, &[condition, Operand::Label(else_label)]);
+                self.block(then_branch)?;
+                if !else_branch.is_empty() {
+                    self.emit(
+                        JUMP_IF_TRUE,
+                        &[Operand::Immediate(1), Operand::Label(end_label)],
+                    );
+                }
+                self.place_label(else_label);
+                self.block(else_branch)?;
+                self.place_label(end_label);
+            }
+            Statement::While(condition, body) => {
+                let start_label = self.new_label();
+                let end_label = self.new_label();
+                self.place_label(start_label);
+                // The condition is re-evaluated every iteration, so its
+                // temporaries must not be shared with locals in the body.
+                let condition = self.expression(condition, None)?;
+                self.emit(JUMP_IF_FALSE, &[condition, Operand::Label(end_label)]);
+                self.block(body)?;
+                self.emit(
+                    JUMP_IF_TRUE,
+                    &[Operand::Immediate(1), Operand::Label(start_label)],
+                );
+                self.place_label(end_label);
+            }
+            Statement::Return(value) => {
+                match value {
+                    Some(value) => self.expression(value, Some(RETURN_VALUE))?,
+                    None => self.expression(&Expr::Number(0), Some(RETURN_VALUE))?,
+                };
+                self.emit(JUMP_IF_TRUE, &[Operand::Immediate(1), RETURN_ADDRESS]);
+            }
+            Statement::Expression(expression) => {
+                self.expression(expression, None)?;
+            }
+        }
+        // Variables are function scoped, so slots claimed by a `let` inside
+        // a nested block stay reserved after the block ends.
+        self.next_slot = mark.max(self.locals_end);
+        Ok(())
+    }
+
+    fn variable(&self, name: &str, line: usize) -> Result<Operand> {
+        match self.variables.get(name) {
+            Some(&offset) => Ok(Operand::Local(offset)),
+            None => error(line, format!("unknown variable '{}'", name)),
+        }
+    }
+
+    // Evaluates `expression`, leaving the result in `destination` if one is
+    // given, and returns where the result can be read from.
+    fn expression(&mut self, expression: &Expr, destination: Option<Operand>) -> Result<Operand> {
+        let result = match expression {
+            Expr::Number(n) => Operand::Immediate(*n),
+            Expr::Variable(name, line) => self.variable(name, *line)?,
+            Expr::Unary(op, operand, line) => {
+                let operand = self.expression(operand, None)?;
+                let result = destination.unwrap_or_else(|| self.temporary());
+                match (op, operand) {
+                    (UnaryOp::Negate, Operand::Immediate(n)) => match n.checked_neg() {
+                        Some(n) => Operand::Immediate(n),
+                        None => return error(*line, "constant expression overflows"),
+                    },
+                    (UnaryOp::Not, Operand::Immediate(n)) => Operand::Immediate((n == 0) as i64),
+                    (UnaryOp::Negate, _) => {
+                        self.emit(MULTIPLY, &[operand, Operand::Immediate(-1), result]);
+                        result
+                    }
+                    (UnaryOp::Not, _) => {
+                        self.emit(EQUALS, &[operand, Operand::Immediate(0), result]);
+                        result
+                    }
+                }
+            }
+            Expr::Binary(BinaryOp::And, left, right, _) => self.logical(false, left, right)?,
+            Expr::Binary(BinaryOp::Or, left, right, _) => self.logical(true, left, right)?,
+            Expr::Binary(BinaryOp::Divide, left, right, _) => {
+                self.call("__div", &[(**left).clone(), (**right).clone()], 0)?
+            }
+            Expr::Binary(BinaryOp::Remainder, left, right, _) => {
+                self.call("__mod", &[(**left).clone(), (**right).clone()], 0)?
+            }
+            Expr::Binary(op, left, right, line) => {
+                let mark = self.next_slot;
+                let left = self.expression(left, None)?;
+                let right = self.expression(right, None)?;
+                if let (Operand::Immediate(l), Operand::Immediate(r)) = (left, right) {
+                    self.next_slot = mark;
+                    match fold(*op, l, r) {
+                        Some(n) => Operand::Immediate(n),
+                        None => return error(*line, "constant expression overflows"),
+                    }
+                } else {
+                    let result = destination.unwrap_or(Operand::Local(mark));
+                    self.arithmetic(*op, left, right, result);
+                    self.next_slot = mark;
+                    if destination.is_none() {
+                        self.temporary();
+                    }
+                    result
+                }
+            }
+            Expr::Call(name, args, line) => self.call(name, args, *line)?,
+        };
+        if let Some(destination) = destination {
+            self.copy(result, destination);
+            return Ok(destination);
+        }
+        Ok(result)
+    }
+
+    fn arithmetic(&mut self, op: BinaryOp, left: Operand, right: Operand, result: Operand) {
+        match op {
+            BinaryOp::Add => self.emit(ADD, &[left, right, result]),
+            BinaryOp::Subtract => match right {
+                Operand::Immediate(n) => self.emit(ADD, &[left, Operand::Immediate(-n), result]),
+                _ => {
+                    let negated = self.temporary();
+                    self.emit(MULTIPLY, &[right, Operand::Immediate(-1), negated]);
+                    self.emit(ADD, &[left, negated, result]);
+                }
+            },
+            BinaryOp::Multiply => self.emit(MULTIPLY, &[left, right, result]),
+            BinaryOp::Less => self.emit(LESS_THAN, &[left, right, result]),
+            BinaryOp::Greater => self.emit(LESS_THAN, &[right, left, result]),
+            BinaryOp::LessEqual => {
+                self.emit(LESS_THAN, &[right, left, result]);
+                self.emit(EQUALS, &[result, Operand::Immediate(0), result]);
+            }
+            BinaryOp::GreaterEqual => {
+                self.emit(LESS_THAN, &[left, right, result]);
+                self.emit(EQUALS, &[result, Operand::Immediate(0), result]);
+            }
+            BinaryOp::Equal => self.emit(EQUALS, &[left, right, result]),
+            BinaryOp::NotEqual => {
+                self.emit(EQUALS, &[left, right, result]);
+                self.emit(EQUALS, &[result, Operand::Immediate(0), result]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Short-circuiting `&&` and `||`. The result is always 0 or 1.
+    fn logical(&mut self, is_or: bool, left: &Expr, right: &Expr) -> Result<Operand> {
+        let end_label = self.new_label();
+        let result = self.temporary();
+        let mark = self.next_slot;
+        self.copy(Operand::Immediate(is_or as i64), result);
+        let left = self.expression(left, None)?;
+        let jump = if is_or { JUMP_IF_TRUE } else { JUMP_IF_FALSE };
+        self.emit(jump, &[left, Operand::Label(end_label)]);
+        self.next_slot = mark;
+        let right = self.expression(right, None)?;
+        self.emit(EQUALS, &[right, Operand::Immediate(0), result]);
+        self.emit(EQUALS, &[result, Operand::Immediate(0), result]);
+        self.place_label(end_label);
+        self.next_slot = mark;
+        Ok(result)
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], line: usize) -> Result<Operand> {
+        match name {
+            "input" | "output" | "halt" => {
+                let expected = if name == "output" { 1 } else { 0 };
+                if args.len() != expected {
+                    return error(line, format!("{}() takes {} argument(s)", name, expected));
+                }
+                return Ok(match name {
+                    "input" => {
+                        let result = self.temporary();
+                        self.emit(INPUT, &[result]);
+                        result
+                    }
+                    "output" => {
+                        let value = self.expression(&args[0], None)?;
+                        self.emit(OUTPUT, &[value]);
+                        Operand::Immediate(0)
+                    }
+                    _ => {
+                        self.code.push(STOP);
+                        Operand::Immediate(0)
+                    }
+                });
+            }
+            _ => {}
+        }
+
+        if !self.functions.contains_key(name) {
+            if let Some(function) = self.prelude.remove(name) {
+                let label = self.new_label();
+                self.functions
+                    .insert(name.to_string(), (label, function.params.len()));
+                self.pending.push(function);
+            }
+        }
+        let (label, arity) = match self.functions.get(name) {
+            Some(&entry) => entry,
+            None => return error(line, format!("unknown function '{}'", name)),
+        };
+        if args.len() != arity {
+            return error(
+                line,
+                format!("'{}' takes {} argument(s), got {}", name, arity, args.len()),
+            );
+        }
+
+        // Arguments are evaluated into this frame first, because evaluating
+        // one of them may itself call a function and clobber the next frame.
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.expression(arg, None)?);
+        }
+        for (i, value) in values.into_iter().enumerate() {
+            self.copy(value, Operand::Frame(2 + i as i64));
+        }
+
+        let return_label = self.new_label();
+        self.copy(Operand::Label(return_label), Operand::Frame(0));
+        self.emit(ADJUST_RELATIVE_BASE, &[Operand::FrameSize(1)]);
+        self.emit(
+            JUMP_IF_TRUE,
+            &[Operand::Immediate(1), Operand::Label(label)],
+        );
+        self.place_label(return_label);
+        self.emit(ADJUST_RELATIVE_BASE, &[Operand::FrameSize(-1)]);
+        let result = self.temporary();
+        self.copy(Operand::Frame(1), result);
+        Ok(result)
+    }
+}
+
+fn fold(op: BinaryOp, left: i64, right: i64) -> Option<i64> {
+    match op {
+        BinaryOp::Add => left.checked_add(right),
+        BinaryOp::Subtract => left.checked_sub(right),
+        BinaryOp::Multiply => left.checked_mul(right),
+        BinaryOp::Less => Some((left < right) as i64),
+        BinaryOp::LessEqual => Some((left <= right) as i64),
+        BinaryOp::Greater => Some((left > right) as i64),
+        BinaryOp::GreaterEqual => Some((left >= right) as i64),
+        BinaryOp::Equal => Some((left == right) as i64),
+        BinaryOp::NotEqual => Some((left != right) as i64),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::computer::Computer;
+
+    fn run(source: &str, input: &[i64]) -> Vec<i64> {
+        let program = super::compile(source).unwrap();
+        let mut computer = Computer::new(&program);
+        let mut result = Vec::new();
+        let mut input = input;
+        while let Some(output) = computer.compute(input) {
+            result.push(output);
+            input = &[];
+        }
+        result
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let source = "
+            fn main() {
+                let a = input();
+                let b = input();
+                output(a + b);
+                output(a - b);
+                output(a * b);
+                output(-a);
+                output(2 * 3 + 4);
+            }";
+        assert_eq!(run(source, &[7, 3]), vec![10, 4, 21, -7, 10]);
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let source = "
+            fn main() {
+                let a = input();
+                let b = input();
+                output(a < b);
+                output(a <= b);
+                output(a > b);
+                output(a >= b);
+                output(a == b);
+                output(a != b);
+                output(!a);
+                output(a > 0 && b > 0);
+                output(a > 9 || b > 9);
+            }";
+        assert_eq!(run(source, &[3, 3]), vec![0, 1, 0, 1, 1, 0, 0, 1, 0]);
+        assert_eq!(run(source, &[2, 10]), vec![1, 1, 0, 0, 0, 1, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = "
+            fn main() {
+                let n = input();
+                while (n > 0) {
+                    if (n % 2 == 0) {
+                        output(n);
+                    } else if (n == 5) {
+                        output(500);
+                    }
+                    n = n - 1;
+                }
+            }";
+        assert_eq!(run(source, &[6]), vec![6, 500, 4, 2]);
+    }
+
+    #[test]
+    fn test_block_locals() {
+        let source = "
+            fn main() {
+                let total = 0;
+                let i = 0;
+                while (i < 3) {
+                    let square = i * i;
+                    total = total + square;
+                    i = i + 1;
+                }
+                let last = (total + 1) * (i + 2);
+                output(square);
+                output(total);
+                output(last);
+                total = total > 4 && last > 0;
+                output(total);
+            }";
+        assert_eq!(run(source, &[]), vec![4, 5, 30, 1]);
+    }
+
+    #[test]
+    fn test_functions() {
+        let source = "
+            fn factorial(n) {
+                if (n <= 1) {
+                    return 1;
+                }
+                return n * factorial(n - 1);
+            }
+
+            fn fibonacci(n) {
+                if (n < 2) {
+                    return n;
+                }
+                return fibonacci(n - 1) + fibonacci(n - 2);
+            }
+
+            fn add3(a, b, c) {
+                return a + b + c;
+            }
+
+            fn main() {
+                output(factorial(input()));
+                output(fibonacci(15));
+                output(add3(factorial(3), add3(1, 2, 3), fibonacci(5)));
+            }";
+        assert_eq!(run(source, &[10]), vec![3628800, 610, 17]);
+    }
+
+    #[test]
+    fn test_division() {
+        let source = "
+            fn main() {
+                let a = input();
+                let b = input();
+                output(a / b);
+                output(a % b);
+            }";
+        assert_eq!(run(source, &[17, 5]), vec![3, 2]);
+        assert_eq!(run(source, &[-17, 5]), vec![-3, -2]);
+        assert_eq!(run(source, &[17, -5]), vec![-3, 2]);
+        assert_eq!(run(source, &[1_000_000_007, 3]), vec![333_333_335, 2]);
+        assert_eq!(run(source, &[1, 0]), vec![]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| super::compile(source).unwrap_err();
+        assert_eq!(error("fn f() {}").message, "missing function 'main'");
+        assert_eq!(error("fn main() {\n x = 1;\n}").line, 2);
+        assert_eq!(error("fn main() { f(1); }").message, "unknown function 'f'");
+        assert_eq!(
+            error("fn f(a) {} fn main() { f(); }").message,
+            "'f' takes 1 argument(s), got 0"
+        );
+        assert_eq!(error("fn main() { let a = 1; let a = 2; }").line, 1);
+        for source in &[
+            "fn main() { output(9223372036854775807 + 1); }",
+            "fn main() { output(-9223372036854775807 - 2); }",
+            "fn main() { output(4611686018427387904 * 2); }",
+            "fn main() {\n output(-(-9223372036854775807 - 1));\n}",
+        ] {
+            assert_eq!(error(source).message, "constant expression overflows");
+        }
+        assert_eq!(
+            error("fn main() {\n output(\n 9223372036854775807 + 1);\n}").line,
+            3
+        );
+    }
+}
@@ -1,9 +1,12 @@
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
 
 #[derive(Copy, Clone)]
-enum Instruction {
+pub(crate) enum Instruction {
     Addition(Parameter, Parameter, Parameter),
     Multiplication(Parameter, Parameter, Parameter),
     Input(Parameter),
@@ -17,7 +20,7 @@ enum Instruction {
 }
 
 impl Instruction {
-    fn new(input: i64) -> Option<Instruction> {
+    pub(crate) fn new(input: i64) -> Option<Instruction> {
         let param_1 = FromPrimitive::from_i64(input / 100 % 10)?;
         let param_2 = FromPrimitive::from_i64(input / 1000 % 10)?;
         let param_3 = FromPrimitive::from_i64(input / 10000 % 10)?;
@@ -35,151 +38,867 @@ impl Instruction {
             _ => None,
         }
     }
+
+    pub(crate) fn parameters(&self) -> Vec<Parameter> {
+        match *self {
+            Instruction::Addition(p1, p2, p3)
+            | Instruction::Multiplication(p1, p2, p3)
+            | Instruction::LessThan(p1, p2, p3)
+            | Instruction::Equals(p1, p2, p3) => vec![p1, p2, p3],
+            Instruction::JumpIfTrue(p1, p2) | Instruction::JumpIfFalse(p1, p2) => vec![p1, p2],
+            Instruction::Input(p1)
+            | Instruction::Output(p1)
+            | Instruction::AdjustRelativeBase(p1) => vec![p1],
+            Instruction::Stop => vec![],
+        }
+    }
+
+    pub(crate) fn len(&self) -> i64 {
+        1 + self.parameters().len() as i64
+    }
+
+    // Index of the parameter that is written to, if any.
+    pub(crate) fn write_parameter(&self) -> Option<usize> {
+        match self {
+            Instruction::Addition(..)
+            | Instruction::Multiplication(..)
+            | Instruction::LessThan(..)
+            | Instruction::Equals(..) => Some(2),
+            Instruction::Input(_) => Some(0),
+            _ => None,
+        }
+    }
 }
 
-#[derive(FromPrimitive, Copy, Clone, Debug)]
-enum Parameter {
+#[derive(FromPrimitive, Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Parameter {
     Position = 0,
     Immediate = 1,
     Relative = 2,
 }
 
-pub struct Computer {
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub index: usize,
+    pub token: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid value {:?} at index {} (line {})",
+            self.token, self.index, self.line
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Parses a comma separated Intcode program. Values may be split across lines,
+// `#` starts a comment that runs to the end of the line, and a trailing comma
+// at the end of a line is ignored.
+pub fn parse_program(input: &str) -> Result<Vec<i64>, ParseError> {
+    let mut program = Vec::new();
+    for (line_number, line) in input.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let line = line.strip_suffix(',').unwrap_or(line);
+        for token in line.split(',') {
+            let token = token.trim();
+            match token.parse::<i64>() {
+                Ok(value) => program.push(value),
+                Err(_) => {
+                    return Err(ParseError {
+                        line: line_number + 1,
+                        index: program.len(),
+                        token: token.to_string(),
+                    })
+                }
+            }
+        }
+    }
+    Ok(program)
+}
+
+// Turns the ASCII outputs of a program into text, skipping values that are
+// not ASCII characters.
+pub fn decode_ascii(values: &[i64]) -> String {
+    values
+        .iter()
+        .filter(|&&value| (0..128).contains(&value))
+        .map(|&value| value as u8 as char)
+        .collect()
+}
+
+// Called by `Computer` as it runs. Every method does nothing by default, and
+// a machine built with `Computer::new` uses `NoObserver`, so the calls compile
+// away entirely. Reads of immediate-mode parameters are not reported, since
+// they are part of the instruction rather than data.
+pub trait Observer {
+    fn on_fetch(&mut self, _address: i64, _word: i64) {}
+    fn on_read(&mut self, _address: i64, _value: i64) {}
+    fn on_write(&mut self, _address: i64, _value: i64) {}
+    fn on_input(&mut self, _value: i64) {}
+    fn on_output(&mut self, _value: i64) {}
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct NoObserver;
+
+impl Observer for NoObserver {}
+
+// Resource quotas for running untrusted programs. `None` means unlimited.
+// The step and time budgets cover the whole life of the machine, not a single
+// call to `compute`. Only time spent running counts against `max_duration`;
+// the clock stops whenever the machine hands control back to its caller.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Limits {
+    pub max_address: Option<i64>,
+    pub max_extended_cells: Option<usize>,
+    pub max_steps: Option<u64>,
+    pub max_duration: Option<Duration>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    InvalidInstruction { address: i64, word: i64 },
+    InvalidAddress(i64),
+    AddressLimit { address: i64, limit: i64 },
+    MemoryLimit(usize),
+    StepLimit(u64),
+    TimeLimit(Duration),
+    MissingInput { address: i64 },
+    Overflow { address: i64 },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::InvalidInstruction { address, word } => {
+                write!(f, "invalid instruction {} at {}", word, address)
+            }
+            Fault::InvalidAddress(address) => write!(f, "access to invalid memory - {}", address),
+            Fault::AddressLimit { address, limit } => {
+                write!(f, "address {} is above the limit of {}", address, limit)
+            }
+            Fault::MemoryLimit(limit) => write!(f, "more than {} memory cells used", limit),
+            Fault::StepLimit(limit) => write!(f, "more than {} instructions executed", limit),
+            Fault::TimeLimit(limit) => write!(f, "ran for longer than {:?}", limit),
+            Fault::MissingInput { address } => write!(f, "no input left for {}", address),
+            Fault::Overflow { address } => write!(f, "arithmetic overflow at {}", address),
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+// Checking the clock on every instruction would dominate the run time.
+const STEPS_PER_CLOCK_CHECK: u64 = 1024;
+
+// Cloning a machine snapshots its whole state, queued input included.
+#[derive(Clone)]
+pub struct Computer<O: Observer = NoObserver> {
     instructions: Vec<i64>,
     counter: i64,
     relative_base: i64,
     extended_memory: HashMap<i64, i64>,
+    observer: O,
+    limits: Limits,
+    steps: u64,
+    run_time: Duration,
+    running_since: Option<Instant>,
+    inputs: VecDeque<i64>,
+}
+
+// Why `resume` returned control to the caller.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Status {
+    Output(i64),
+    NeedsInput,
+    Halted,
 }
 
 impl Computer {
     pub fn new(instructions: &[i64]) -> Computer {
+        Computer::with_observer(instructions, NoObserver)
+    }
+}
+
+// An empty machine, to be given a program with `reset` later.
+impl<O: Observer + Default> Default for Computer<O> {
+    fn default() -> Computer<O> {
+        Computer::with_observer(&[], O::default())
+    }
+}
+
+impl<O: Observer> Computer<O> {
+    pub fn with_observer(instructions: &[i64], observer: O) -> Computer<O> {
         Computer {
             instructions: instructions.to_vec(),
             counter: 0,
             relative_base: 0,
             extended_memory: HashMap::new(),
+            observer,
+            limits: Limits::default(),
+            steps: 0,
+            run_time: Duration::ZERO,
+            running_since: None,
+            inputs: VecDeque::new(),
         }
     }
 
-    fn next_instruction(&mut self) -> Option<Instruction> {
-        let instruction = Instruction::new(self.read_memory(self.counter));
+    pub fn with_limits(mut self, limits: Limits) -> Computer<O> {
+        self.limits = limits;
+        self
+    }
+
+    // Puts the machine back to the state `Computer::new(image)` would give,
+    // reusing the memory already allocated. Limits and the observer are kept.
+    pub fn reset(&mut self, image: &[i64]) {
+        self.instructions.clear();
+        self.instructions.extend_from_slice(image);
+        self.counter = 0;
+        self.relative_base = 0;
+        self.extended_memory.clear();
+        self.steps = 0;
+        self.run_time = Duration::ZERO;
+        self.running_since = None;
+        self.inputs.clear();
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn counter(&self) -> i64 {
+        self.counter
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    // Reads a cell without going through the observer. Negative addresses
+    // read as 0.
+    pub fn memory(&self, address: i64) -> i64 {
+        self.read_memory(address).unwrap_or(0)
+    }
+
+    // Number of instructions fetched so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    // Direct access to the initial image and the registers, for engines
+    // that execute part of a program themselves and leave the rest to this
+    // machine. Cells past the image live elsewhere.
+    #[cfg(feature = "jit")]
+    pub(crate) fn image_mut(&mut self) -> &mut [i64] {
+        &mut self.instructions
+    }
+
+    // Makes the image at least `length` cells long, moving any cells that
+    // were already written there out of extended memory.
+    #[cfg(feature = "jit")]
+    pub(crate) fn grow_image(&mut self, length: usize) {
+        if length <= self.instructions.len() {
+            return;
+        }
+        self.instructions.resize(length, 0);
+        let extended = &mut self.extended_memory;
+        let moved: Vec<i64> = extended
+            .keys()
+            .copied()
+            .filter(|&address| address < length as i64)
+            .collect();
+        for address in moved {
+            self.instructions[address as usize] = extended.remove(&address).unwrap();
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    pub(crate) fn set_registers(&mut self, counter: i64, relative_base: i64, steps: u64) {
+        self.counter = counter;
+        self.relative_base = relative_base;
+        self.steps = steps;
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    // Returns None for an input with nothing queued, which is fetched again
+    // once there is something, so observers only see the fetch that runs it.
+    fn next_instruction(&mut self) -> Result<Option<Instruction>, Fault> {
+        self.check_budget()?;
+        let word = self.read_memory(self.counter)?;
+        let instruction = Instruction::new(word);
+        if let Some(Instruction::Input(_)) = instruction {
+            if self.inputs.is_empty() {
+                return Ok(None);
+            }
+        }
+        self.observer.on_fetch(self.counter, word);
         self.counter += 1;
+        self.steps += 1;
         instruction
+            .ok_or(Fault::InvalidInstruction {
+                address: self.counter - 1,
+                word,
+            })
+            .map(Some)
+    }
+
+    fn check_budget(&mut self) -> Result<(), Fault> {
+        if let Some(limit) = self.limits.max_steps {
+            if self.steps >= limit {
+                return Err(Fault::StepLimit(limit));
+            }
+        }
+        if let Some(limit) = self.limits.max_duration {
+            let since = *self.running_since.get_or_insert_with(Instant::now);
+            if self.steps.is_multiple_of(STEPS_PER_CLOCK_CHECK)
+                && self.run_time + since.elapsed() > limit
+            {
+                return Err(Fault::TimeLimit(limit));
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_clock(&mut self) {
+        if let Some(since) = self.running_since.take() {
+            self.run_time += since.elapsed();
+        }
     }
 
     fn compute_three_operands(
         &mut self,
+        address: i64,
         param_1: Parameter,
         param_2: Parameter,
         param_3: Parameter,
-    ) -> (i64, i64, i64) {
-        let op_1 = self.compute_operand(param_1);
-        let op_2 = self.compute_operand(param_2);
-        let op_3 = self.compute_operand(param_3);
-        (op_1, op_2, op_3)
+    ) -> Result<(i64, i64, i64), Fault> {
+        let op_1 = self.compute_operand(address, param_1)?;
+        let op_2 = self.compute_operand(address, param_2)?;
+        let op_3 = self.compute_operand(address, param_3)?;
+        Ok((op_1, op_2, op_3))
     }
 
-    fn compute_two_operands(&mut self, param_1: Parameter, param_2: Parameter) -> (i64, i64) {
-        let op_1 = self.compute_operand(param_1);
-        let op_2 = self.compute_operand(param_2);
-        (op_1, op_2)
+    fn compute_two_operands(
+        &mut self,
+        address: i64,
+        param_1: Parameter,
+        param_2: Parameter,
+    ) -> Result<(i64, i64), Fault> {
+        let op_1 = self.compute_operand(address, param_1)?;
+        let op_2 = self.compute_operand(address, param_2)?;
+        Ok((op_1, op_2))
     }
 
-    fn compute_operand(&mut self, parameter: Parameter) -> i64 {
+    // `address` is the instruction's, for reporting an overflow.
+    fn compute_operand(&mut self, address: i64, parameter: Parameter) -> Result<i64, Fault> {
         let op = match parameter {
-            Parameter::Position => self.read_memory(self.counter),
+            Parameter::Position => self.read_memory(self.counter)?,
             Parameter::Immediate => self.counter,
-            Parameter::Relative => self.relative_base + self.read_memory(self.counter),
+            Parameter::Relative => self
+                .relative_base
+                .checked_add(self.read_memory(self.counter)?)
+                .ok_or(Fault::Overflow { address })?,
         };
         self.counter += 1;
-        op
+        Ok(op)
     }
 
-    fn read_memory(&self, location: i64) -> i64 {
+    fn check_address(&self, location: i64) -> Result<(), Fault> {
         if location < 0 {
-            panic!("read from invalid memory - {}", location);
+            return Err(Fault::InvalidAddress(location));
         }
-        if location < self.instructions.len() as i64 {
-            return self.instructions[location as usize];
+        if let Some(limit) = self.limits.max_address {
+            if location > limit {
+                return Err(Fault::AddressLimit {
+                    address: location,
+                    limit,
+                });
+            }
         }
-        *self.extended_memory.get(&location).unwrap_or(&0)
+        Ok(())
     }
 
-    fn write_memory(&mut self, location: i64, val: i64) {
-        if location < 0 {
-            panic!("write to invalid memory - {}", location);
+    fn read_memory(&self, location: i64) -> Result<i64, Fault> {
+        self.check_address(location)?;
+        if location < self.instructions.len() as i64 {
+            return Ok(self.instructions[location as usize]);
         }
+        Ok(*self.extended_memory.get(&location).unwrap_or(&0))
+    }
+
+    fn write_memory(&mut self, location: i64, val: i64) -> Result<(), Fault> {
+        self.check_address(location)?;
         if location < self.instructions.len() as i64 {
             self.instructions[location as usize] = val;
-            return;
+            return Ok(());
+        }
+        if let Some(limit) = self.limits.max_extended_cells {
+            if self.extended_memory.len() >= limit && !self.extended_memory.contains_key(&location)
+            {
+                return Err(Fault::MemoryLimit(limit));
+            }
         }
         self.extended_memory.insert(location, val);
+        Ok(())
+    }
+
+    fn load(&mut self, parameter: Parameter, location: i64) -> Result<i64, Fault> {
+        let val = self.read_memory(location)?;
+        if parameter != Parameter::Immediate {
+            self.observer.on_read(location, val);
+        }
+        Ok(val)
+    }
+
+    fn store(&mut self, location: i64, val: i64) -> Result<(), Fault> {
+        self.write_memory(location, val)?;
+        self.observer.on_write(location, val);
+        Ok(())
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.inputs.push_back(value);
+    }
+
+    // Queues every byte of `text`, for programs that read ASCII.
+    pub fn push_ascii(&mut self, text: &str) {
+        self.inputs.extend(text.bytes().map(i64::from));
+    }
+
+    // Runs until the program stops or waits for input, collecting every
+    // output on the way. The status is either `Halted` or `NeedsInput`.
+    pub fn run_until_blocked(&mut self) -> Result<(Vec<i64>, Status), Fault> {
+        let mut outputs = Vec::new();
+        loop {
+            match self.resume()? {
+                Status::Output(value) => outputs.push(value),
+                status => return Ok((outputs, status)),
+            }
+        }
     }
 
+    // Runs until the next output, returning `None` once the program stops.
+    // An invalid instruction also stops the program; other faults panic.
+    // Inputs that are not consumed before the output stay queued.
     pub fn compute(&mut self, input: &[i64]) -> Option<i64> {
-        let mut input_counter = 0;
-        while let Some(instruction) = self.next_instruction() {
-            match instruction {
-                Instruction::Addition(param_1, param_2, param_3) => {
-                    let (op_1, op_2, op_3) = self.compute_three_operands(param_1, param_2, param_3);
-                    self.write_memory(
-                        op_3,
-                        self.read_memory(op_1) + self.read_memory(op_2),
-                    );
-                }
-                Instruction::Multiplication(param_1, param_2, param_3) => {
-                    let (op_1, op_2, op_3) = self.compute_three_operands(param_1, param_2, param_3);
-                    self.write_memory(
-                        op_3,
-                        self.read_memory(op_1) * self.read_memory(op_2),
-                    );
-                }
-                Instruction::Input(param) => {
-                    let op_1 = self.compute_operand(param);
-                    self.write_memory(op_1, input[input_counter]);
-                    input_counter += 1;
-                }
-                Instruction::Output(param) => {
-                    let op_1 = self.compute_operand(param);
-                    return Some(self.read_memory(op_1));
-                }
-                Instruction::JumpIfTrue(param_1, param_2) => {
-                    let (op_1, op_2) = self.compute_two_operands(param_1, param_2);
-                    if self.read_memory(op_1) != 0 {
-                        self.counter = self.read_memory(op_2);
-                    }
-                }
-                Instruction::JumpIfFalse(param_1, param_2) => {
-                    let (op_1, op_2) = self.compute_two_operands(param_1, param_2);
-                    if self.read_memory(op_1) == 0 {
-                        self.counter = self.read_memory(op_2);
-                    }
-                }
-                Instruction::LessThan(param_1, param_2, param_3) => {
-                    let (op_1, op_2, op_3) = self.compute_three_operands(param_1, param_2, param_3);
-                    if self.read_memory(op_1) < self.read_memory(op_2) {
-                        self.write_memory(op_3, 1);
-                    } else {
-                        self.write_memory(op_3, 0);
-                    }
+        match self.try_compute(input) {
+            Ok(output) => output,
+            Err(Fault::InvalidInstruction { .. }) => None,
+            Err(fault) => panic!("{}", fault),
+        }
+    }
+
+    pub fn try_compute(&mut self, input: &[i64]) -> Result<Option<i64>, Fault> {
+        self.inputs.extend(input);
+        match self.resume()? {
+            Status::Output(val) => Ok(Some(val)),
+            Status::Halted => Ok(None),
+            Status::NeedsInput => Err(Fault::MissingInput {
+                address: self.counter,
+            }),
+        }
+    }
+
+    // Runs until the program produces an output, stops, or reads from an
+    // empty input queue. In the last case the input instruction has not been
+    // executed, so calling `push_input` and then `resume` carries on.
+    pub fn resume(&mut self) -> Result<Status, Fault> {
+        loop {
+            if let Some(status) = self.step()? {
+                return Ok(status);
+            }
+        }
+    }
+
+    // Executes one instruction, returning the status if it is one that hands
+    // control back to the caller.
+    pub(crate) fn step(&mut self) -> Result<Option<Status>, Fault> {
+        let result = self.execute();
+        if !matches!(result, Ok(None)) {
+            self.stop_clock();
+        }
+        result
+    }
+
+    fn execute(&mut self) -> Result<Option<Status>, Fault> {
+        let address = self.counter;
+        let instruction = match self.next_instruction()? {
+            Some(instruction) => instruction,
+            None => return Ok(Some(Status::NeedsInput)),
+        };
+        match instruction {
+            Instruction::Addition(param_1, param_2, param_3) => {
+                let (op_1, op_2, op_3) =
+                    self.compute_three_operands(address, param_1, param_2, param_3)?;
+                let val = self
+                    .load(param_1, op_1)?
+                    .checked_add(self.load(param_2, op_2)?)
+                    .ok_or(Fault::Overflow { address })?;
+                self.store(op_3, val)?;
+            }
+            Instruction::Multiplication(param_1, param_2, param_3) => {
+                let (op_1, op_2, op_3) =
+                    self.compute_three_operands(address, param_1, param_2, param_3)?;
+                let val = self
+                    .load(param_1, op_1)?
+                    .checked_mul(self.load(param_2, op_2)?)
+                    .ok_or(Fault::Overflow { address })?;
+                self.store(op_3, val)?;
+            }
+            Instruction::Input(param) => {
+                let val = self
+                    .inputs
+                    .pop_front()
+                    .expect("inputs are only fetched when one is queued");
+                let op_1 = self.compute_operand(address, param)?;
+                self.observer.on_input(val);
+                self.store(op_1, val)?;
+            }
+            Instruction::Output(param) => {
+                let op_1 = self.compute_operand(address, param)?;
+                let val = self.load(param, op_1)?;
+                self.observer.on_output(val);
+                return Ok(Some(Status::Output(val)));
+            }
+            Instruction::JumpIfTrue(param_1, param_2) => {
+                let (op_1, op_2) = self.compute_two_operands(address, param_1, param_2)?;
+                if self.load(param_1, op_1)? != 0 {
+                    self.counter = self.load(param_2, op_2)?;
                 }
-                Instruction::Equals(param_1, param_2, param_3) => {
-                    let (op_1, op_2, op_3) = self.compute_three_operands(param_1, param_2, param_3);
-                    if self.read_memory(op_1) == self.read_memory(op_2) {
-                        self.write_memory(op_3, 1);
-                    } else {
-                        self.write_memory(op_3, 0);
-                    }
+            }
+            Instruction::JumpIfFalse(param_1, param_2) => {
+                let (op_1, op_2) = self.compute_two_operands(address, param_1, param_2)?;
+                if self.load(param_1, op_1)? == 0 {
+                    self.counter = self.load(param_2, op_2)?;
                 }
-                Instruction::AdjustRelativeBase(param) => {
-                    let op_1 = self.compute_operand(param);
-                    self.relative_base += self.read_memory(op_1);
+            }
+            Instruction::LessThan(param_1, param_2, param_3) => {
+                let (op_1, op_2, op_3) =
+                    self.compute_three_operands(address, param_1, param_2, param_3)?;
+                if self.load(param_1, op_1)? < self.load(param_2, op_2)? {
+                    self.store(op_3, 1)?;
+                } else {
+                    self.store(op_3, 0)?;
                 }
-                Instruction::Stop => {
-                    return None;
+            }
+            Instruction::Equals(param_1, param_2, param_3) => {
+                let (op_1, op_2, op_3) =
+                    self.compute_three_operands(address, param_1, param_2, param_3)?;
+                if self.load(param_1, op_1)? == self.load(param_2, op_2)? {
+                    self.store(op_3, 1)?;
+                } else {
+                    self.store(op_3, 0)?;
                 }
-            };
+            }
+            Instruction::AdjustRelativeBase(param) => {
+                let op_1 = self.compute_operand(address, param)?;
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(self.load(param, op_1)?)
+                    .ok_or(Fault::Overflow { address })?;
+            }
+            Instruction::Stop => return Ok(Some(Status::Halted)),
+        }
+        Ok(None)
+    }
+}
+
+impl<O: Observer> Computer<O> {
+    // Every cell that does not hold 0, in address order.
+    pub(crate) fn nonzero_cells(&self) -> Vec<(i64, i64)> {
+        let image = self.instructions.iter().enumerate();
+        let extended = self.extended_memory.iter();
+        let mut cells: Vec<(i64, i64)> = image
+            .map(|(address, &value)| (address as i64, value))
+            .chain(extended.map(|(&address, &value)| (address, value)))
+            .filter(|&(_, value)| value != 0)
+            .collect();
+        cells.sort_unstable();
+        cells
+    }
+}
+
+// Two machines are equal when they will behave the same from here on: same
+// memory, counter, relative base and queued input. Memory is compared by
+// value, so a cell holding 0 is the same whether it belongs to the image, was
+// written with 0 or was never touched. The observer, limits and step count
+// are not part of the state.
+impl<O: Observer> PartialEq for Computer<O> {
+    fn eq(&self, other: &Computer<O>) -> bool {
+        self.counter == other.counter
+            && self.relative_base == other.relative_base
+            && self.inputs == other.inputs
+            && self.nonzero_cells() == other.nonzero_cells()
+    }
+}
+
+impl<O: Observer> Eq for Computer<O> {}
+
+impl<O: Observer> Hash for Computer<O> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.counter.hash(state);
+        self.relative_base.hash(state);
+        self.inputs.hash(state);
+        self.nonzero_cells().hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Computer, Fault, Limits, Observer, ParseError, Status};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+    }
+
+    impl Observer for Recorder {
+        fn on_fetch(&mut self, address: i64, word: i64) {
+            self.events.push(format!("fetch {} {}", address, word));
+        }
+        fn on_read(&mut self, address: i64, value: i64) {
+            self.events.push(format!("read {} {}", address, value));
         }
-        None
+        fn on_write(&mut self, address: i64, value: i64) {
+            self.events.push(format!("write {} {}", address, value));
+        }
+        fn on_input(&mut self, value: i64) {
+            self.events.push(format!("input {}", value));
+        }
+        fn on_output(&mut self, value: i64) {
+            self.events.push(format!("output {}", value));
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let mut computer = Computer::with_observer(
+            &[3, 9, 1001, 9, 5, 10, 4, 10, 99, 0, 0],
+            Recorder::default(),
+        );
+        assert_eq!(computer.compute(&[7]), Some(12));
+        assert_eq!(computer.compute(&[]), None);
+        assert_eq!(
+            computer.observer().events,
+            vec![
+                "fetch 0 3",
+                "input 7",
+                "write 9 7",
+                "fetch 2 1001",
+                "read 9 7",
+                "write 10 12",
+                "fetch 6 4",
+                "read 10 12",
+                "output 12",
+                "fetch 8 99",
+            ]
+        );
+
+        // waiting for input does not fetch the input instruction
+        let mut computer = Computer::with_observer(&[3, 5, 4, 5, 99, 0], Recorder::default());
+        assert_eq!(computer.resume(), Ok(Status::NeedsInput));
+        assert_eq!(computer.resume(), Ok(Status::NeedsInput));
+        computer.push_input(4);
+        assert_eq!(computer.resume(), Ok(Status::Output(4)));
+        assert_eq!(computer.steps(), 2);
+        assert_eq!(computer.observer().events[..2], ["fetch 0 3", "input 4"]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits {
+            max_address: Some(1000),
+            ..Limits::default()
+        };
+        let mut computer = Computer::new(&[1101, 1, 1, 1001, 99]).with_limits(limits);
+        assert_eq!(
+            computer.try_compute(&[]),
+            Err(Fault::AddressLimit {
+                address: 1001,
+                limit: 1000
+            })
+        );
+
+        // writes to an ever increasing address
+        let program = [109, 1, 21101, 0, 0, 10, 1105, 1, 0];
+        let limits = Limits {
+            max_extended_cells: Some(100),
+            ..Limits::default()
+        };
+        let mut computer = Computer::new(&program).with_limits(limits);
+        assert_eq!(computer.try_compute(&[]), Err(Fault::MemoryLimit(100)));
+
+        let limits = Limits {
+            max_steps: Some(50),
+            ..Limits::default()
+        };
+        let mut computer = Computer::new(&[1105, 1, 0]).with_limits(limits);
+        assert_eq!(computer.try_compute(&[]), Err(Fault::StepLimit(50)));
+        assert_eq!(computer.steps(), 50);
+
+        let limits = Limits {
+            max_duration: Some(Duration::from_millis(10)),
+            ..Limits::default()
+        };
+        let mut computer = Computer::new(&[1105, 1, 0]).with_limits(limits);
+        assert_eq!(
+            computer.try_compute(&[]),
+            Err(Fault::TimeLimit(Duration::from_millis(10)))
+        );
+
+        // time spent waiting for input does not count
+        let mut computer = Computer::new(&[3, 7, 4, 7, 1105, 1, 0, 0]).with_limits(limits);
+        for value in 0..1000 {
+            assert_eq!(computer.try_compute(&[value]), Ok(Some(value)));
+            if value == 0 {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        }
+        assert!(computer.steps() > 2 * super::STEPS_PER_CLOCK_CHECK);
+    }
+
+    #[test]
+    fn test_faults() {
+        let mut computer = Computer::new(&[4, -1, 99]);
+        assert_eq!(computer.try_compute(&[]), Err(Fault::InvalidAddress(-1)));
+
+        let mut computer = Computer::new(&[104, 1, 42]);
+        assert_eq!(computer.try_compute(&[]), Ok(Some(1)));
+        assert_eq!(
+            computer.try_compute(&[]),
+            Err(Fault::InvalidInstruction {
+                address: 2,
+                word: 42
+            })
+        );
+
+        // arithmetic that does not fit in 64 bits faults instead of wrapping
+        for &(ref program, address) in &[
+            (vec![1101, i64::MAX, 1, 0, 99], 0),
+            (vec![104, 0, 1102, i64::MAX, 2, 0, 99], 2),
+            (vec![109, i64::MIN, 109, -1, 99], 2),
+            (vec![109, i64::MAX, 204, 1, 99], 2),
+        ] {
+            let mut computer = Computer::new(program);
+            let mut result = computer.resume();
+            while let Ok(Status::Output(_)) = result {
+                result = computer.resume();
+            }
+            assert_eq!(result, Err(Fault::Overflow { address }));
+        }
+    }
+
+    #[test]
+    fn test_parse_program() {
+        assert_eq!(
+            super::parse_program("1,0,0,3,99\n"),
+            Ok(vec![1, 0, 0, 3, 99])
+        );
+        assert_eq!(
+            super::parse_program("# header\n1, 0, 0,  # add\n3,\n-99,\n\n"),
+            Ok(vec![1, 0, 0, 3, -99])
+        );
+        assert_eq!(
+            super::parse_program("1,0\n0,x3,99"),
+            Err(ParseError {
+                line: 2,
+                index: 3,
+                token: "x3".to_string()
+            })
+        );
+        assert_eq!(
+            super::parse_program("1,,2").unwrap_err().to_string(),
+            "invalid value \"\" at index 1 (line 1)"
+        );
+    }
+
+    #[test]
+    fn test_resume() {
+        // adds pairs of inputs until it reads a zero
+        let program = [
+            3, 17, 1006, 17, 16, 3, 18, 1, 17, 18, 19, 4, 19, 1105, 1, 0, 99, 0, 0, 0,
+        ];
+        let mut computer = Computer::new(&program);
+        assert_eq!(computer.resume(), Ok(Status::NeedsInput));
+        computer.push_input(2);
+        assert_eq!(computer.resume(), Ok(Status::NeedsInput));
+        computer.push_input(3);
+        computer.push_input(4);
+        assert_eq!(computer.resume(), Ok(Status::Output(5)));
+        assert_eq!(computer.resume(), Ok(Status::NeedsInput));
+        assert_eq!(
+            computer.try_compute(&[]),
+            Err(Fault::MissingInput { address: 5 })
+        );
+        assert_eq!(computer.compute(&[6]), Some(10));
+        computer.push_input(0);
+        assert_eq!(computer.resume(), Ok(Status::Halted));
+    }
+
+    #[test]
+    fn test_state_equality() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::HashSet;
+        use std::hash::{Hash, Hasher};
+
+        // writes input to cell 100, then waits for another input
+        let program = [3, 100, 3, 101, 99];
+        let mut a = Computer::new(&program);
+        let mut b = Computer::new(&program).with_limits(Limits {
+            max_steps: Some(10),
+            ..Limits::default()
+        });
+        assert!(a == b);
+        a.push_input(0);
+        assert!(a != b);
+        b.push_input(0);
+        assert_eq!(a.resume(), Ok(Status::NeedsInput));
+        assert!(a != b);
+        assert_eq!(b.resume(), Ok(Status::NeedsInput));
+        // both wrote 0 to cell 100; c's image already had zeros past the end
+        let mut c = Computer::new(&program);
+        c.reset(&[3, 100, 3, 101, 99, 0, 0]);
+        c.push_input(0);
+        assert_eq!(c.resume(), Ok(Status::NeedsInput));
+        assert!(a == b && b == c);
+
+        let states: HashSet<Computer> = vec![a, b, c].into_iter().collect();
+        assert_eq!(states.len(), 1);
+
+        // writes 0 to cell 100 unless the input is nonzero, then clears the
+        // input cell, so both runs halt with the same memory
+        let program = [3, 14, 1005, 14, 9, 1101, 0, 0, 100, 1101, 0, 0, 14, 99, 0];
+        let mut wrote = Computer::new(&program);
+        assert_eq!(wrote.compute(&[0]), None);
+        let mut skipped = Computer::new(&program);
+        assert_eq!(skipped.compute(&[1]), None);
+        assert_eq!((wrote.steps(), skipped.steps()), (5, 4));
+        assert!(wrote == skipped);
+        let hash = |computer: &Computer| {
+            let mut hasher = DefaultHasher::new();
+            computer.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&wrote), hash(&skipped));
     }
 }
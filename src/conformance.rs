@@ -0,0 +1,310 @@
+// Checks an Intcode interpreter against a corpus of small programs with known
+// results, so that every engine is held to the same behaviour as `Computer`.
+//
+// The corpus lives in `conformance/intcode.txt`; the format is described at
+// the top of that file. Each case gives a program and its input, and expects
+// the exact outputs, the final value of some cells and either a halt or a
+// particular fault. Faults are compared by how they display, so an engine
+// has to report the same address as the reference, not just the same kind of
+// failure.
+//
+// The runner talks to a `Backend`: run a program to the end on some input,
+// then answer questions about the memory it left behind. Every
+// `IntcodeEngine` is one.
+
+use crate::computer::{parse_program, Fault};
+use crate::engine::IntcodeEngine;
+use std::collections::HashSet;
+use std::fmt;
+
+// Instructions a case may execute before the backend is considered stuck.
+// The longest case in the corpus needs a few hundred.
+pub const MAX_STEPS: u64 = 100_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Case {
+    pub name: String,
+    pub program: Vec<i64>,
+    pub input: Vec<i64>,
+    pub output: Vec<i64>,
+    pub memory: Vec<(i64, i64)>,
+    pub fault: Option<String>,
+}
+
+// What a backend reports about one run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub output: Vec<i64>,
+    pub fault: Option<Fault>,
+}
+
+pub trait Backend {
+    fn name(&self) -> &str;
+
+    // Loads `program`, queues `input` and runs until the program halts or
+    // fails. Running out of input is a `Fault::MissingInput`, and executing
+    // more than `max_steps` instructions a `Fault::StepLimit`. Outputs
+    // produced before a fault are kept.
+    fn execute(&mut self, program: &[i64], input: &[i64], max_steps: u64) -> Outcome;
+
+    // Reads a cell as the last `execute` left it.
+    fn memory(&self, address: i64) -> i64;
+}
+
+impl<E: IntcodeEngine + ?Sized> Backend for E {
+    fn name(&self) -> &str {
+        IntcodeEngine::name(self)
+    }
+
+    fn execute(&mut self, program: &[i64], input: &[i64], max_steps: u64) -> Outcome {
+        self.load(program);
+        self.set_step_limit(Some(max_steps));
+        self.provide_inputs(input);
+        let mut output = Vec::new();
+        let fault = loop {
+            match self.take_output() {
+                Ok(Some(value)) => output.push(value),
+                Ok(None) => break None,
+                Err(fault) => break Some(fault),
+            }
+        };
+        Outcome { output, fault }
+    }
+
+    fn memory(&self, address: i64) -> i64 {
+        IntcodeEngine::memory(self, address)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub case: String,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.case, self.message)
+    }
+}
+
+// Runs one case, describing the first way the backend got it wrong.
+pub fn run_case<B: Backend + ?Sized>(backend: &mut B, case: &Case) -> Result<(), String> {
+    let outcome = backend.execute(&case.program, &case.input, MAX_STEPS);
+    if outcome.output != case.output {
+        return Err(format!(
+            "expected output {:?}, got {:?}",
+            case.output, outcome.output
+        ));
+    }
+    match (&case.fault, &outcome.fault) {
+        (None, None) => {}
+        (Some(expected), Some(fault)) if *expected == fault.to_string() => {}
+        (Some(expected), None) => return Err(format!("expected '{}', but halted", expected)),
+        (None, Some(fault)) => return Err(format!("expected a halt, got '{}'", fault)),
+        (Some(expected), Some(fault)) => {
+            return Err(format!("expected '{}', got '{}'", expected, fault))
+        }
+    }
+    for &(address, expected) in &case.memory {
+        let value = backend.memory(address);
+        if value != expected {
+            return Err(format!(
+                "expected {} at {}, found {}",
+                expected, address, value
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Runs every case and returns the ones that failed.
+pub fn check<B: Backend + ?Sized>(backend: &mut B, cases: &[Case]) -> Vec<Failure> {
+    cases
+        .iter()
+        .filter_map(|case| {
+            run_case(backend, case).err().map(|message| Failure {
+                case: case.name.clone(),
+                message,
+            })
+        })
+        .collect()
+}
+
+// The corpus shipped with the crate.
+pub fn corpus() -> Vec<Case> {
+    parse_corpus(include_str!("../conformance/intcode.txt")).expect("the corpus is well formed")
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CorpusError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CorpusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CorpusError {}
+
+pub fn parse_corpus(text: &str) -> Result<Vec<Case>, CorpusError> {
+    let mut cases: Vec<Case> = Vec::new();
+    let mut names = HashSet::new();
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| CorpusError {
+            line: index + 1,
+            message,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        let value = value.trim();
+        if key == "case" {
+            if value.is_empty() || !names.insert(value.to_string()) {
+                return Err(error(format!("missing or repeated case name '{}'", value)));
+            }
+            cases.push(Case {
+                name: value.to_string(),
+                program: Vec::new(),
+                input: Vec::new(),
+                output: Vec::new(),
+                memory: Vec::new(),
+                fault: None,
+            });
+            continue;
+        }
+        let case = cases
+            .last_mut()
+            .ok_or_else(|| error(format!("'{}' before the first case", key)))?;
+        let values = || {
+            parse_program(value).map_err(|parse_error| error(format!("{}: {}", key, parse_error)))
+        };
+        match key {
+            "program" => case.program = values()?,
+            "input" => case.input = values()?,
+            "output" => case.output = values()?,
+            "memory" => {
+                for cell in value.split_whitespace() {
+                    let parsed = cell
+                        .split_once('=')
+                        .and_then(|(address, value)| {
+                            Some((address.parse().ok()?, value.parse().ok()?))
+                        })
+                        .filter(|&(address, _)| address >= 0);
+                    case.memory
+                        .push(parsed.ok_or_else(|| error(format!("invalid cell '{}'", cell)))?);
+                }
+            }
+            "fault" if !value.is_empty() => case.fault = Some(value.to_string()),
+            _ => return Err(error(format!("unexpected line '{}'", line))),
+        }
+    }
+    if let Some(case) = cases.iter().find(|case| case.program.is_empty()) {
+        return Err(CorpusError {
+            line: text.lines().count(),
+            message: format!("case '{}' has no program", case.name),
+        });
+    }
+    Ok(cases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, Outcome};
+    use crate::computer::{Computer, Instruction};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_reference() {
+        let corpus = super::corpus();
+        let failures = super::check(&mut crate::engine::boot(&[]), &corpus);
+        assert!(failures.is_empty(), "{:?}", failures);
+
+        // every opcode is run with every mode it allows
+        let words: HashSet<i64> = corpus
+            .iter()
+            .filter_map(|case| case.program.get(2).copied())
+            .collect();
+        for opcode in 1..=9 {
+            let arity = Instruction::new(opcode).unwrap().len() - 1;
+            for modes in 0..3i64.pow(arity as u32) {
+                let word = (0..arity).fold(opcode, |word, index| {
+                    word + modes / 3i64.pow(index as u32) % 3 * 10i64.pow(index as u32 + 2)
+                });
+                assert!(words.contains(&word), "{} is not covered", word);
+            }
+        }
+    }
+
+    // Keeps only the first output and loses memory beyond the image.
+    struct Forgetful {
+        computer: Computer,
+        image_size: i64,
+    }
+
+    impl Backend for Forgetful {
+        fn name(&self) -> &str {
+            "forgetful"
+        }
+
+        fn execute(&mut self, program: &[i64], input: &[i64], max_steps: u64) -> Outcome {
+            self.image_size = program.len() as i64;
+            let mut outcome = self.computer.execute(program, input, max_steps);
+            outcome.output.truncate(1);
+            outcome
+        }
+
+        fn memory(&self, address: i64) -> i64 {
+            if address < self.image_size {
+                self.computer.memory(address)
+            } else {
+                0
+            }
+        }
+    }
+
+    #[test]
+    fn test_failures() {
+        let cases = super::parse_corpus(
+            "case quine
+            program 109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99
+            output 109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99
+
+            case fault
+            program 3,0,99
+            fault no input left for 0
+
+            case far
+            program 109,1000000,21101,3,4,0,99
+            memory 1000000=7",
+        )
+        .unwrap();
+        let mut backend = Forgetful {
+            computer: Computer::new(&[]),
+            image_size: 0,
+        };
+        let failures = super::check(&mut backend, &cases);
+        let messages: Vec<String> = failures.iter().map(|failure| failure.to_string()).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("quine: expected output [109, 1, 204,"));
+        assert_eq!(messages[1], "far: expected 7 at 1000000, found 0");
+        assert!(!super::check(&mut backend, &super::corpus()).is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = super::parse_corpus("program 1,2\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: 'program' before the first case");
+        let error = super::parse_corpus("case a\nprogram 99\ncase a\n").unwrap_err();
+        assert_eq!(error.line, 3);
+        let error = super::parse_corpus("case a\nprogram 99\nmemory 3:4\n").unwrap_err();
+        assert_eq!(error.message, "invalid cell '3:4'");
+        let error = super::parse_corpus("case a\ninput 1\n").unwrap_err();
+        assert_eq!(error.message, "case 'a' has no program");
+    }
+}
@@ -0,0 +1,331 @@
+// A compact, checksummed on-disk format for Intcode programs.
+//
+// Layout, with all integers after the header stored as LEB128 varints:
+//
+//     magic       4 bytes   "INTC"
+//     version     1 byte    currently 1
+//     flags       1 byte    bit 0: symbol table, bit 1: source map
+//     checksum    4 bytes   CRC-32 (IEEE, little endian) of everything below
+//     words       count, then each word zigzag encoded
+//     symbols     count, then (address, name length, UTF-8 name) per entry
+//     source map  count, then (address, line) per entry
+//
+// The symbol table and source map are only present when their flag is set.
+// The checksum does not cover the header, so flag bits with no meaning are
+// rejected rather than ignored.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+const MAGIC: &[u8; 4] = b"INTC";
+const VERSION: u8 = 1;
+const HAS_SYMBOLS: u8 = 1;
+const HAS_SOURCE_MAP: u8 = 2;
+const HEADER_LEN: usize = 10;
+
+#[derive(Debug)]
+pub enum FormatError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownFlags(u8),
+    ChecksumMismatch { expected: u32, actual: u32 },
+    Truncated,
+    VarintOverflow,
+    InvalidSymbol,
+    TrailingBytes,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Io(error) => write!(f, "{}", error),
+            FormatError::BadMagic => write!(f, "not an Intcode container"),
+            FormatError::UnsupportedVersion(version) => {
+                write!(f, "unsupported container version {}", version)
+            }
+            FormatError::UnknownFlags(flags) => write!(f, "unknown flags {:#04x}", flags),
+            FormatError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected {:08x}, found {:08x}",
+                expected, actual
+            ),
+            FormatError::Truncated => write!(f, "container is truncated"),
+            FormatError::VarintOverflow => write!(f, "varint does not fit in 64 bits"),
+            FormatError::InvalidSymbol => write!(f, "symbol name is not valid UTF-8"),
+            FormatError::TrailingBytes => write!(f, "unexpected bytes after the container"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+impl From<io::Error> for FormatError {
+    fn from(error: io::Error) -> FormatError {
+        FormatError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Container {
+    pub program: Vec<i64>,
+    pub symbols: BTreeMap<i64, String>,
+    pub source_map: BTreeMap<i64, u64>,
+}
+
+impl Container {
+    pub fn new(program: &[i64]) -> Container {
+        Container {
+            program: program.to_vec(),
+            ..Container::default()
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut flags = 0;
+        let mut body = Vec::new();
+        write_varint(&mut body, self.program.len() as u64);
+        for &word in &self.program {
+            write_varint(&mut body, zigzag(word));
+        }
+        if !self.symbols.is_empty() {
+            flags |= HAS_SYMBOLS;
+            write_varint(&mut body, self.symbols.len() as u64);
+            for (&address, name) in &self.symbols {
+                write_varint(&mut body, zigzag(address));
+                write_varint(&mut body, name.len() as u64);
+                body.extend_from_slice(name.as_bytes());
+            }
+        }
+        if !self.source_map.is_empty() {
+            flags |= HAS_SOURCE_MAP;
+            write_varint(&mut body, self.source_map.len() as u64);
+            for (&address, &line) in &self.source_map {
+                write_varint(&mut body, zigzag(address));
+                write_varint(&mut body, line);
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(flags);
+        bytes.extend_from_slice(&crc32(&body).to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Container, FormatError> {
+        if !bytes.starts_with(&MAGIC[..bytes.len().min(MAGIC.len())]) {
+            return Err(FormatError::BadMagic);
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(FormatError::Truncated);
+        }
+        if bytes[4] != VERSION {
+            return Err(FormatError::UnsupportedVersion(bytes[4]));
+        }
+        let flags = bytes[5];
+        if flags & !(HAS_SYMBOLS | HAS_SOURCE_MAP) != 0 {
+            return Err(FormatError::UnknownFlags(flags));
+        }
+        let mut expected = [0; 4];
+        expected.copy_from_slice(&bytes[6..HEADER_LEN]);
+        let expected = u32::from_le_bytes(expected);
+        let body = &bytes[HEADER_LEN..];
+        let actual = crc32(body);
+        if expected != actual {
+            return Err(FormatError::ChecksumMismatch { expected, actual });
+        }
+
+        let mut reader = Reader { bytes: body };
+        let mut container = Container::default();
+        let words = reader.varint()?;
+        for _ in 0..words {
+            container.program.push(unzigzag(reader.varint()?));
+        }
+        if flags & HAS_SYMBOLS != 0 {
+            for _ in 0..reader.varint()? {
+                let address = unzigzag(reader.varint()?);
+                let length = reader.varint()? as usize;
+                let name = String::from_utf8(reader.take(length)?.to_vec())
+                    .map_err(|_| FormatError::InvalidSymbol)?;
+                container.symbols.insert(address, name);
+            }
+        }
+        if flags & HAS_SOURCE_MAP != 0 {
+            for _ in 0..reader.varint()? {
+                let address = unzigzag(reader.varint()?);
+                let line = reader.varint()?;
+                container.source_map.insert(address, line);
+            }
+        }
+        if !reader.bytes.is_empty() {
+            return Err(FormatError::TrailingBytes);
+        }
+        Ok(container)
+    }
+
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+
+    pub fn read<R: io::Read>(reader: &mut R) -> Result<Container, FormatError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Container::from_bytes(&bytes)
+    }
+}
+
+// Reads a container and returns just the program, ready for `Computer::new`.
+pub fn read_program<R: io::Read>(reader: &mut R) -> Result<Vec<i64>, FormatError> {
+    Ok(Container::read(reader)?.program)
+}
+
+pub fn write_program<W: io::Write>(writer: &mut W, program: &[i64]) -> io::Result<()> {
+    Container::new(program).write(writer)
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        bytes.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], FormatError> {
+        if self.bytes.len() < length {
+            return Err(FormatError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64, FormatError> {
+        let mut result = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1 {
+                return Err(FormatError::VarintOverflow);
+            }
+            result |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(FormatError::VarintOverflow)
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    lazy_static! {
+        static ref TABLE: Vec<u32> = (0..256u32)
+            .map(|mut c| {
+                for _ in 0..8 {
+                    c = if c & 1 != 0 {
+                        0xedb8_8320 ^ (c >> 1)
+                    } else {
+                        c >> 1
+                    };
+                }
+                c
+            })
+            .collect();
+    }
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Container, FormatError};
+    use crate::computer::parse_program;
+
+    #[test]
+    fn test_varints() {
+        for &n in &[0, 1, -1, 63, -64, 64, 1 << 40, i64::MAX, i64::MIN] {
+            let mut bytes = Vec::new();
+            super::write_varint(&mut bytes, super::zigzag(n));
+            let mut reader = super::Reader { bytes: &bytes };
+            assert_eq!(super::unzigzag(reader.varint().unwrap()), n);
+        }
+        assert_eq!(super::crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let program = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1125899906842624, 99];
+        let mut bytes = Vec::new();
+        super::write_program(&mut bytes, &program).unwrap();
+        assert_eq!(super::read_program(&mut &bytes[..]).unwrap(), program);
+
+        let mut container = Container::new(&program);
+        container.symbols.insert(0, "main".to_string());
+        container.symbols.insert(100, "counter".to_string());
+        container.source_map.insert(0, 1);
+        container.source_map.insert(4, 3);
+        let bytes = container.to_bytes();
+        assert_eq!(Container::from_bytes(&bytes).unwrap(), container);
+    }
+
+    #[test]
+    fn test_compact() {
+        let text = include_str!("../input/2019/day9.txt");
+        let program = parse_program(text).unwrap();
+        let bytes = Container::new(&program).to_bytes();
+        assert!(bytes.len() < text.len() / 2);
+    }
+
+    #[test]
+    fn test_validation() {
+        let bytes = Container::new(&[1, 2, 3, 99]).to_bytes();
+
+        let mut corrupt = bytes.clone();
+        corrupt[12] ^= 1;
+        match Container::from_bytes(&corrupt) {
+            Err(FormatError::ChecksumMismatch { .. }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut future = bytes.clone();
+        future[4] = 9;
+        match Container::from_bytes(&future) {
+            Err(FormatError::UnsupportedVersion(9)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut flipped = bytes.clone();
+        flipped[5] ^= 0x80;
+        match Container::from_bytes(&flipped) {
+            Err(FormatError::UnknownFlags(0x80)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        match Container::from_bytes(b"1,2,3,99") {
+            Err(FormatError::BadMagic) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match Container::from_bytes(&bytes[..6]) {
+            Err(FormatError::Truncated) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
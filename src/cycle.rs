@@ -0,0 +1,156 @@
+// Detects when a machine and its surroundings are back in a state they were
+// in before. From then on everything repeats with the same period, so a
+// simulation can skip ahead instead of running to the end.
+//
+// The state can be anything hashable. For a machine paired with its
+// environment, such as a robot and the panels it has painted, it is a tuple of
+// the two; `Computer` compares by what affects its future behaviour only.
+
+use crate::computer::{Computer, Fault, Observer};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Recurrence {
+    // Number of states observed before the one that repeats.
+    pub first: usize,
+    pub period: usize,
+}
+
+pub struct CycleDetector<S> {
+    seen: HashMap<S, usize>,
+    observed: usize,
+}
+
+impl<S: Hash + Eq> CycleDetector<S> {
+    pub fn new() -> CycleDetector<S> {
+        CycleDetector {
+            seen: HashMap::new(),
+            observed: 0,
+        }
+    }
+
+    pub fn observed(&self) -> usize {
+        self.observed
+    }
+
+    // Records the next state, or returns the recurrence if it was seen
+    // before. Repeats are measured from the first time a state was seen.
+    pub fn observe(&mut self, state: S) -> Option<Recurrence> {
+        let index = self.observed;
+        self.observed += 1;
+        match self.seen.get(&state) {
+            Some(&first) => Some(Recurrence {
+                first,
+                period: index - first,
+            }),
+            None => {
+                self.seen.insert(state, index);
+                None
+            }
+        }
+    }
+}
+
+impl<S: Hash + Eq> Default for CycleDetector<S> {
+    fn default() -> CycleDetector<S> {
+        CycleDetector::new()
+    }
+}
+
+// Advances a machine and its environment together with `step`, observing
+// their combined state before every step. Stops when the state recurs, when
+// `step` returns false, or after `max_steps` steps.
+pub fn find_recurrence<O, E, F>(
+    computer: &mut Computer<O>,
+    environment: &mut E,
+    mut step: F,
+    max_steps: usize,
+) -> Result<Option<Recurrence>, Fault>
+where
+    O: Observer + Clone,
+    E: Hash + Eq + Clone,
+    F: FnMut(&mut Computer<O>, &mut E) -> Result<bool, Fault>,
+{
+    let mut detector = CycleDetector::new();
+    for _ in 0..max_steps {
+        let state = (computer.clone(), environment.clone());
+        if let Some(recurrence) = detector.observe(state) {
+            return Ok(Some(recurrence));
+        }
+        if !step(computer, environment)? {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CycleDetector, Recurrence};
+    use crate::compiler::compile;
+    use crate::computer::{Computer, Status};
+
+    #[test]
+    fn test_detector() {
+        let mut detector = CycleDetector::new();
+        let sequence = [3, 1, 4, 1, 5, 9, 2, 6, 5];
+        let found: Vec<Option<Recurrence>> =
+            sequence.iter().map(|&n| detector.observe(n)).collect();
+        assert_eq!(
+            found[3],
+            Some(Recurrence {
+                first: 1,
+                period: 2
+            })
+        );
+        assert_eq!(
+            found[8],
+            Some(Recurrence {
+                first: 4,
+                period: 4
+            })
+        );
+        assert_eq!(found.iter().flatten().count(), 2);
+        assert_eq!(detector.observed(), 9);
+    }
+
+    #[test]
+    fn test_machine_and_environment() {
+        // adds its inputs modulo 5 and reports the running total
+        let program = compile(
+            "fn main() {
+                let total = 0;
+                while (1) {
+                    total = total + input();
+                    if (total >= 5) {
+                        total = total - 5;
+                    }
+                    output(total);
+                }
+            }",
+        )
+        .unwrap();
+        // The environment feeds 0, 1, 2, 0, 1, 2, ... and remembers the last
+        // total it was told.
+        let step = |computer: &mut Computer, environment: &mut (i64, i64)| {
+            computer.push_input(environment.0);
+            let status = computer.resume()?;
+            if let Status::Output(total) = status {
+                *environment = ((environment.0 + 1) % 3, total);
+            }
+            Ok(status != Status::Halted)
+        };
+        let mut computer = Computer::new(&program);
+        let mut environment = (0, 0);
+        let recurrence =
+            super::find_recurrence(&mut computer, &mut environment, step, 1000).unwrap();
+        // the totals repeat every 15 steps, once the start-up code is done
+        assert_eq!(recurrence.map(|r| r.period), Some(15));
+
+        let mut computer = Computer::new(&program);
+        let mut environment = (0, 0);
+        let found = super::find_recurrence(&mut computer, &mut environment, step, 10);
+        assert_eq!(found, Ok(None));
+    }
+}
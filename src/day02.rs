@@ -1,3 +1,4 @@
+use crate::symbolic::{Solver, Source, Target};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
@@ -30,20 +31,13 @@ pub fn compute_instructions(input: &[u32]) -> u32 {
 }
 
 #[aoc(day2, part2)]
-pub fn exhaustive_search(input: &[u32]) -> Option<u32> {
-    let mut mut_input = input.to_vec();
-    let desired_result = 19690720;
-    for noun in 0..100 {
-        for verb in 0..100 {
-            mut_input[1] = noun;
-            mut_input[2] = verb;
-            let computed_result = compute_instructions(&mut_input);
-            if computed_result == desired_result {
-                return Some(100 * noun + verb);
-            }
-        }
-    }
-    None
+pub fn find_noun_and_verb(input: &[u32]) -> Option<u32> {
+    let program: Vec<i64> = input.iter().map(|&word| word as i64).collect();
+    let solution = Solver::new(&program)
+        .with_variable(Source::Cell(1), 0..=99)
+        .with_variable(Source::Cell(2), 0..=99)
+        .solve(&Target::Cell(0), 19690720)?;
+    Some(100 * solution.values[0] as u32 + solution.values[1] as u32)
 }
 
 #[derive(FromPrimitive, PartialEq)]
@@ -80,4 +74,14 @@ mod tests {
         let input = vec![1, 1, 1, 4, 99, 5, 6, 0, 99];
         assert_eq!(super::compute_instructions(&input), 30);
     }
+
+    #[test]
+    fn test_find_noun_and_verb() {
+        let input = super::input_generator(include_str!("../input/2019/day2.txt"));
+        let answer = super::find_noun_and_verb(&input).unwrap();
+        let mut program = input.clone();
+        program[1] = answer / 100;
+        program[2] = answer % 100;
+        assert_eq!(super::compute_instructions(&program), 19690720);
+    }
 }
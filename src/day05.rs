@@ -1,27 +1,46 @@
-use crate::computer::Computer;
+use crate::computer::{parse_program, ParseError};
+use crate::engine::{self, IntcodeEngine};
 
 #[aoc_generator(day5)]
-pub fn input_generator(input: &str) -> Vec<i64> {
-    input
-        .trim()
-        .split(',')
-        .map(|s| s.parse::<i64>().unwrap())
-        .collect()
+pub fn input_generator(input: &str) -> Result<Vec<i64>, ParseError> {
+    parse_program(input)
 }
 
 #[aoc(day5, part1)]
 pub fn test_systems(input: &[i64]) -> i64 {
-    let mut computer = Computer::new(&input);
-    let mut output = computer.compute(&vec![1]).unwrap();
-    
-    while let Some(new_output) = computer.compute(&vec![]) {
-        output = new_output;
-    }
-    output
+    let mut engine = engine::boot(input);
+    engine.provide_input(1);
+    // every test reports 0 before the diagnostic code
+    *engine.run_to_halt().unwrap().last().unwrap()
 }
 
 #[aoc(day5, part2)]
 pub fn test_aircon(input: &[i64]) -> i64 {
-    let mut computer = Computer::new(&input);
-    computer.compute(&vec![5]).unwrap()
+    let mut engine = engine::boot(input);
+    engine.provide_input(5);
+    engine.take_output().unwrap().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_systems() {
+        let input = super::input_generator(include_str!("../input/2019/day5.txt")).unwrap();
+        assert_eq!(super::test_systems(&input), 16489636);
+    }
+
+    #[test]
+    fn test_aircon() {
+        // outputs 999 for inputs below 8
+        let input = super::input_generator(
+            "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,
+            1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,
+            999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99",
+        )
+        .unwrap();
+        assert_eq!(super::test_aircon(&input), 999);
+
+        let input = super::input_generator(include_str!("../input/2019/day5.txt")).unwrap();
+        assert_eq!(super::test_aircon(&input), 9386583);
+    }
 }
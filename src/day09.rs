@@ -1,12 +1,9 @@
-use crate::computer::Computer;
+use crate::computer::{parse_program, ParseError};
+use crate::engine::{self, IntcodeEngine};
 
 #[aoc_generator(day9)]
-pub fn input_generator(input: &str) -> Vec<i64> {
-    input
-        .trim()
-        .split(',')
-        .map(|s| s.parse::<i64>().unwrap())
-        .collect()
+pub fn input_generator(input: &str) -> Result<Vec<i64>, ParseError> {
+    parse_program(input)
 }
 
 #[aoc(day9, part1)]
@@ -20,14 +17,9 @@ pub fn distress_signal(input: &[i64]) -> i64 {
 }
 
 pub fn compute(instructions: &[i64], input: &[i64]) -> Vec<i64> {
-    let mut computer = Computer::new(instructions);
-    let mut result = Vec::new();
-    let output = computer.compute(input);
-    result.push(output.unwrap());
-    while let Some(output) = computer.compute(&vec![]) {
-        result.push(output);
-    }
-    result
+    let mut engine = engine::boot(instructions);
+    engine.provide_inputs(input);
+    engine.run_to_halt().unwrap()
 }
 
 #[cfg(test)]
@@ -35,16 +27,23 @@ mod tests {
     #[test]
     fn test_complete_computer() {
         let instructions =
-            super::input_generator("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99");
+            super::input_generator("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99")
+                .unwrap();
         assert_eq!(
             super::compute(&instructions, &vec![]),
             vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99]
         );
 
-        let instructions = super::input_generator("1102,34915192,34915192,7,4,7,99,0");
-        assert_eq!(super::compute(&instructions, &vec![]), vec![1219070632396864]);
+        let instructions = super::input_generator("1102,34915192,34915192,7,4,7,99,0").unwrap();
+        assert_eq!(
+            super::compute(&instructions, &vec![]),
+            vec![1219070632396864]
+        );
 
-        let instructions = super::input_generator("104,1125899906842624,99");
-        assert_eq!(super::compute(&instructions, &vec![]), vec![1125899906842624]);
+        let instructions = super::input_generator("104,1125899906842624,99").unwrap();
+        assert_eq!(
+            super::compute(&instructions, &vec![]),
+            vec![1125899906842624]
+        );
     }
 }
@@ -1,13 +1,10 @@
-use crate::computer::Computer;
+use crate::computer::{parse_program, ParseError};
+use crate::engine::{self, Engine, IntcodeEngine};
 use std::collections::HashSet;
 
 #[aoc_generator(day11)]
-pub fn input_generator(input: &str) -> Vec<i64> {
-    input
-        .trim()
-        .split(',')
-        .map(|s| s.parse::<i64>().unwrap())
-        .collect()
+pub fn input_generator(input: &str) -> Result<Vec<i64>, ParseError> {
+    parse_program(input)
 }
 
 // 2469
@@ -41,7 +38,7 @@ pub fn paint_grid(input: &[i64], initial_paint: i64, grid_size: usize) -> (Vec<V
         x: grid_size / 2,
         y: grid_size / 2,
         direction: Direction::UP,
-        computer: Computer::new(input),
+        computer: engine::boot(input),
     };
     grid[robot.y][robot.x] = initial_paint;
 
@@ -60,13 +57,14 @@ pub struct Robot {
     x: usize,
     y: usize,
     direction: Direction,
-    computer: Computer,
+    computer: Engine,
 }
 
 impl Robot {
     fn paint(&mut self, current_paint: i64) -> Option<i64> {
-        let new_paint = self.computer.compute(&vec![current_paint])?;
-        let turn = self.computer.compute(&vec![])?;
+        self.computer.provide_input(current_paint);
+        let new_paint = self.computer.take_output().unwrap()?;
+        let turn = self.computer.take_output().unwrap()?;
         // can also be implemented as fromPrimitive(self.direction.as_u8() + 1)
         //  but I think the current code is more idiomatic
         self.direction = match turn {
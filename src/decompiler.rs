@@ -0,0 +1,1713 @@
+// Turns an Intcode program back into structured pseudo-code.
+//
+//     fn sub_7(arg0) {
+//         var local0;
+//         local0 = input();
+//         while (local0 > 0) {
+//             output(local0 * arg0);
+//             local0 = local0 - 1;
+//         }
+//         return 0;
+//     }
+//
+// Code is found by following control flow from address 0. A call is the
+// usual idiom of storing a constant return address, possibly moving the
+// relative base, and jumping away unconditionally: the stored constant is the
+// address right after the jump. Every call target becomes a function, and a
+// jump through the cell the return address was stored in is a `return`.
+//
+// Within a function the relative base is tracked as an offset from its value
+// on entry, so relative operands become frame slots. Slots read before they
+// are written are arguments, and the lowest slot that is only ever written is
+// the return value. Address 0 starts with a relative base of 0, so its slots
+// are plain memory cells. If the base moves by an amount that is not a
+// constant, operands are shown as `rb[offset]` from then on.
+//
+// Slots that hold a value for one statement only are folded into the next,
+// and if/else and while loops are recovered from the jump structure. Jumps
+// that do not fit show up as `goto`. The image is decompiled as given, so a
+// program that patches its own code shows the unpatched instructions.
+
+use crate::computer::{Instruction, Parameter};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+// Calls that are followed through before giving up on the relative base
+// deltas of their callees settling down.
+const MAX_PASSES: usize = 8;
+
+// How many instructions before a jump the return address may be stored.
+const CALL_WINDOW: usize = 8;
+
+pub struct Function {
+    pub address: i64,
+    pub name: String,
+    pub params: Vec<String>,
+    pub locals: Vec<String>,
+    body: Vec<Stmt>,
+    names: HashMap<i64, String>,
+}
+
+pub fn decompile(program: &[i64]) -> Vec<Function> {
+    let layouts = discover(program);
+
+    let mut returns = HashMap::new();
+    for layout in layouts.values() {
+        for (&jump, &(callee, store)) in &layout.calls {
+            if let Some(location) = return_location(program, layout, jump, store) {
+                returns.entry(callee).or_insert(location);
+            }
+        }
+    }
+
+    let works: Vec<Work> = layouts
+        .iter()
+        .map(|(&entry, layout)| Work::new(program, entry, layout, returns.get(&entry).copied()))
+        .collect();
+    let signatures: HashMap<i64, (Vec<i64>, Option<i64>)> = works
+        .iter()
+        .map(|work| (work.entry, (work.params.clone(), work.result)))
+        .collect();
+    works
+        .into_iter()
+        .map(|mut work| {
+            work.resolve_calls(&signatures);
+            work.inline_temporaries();
+            work.fold_returns();
+            work.finish()
+        })
+        .collect()
+}
+
+// Decompiles the whole program into one listing.
+pub fn pseudo_code(program: &[i64]) -> String {
+    decompile(program)
+        .iter()
+        .map(Function::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn function_name(address: i64) -> String {
+    if address == 0 {
+        "start".to_string()
+    } else {
+        format!("sub_{}", address)
+    }
+}
+
+fn word(program: &[i64], address: i64) -> i64 {
+    if address >= 0 && address < program.len() as i64 {
+        program[address as usize]
+    } else {
+        0
+    }
+}
+
+struct Decoded {
+    instruction: Instruction,
+    operands: Vec<(Parameter, i64)>,
+}
+
+impl Decoded {
+    fn new(program: &[i64], address: i64) -> Option<Decoded> {
+        let instruction = Instruction::new(word(program, address))?;
+        let operands = instruction
+            .parameters()
+            .into_iter()
+            .enumerate()
+            .map(|(i, parameter)| (parameter, word(program, address + 1 + i as i64)))
+            .collect();
+        Some(Decoded {
+            instruction,
+            operands,
+        })
+    }
+
+    // For jumps: whether the jump is taken, if that is fixed, and the target,
+    // if that is a constant.
+    fn jump(&self) -> Option<(Option<bool>, Option<i64>)> {
+        let on_true = match self.instruction {
+            Instruction::JumpIfTrue(..) => true,
+            Instruction::JumpIfFalse(..) => false,
+            _ => return None,
+        };
+        let taken = match self.operands[0] {
+            (Parameter::Immediate, value) => Some((value != 0) == on_true),
+            _ => None,
+        };
+        let target = match self.operands[1] {
+            (Parameter::Immediate, value) => Some(value),
+            _ => None,
+        };
+        Some((taken, target))
+    }
+
+    // The constant an arithmetic instruction stores, if both operands are
+    // immediate.
+    fn constant(&self) -> Option<i64> {
+        let (a, b) = match self.operands[..] {
+            [(Parameter::Immediate, a), (Parameter::Immediate, b), _] => (a, b),
+            _ => return None,
+        };
+        match self.instruction {
+            Instruction::Addition(..) => Some(a.wrapping_add(b)),
+            Instruction::Multiplication(..) => Some(a.wrapping_mul(b)),
+            _ => None,
+        }
+    }
+}
+
+// The instructions of one function and the relative base delta at each of
+// them, `None` where it is not known.
+struct Layout {
+    deltas: BTreeMap<i64, Option<i64>>,
+    // jump address -> (callee, address of the return address store)
+    calls: BTreeMap<i64, (i64, i64)>,
+}
+
+fn discover(program: &[i64]) -> BTreeMap<i64, Layout> {
+    // A caller resumes with the relative base moved by whatever its callee
+    // leaves it at, which is only known once the callee has been walked.
+    let mut nets = HashMap::new();
+    let mut layouts = BTreeMap::new();
+    for _ in 0..MAX_PASSES {
+        layouts = BTreeMap::new();
+        let mut worklist = vec![0];
+        while let Some(entry) = worklist.pop() {
+            if layouts.contains_key(&entry) {
+                continue;
+            }
+            let layout = walk(program, entry, &nets);
+            worklist.extend(layout.calls.values().map(|&(callee, _)| callee));
+            layouts.insert(entry, layout);
+        }
+        let updated: HashMap<i64, Option<i64>> = layouts
+            .iter()
+            .map(|(&entry, layout)| (entry, net_delta(program, layout)))
+            .collect();
+        if updated == nets {
+            break;
+        }
+        nets = updated;
+    }
+    layouts
+}
+
+fn walk(program: &[i64], entry: i64, nets: &HashMap<i64, Option<i64>>) -> Layout {
+    let mut layout = Layout {
+        deltas: BTreeMap::new(),
+        calls: BTreeMap::new(),
+    };
+    let mut previous = HashMap::new();
+    let mut worklist = vec![(entry, Some(0))];
+
+    while let Some((address, delta)) = worklist.pop() {
+        // Paths that disagree about the relative base make it unknown.
+        let delta = match layout.deltas.get(&address) {
+            Some(&old) if old == delta || old.is_none() => continue,
+            Some(_) => None,
+            None => delta,
+        };
+        layout.deltas.insert(address, delta);
+        let decoded = match Decoded::new(program, address) {
+            Some(decoded) => decoded,
+            None => continue,
+        };
+        let next = address + decoded.instruction.len();
+        match decoded.instruction {
+            Instruction::Stop => {}
+            Instruction::AdjustRelativeBase(_) => {
+                let delta = match decoded.operands[0] {
+                    (Parameter::Immediate, amount) => delta.map(|delta| delta + amount),
+                    _ => None,
+                };
+                previous.insert(next, address);
+                worklist.push((next, delta));
+            }
+            Instruction::JumpIfTrue(..) | Instruction::JumpIfFalse(..) => {
+                let (taken, target) = decoded.jump().unwrap();
+                if let (Some(true), Some(target)) = (taken, target) {
+                    if let Some(store) = find_return_store(program, &previous, address, next) {
+                        layout.calls.insert(address, (target, store));
+                        let net = nets.get(&target).copied().unwrap_or(Some(0));
+                        let delta = match (delta, net) {
+                            (Some(delta), Some(net)) => Some(delta + net),
+                            _ => None,
+                        };
+                        worklist.push((next, delta));
+                        continue;
+                    }
+                }
+                if let (true, Some(target)) = (taken != Some(false), target) {
+                    worklist.push((target, delta));
+                }
+                if taken != Some(true) {
+                    worklist.push((next, delta));
+                }
+            }
+            _ => {
+                previous.insert(next, address);
+                worklist.push((next, delta));
+            }
+        }
+    }
+    layout
+}
+
+// Looks back along the straight-line code before a jump for an instruction
+// that stores the address following the jump.
+fn find_return_store(
+    program: &[i64],
+    previous: &HashMap<i64, i64>,
+    jump: i64,
+    next: i64,
+) -> Option<i64> {
+    let mut address = jump;
+    for _ in 0..CALL_WINDOW {
+        address = *previous.get(&address)?;
+        let decoded = Decoded::new(program, address)?;
+        if decoded.constant() == Some(next) {
+            return Some(address);
+        }
+    }
+    None
+}
+
+// The relative base delta a function returns with, `None` if it differs
+// between returns. Functions that never return count as restoring it.
+fn net_delta(program: &[i64], layout: &Layout) -> Option<i64> {
+    let mut deltas = layout.deltas.iter().filter_map(|(&address, &delta)| {
+        match Decoded::new(program, address)?.jump()? {
+            (taken, None) if taken != Some(false) => Some(delta),
+            _ => None,
+        }
+    });
+    let first = deltas.next().unwrap_or(Some(0));
+    if deltas.all(|delta| delta == first) {
+        first
+    } else {
+        None
+    }
+}
+
+// Where a callee finds its return address, in its own frame.
+fn return_location(program: &[i64], layout: &Layout, jump: i64, store: i64) -> Option<Value> {
+    let decoded = Decoded::new(program, store)?;
+    match decoded.operands[2] {
+        (Parameter::Position, address) => Some(Value::Mem(address)),
+        (Parameter::Relative, offset) => {
+            let at_store = layout.deltas[&store]?;
+            let at_jump = layout.deltas[&jump]?;
+            Some(Value::Slot(offset + at_store - at_jump))
+        }
+        (Parameter::Immediate, _) => None,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Value {
+    Const(i64),
+    Mem(i64),
+    // Offset from the relative base on entry to the function.
+    Slot(i64),
+    // Offset from the current relative base, when that is not known.
+    Rel(i64),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Op {
+    Add,
+    Mul,
+    Less,
+    Equal,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Value(Value),
+    Input,
+    Call(i64, Vec<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    // Only ever wraps a comparison.
+    Not(Box<Expr>),
+}
+
+impl Expr {
+    fn binary(op: Op, left: Expr, right: Expr) -> Expr {
+        use Value::Const;
+        match (op, &left, &right) {
+            (_, Expr::Value(Const(a)), Expr::Value(Const(b))) => {
+                let (a, b) = (*a, *b);
+                Expr::Value(Const(match op {
+                    Op::Add => a.wrapping_add(b),
+                    Op::Mul => a.wrapping_mul(b),
+                    Op::Less => (a < b) as i64,
+                    Op::Equal => (a == b) as i64,
+                }))
+            }
+            // Constants go on the right of sums and products.
+            (Op::Add | Op::Mul, Expr::Value(Const(_)), _) => Expr::binary(op, right, left),
+            (Op::Add, _, Expr::Value(Const(0))) | (Op::Mul, _, Expr::Value(Const(1))) => left,
+            (Op::Equal, _, Expr::Value(Const(0))) if left.is_boolean() => left.negate(),
+            (Op::Equal, Expr::Value(Const(0)), _) if right.is_boolean() => right.negate(),
+            (Op::Equal, _, Expr::Value(Const(1))) if left.is_boolean() => left,
+            (Op::Equal, Expr::Value(Const(1)), _) if right.is_boolean() => right,
+            _ => Expr::Binary(op, Box::new(left), Box::new(right)),
+        }
+    }
+
+    fn is_boolean(&self) -> bool {
+        matches!(
+            self,
+            Expr::Binary(Op::Less, ..) | Expr::Binary(Op::Equal, ..) | Expr::Not(_)
+        )
+    }
+
+    fn negate(self) -> Expr {
+        match self {
+            Expr::Not(inner) => *inner,
+            Expr::Value(Value::Const(value)) => Expr::Value(Value::Const((value == 0) as i64)),
+            _ if self.is_boolean() => Expr::Not(Box::new(self)),
+            _ => Expr::Binary(
+                Op::Equal,
+                Box::new(self),
+                Box::new(Expr::Value(Value::Const(0))),
+            ),
+        }
+    }
+
+    fn reads(&self, out: &mut Vec<Value>) {
+        match self {
+            Expr::Value(Value::Const(_)) | Expr::Input => {}
+            Expr::Value(value) => out.push(*value),
+            Expr::Call(_, args) => args.iter().for_each(|arg| arg.reads(out)),
+            Expr::Binary(_, left, right) => {
+                left.reads(out);
+                right.reads(out);
+            }
+            Expr::Not(inner) => inner.reads(out),
+        }
+    }
+
+    // Number of inputs and calls, which must not be reordered.
+    fn effects(&self) -> usize {
+        match self {
+            Expr::Value(_) => 0,
+            Expr::Input => 1,
+            Expr::Call(_, args) => 1 + args.iter().map(Expr::effects).sum::<usize>(),
+            Expr::Binary(_, left, right) => left.effects() + right.effects(),
+            Expr::Not(inner) => inner.effects(),
+        }
+    }
+
+    // Whether `value` is read before the first input or call happens, if it
+    // is read at all.
+    fn read_first(&self, value: Value) -> Option<bool> {
+        match self {
+            Expr::Value(v) if *v == value => Some(true),
+            Expr::Value(_) => None,
+            Expr::Input => Some(false),
+            Expr::Call(_, args) => args
+                .iter()
+                .find_map(|arg| arg.read_first(value))
+                .or(Some(false)),
+            Expr::Binary(_, left, right) => {
+                left.read_first(value).or_else(|| right.read_first(value))
+            }
+            Expr::Not(inner) => inner.read_first(value),
+        }
+    }
+
+    fn substitute(self, value: Value, with: &Expr) -> Expr {
+        match self {
+            Expr::Value(v) if v == value => with.clone(),
+            Expr::Call(callee, args) => Expr::Call(
+                callee,
+                args.into_iter()
+                    .map(|arg| arg.substitute(value, with))
+                    .collect(),
+            ),
+            Expr::Binary(op, left, right) => Expr::binary(
+                op,
+                left.substitute(value, with),
+                right.substitute(value, with),
+            ),
+            Expr::Not(inner) => inner.substitute(value, with).negate(),
+            other => other,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Leave {
+    Return(Option<Expr>),
+    Halt,
+    Indirect(Expr),
+    Invalid(i64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Stmt {
+    Assign(Value, Expr),
+    Eval(Expr),
+    Output(Expr),
+    AdjustBase(Expr),
+    // A call before its arguments are known, with the relative base delta
+    // of the callee's frame.
+    Call(i64, Option<i64>),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    Loop(Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Break,
+    Continue,
+    Leave(Leave),
+    Goto(i64),
+    Label(i64),
+}
+
+impl Stmt {
+    fn exprs(&self) -> Vec<&Expr> {
+        match self {
+            Stmt::Assign(_, expr) | Stmt::Eval(expr) | Stmt::Output(expr) => vec![expr],
+            Stmt::AdjustBase(expr) => vec![expr],
+            _ => vec![],
+        }
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Stmt::Assign(_, expr) | Stmt::Eval(expr) | Stmt::Output(expr) => vec![expr],
+            Stmt::AdjustBase(expr) => vec![expr],
+            _ => vec![],
+        }
+    }
+
+    fn target(&self) -> Option<Value> {
+        match self {
+            Stmt::Assign(target, _) => Some(*target),
+            _ => None,
+        }
+    }
+
+    fn call_args(&mut self) -> Option<&mut Vec<Expr>> {
+        match self {
+            Stmt::Assign(_, Expr::Call(_, args)) | Stmt::Eval(Expr::Call(_, args)) => Some(args),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Exit {
+    Goto(i64),
+    // Taken when the condition holds.
+    Branch(Expr, i64, i64),
+    Leave(Leave),
+    CondLeave(Expr, Leave, i64),
+}
+
+impl Exit {
+    fn successors(&self) -> Vec<i64> {
+        match self {
+            Exit::Goto(target) => vec![*target],
+            Exit::Branch(_, taken, other) => vec![*taken, *other],
+            Exit::Leave(_) => vec![],
+            Exit::CondLeave(_, _, next) => vec![*next],
+        }
+    }
+
+    fn leave(&self) -> Option<&Leave> {
+        match self {
+            Exit::Leave(leave) | Exit::CondLeave(_, leave, _) => Some(leave),
+            _ => None,
+        }
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut Expr> {
+        let (condition, leave) = match self {
+            Exit::Goto(_) => return vec![],
+            Exit::Branch(condition, ..) => return vec![condition],
+            Exit::Leave(leave) => (None, leave),
+            Exit::CondLeave(condition, leave, _) => (Some(condition), leave),
+        };
+        let mut exprs: Vec<&mut Expr> = condition.into_iter().collect();
+        match leave {
+            Leave::Return(Some(expr)) | Leave::Indirect(expr) => exprs.push(expr),
+            _ => {}
+        }
+        exprs
+    }
+
+    fn reads(&self) -> Vec<Value> {
+        let mut reads = Vec::new();
+        for expr in self.clone().exprs_mut() {
+            expr.reads(&mut reads);
+        }
+        reads
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Block {
+    stmts: Vec<Stmt>,
+    exit: Exit,
+}
+
+fn stmt_reads(stmt: &Stmt) -> Vec<Value> {
+    let mut reads = Vec::new();
+    for expr in stmt.exprs() {
+        expr.reads(&mut reads);
+    }
+    reads
+}
+
+fn slots(values: Vec<Value>) -> impl Iterator<Item = i64> {
+    values.into_iter().filter_map(|value| match value {
+        Value::Slot(offset) => Some(offset),
+        _ => None,
+    })
+}
+
+// Slots that are written on every path to a point. `floor` stands for every
+// slot from there up, which a call may have used for the callee's frame.
+#[derive(Clone, PartialEq)]
+struct Assigned {
+    slots: BTreeSet<i64>,
+    floor: Option<i64>,
+}
+
+impl Assigned {
+    fn contains(&self, slot: i64) -> bool {
+        self.slots.contains(&slot) || self.floor.is_some_and(|floor| slot >= floor)
+    }
+
+    fn meet(&self, other: &Assigned) -> Assigned {
+        let floor = match (self.floor, other.floor) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None,
+        };
+        let slots = self
+            .slots
+            .union(&other.slots)
+            .copied()
+            .filter(|&slot| self.contains(slot) && other.contains(slot))
+            .collect();
+        Assigned { slots, floor }
+    }
+
+    fn apply(&mut self, stmt: &Stmt) {
+        match *stmt {
+            Stmt::Assign(Value::Slot(offset), _) => {
+                self.slots.insert(offset);
+            }
+            Stmt::Call(_, Some(frame)) => {
+                self.floor = Some(self.floor.map_or(frame, |floor| floor.min(frame)));
+            }
+            _ => {}
+        }
+    }
+}
+
+// A function on its way from instructions to pseudo-code.
+struct Work {
+    entry: i64,
+    blocks: BTreeMap<i64, Block>,
+    ret: Option<Value>,
+    params: Vec<i64>,
+    result: Option<i64>,
+}
+
+enum Step {
+    Next(Option<Stmt>),
+    Exit(Exit),
+}
+
+impl Work {
+    fn new(program: &[i64], entry: i64, layout: &Layout, ret: Option<Value>) -> Work {
+        let stores: HashSet<i64> = layout.calls.values().map(|&(_, store)| store).collect();
+        let mut leaders = BTreeSet::new();
+        leaders.insert(entry);
+        for &address in layout.deltas.keys() {
+            if layout.calls.contains_key(&address) {
+                continue;
+            }
+            let decoded = match Decoded::new(program, address) {
+                Some(decoded) => decoded,
+                None => continue,
+            };
+            if let Some((taken, target)) = decoded.jump() {
+                if let (true, Some(target)) = (taken != Some(false), target) {
+                    leaders.insert(target);
+                }
+                if taken.is_none() {
+                    leaders.insert(address + 3);
+                }
+            }
+        }
+
+        let mut work = Work {
+            entry,
+            blocks: BTreeMap::new(),
+            ret,
+            params: Vec::new(),
+            result: None,
+        };
+        for &leader in &leaders {
+            let mut stmts = Vec::new();
+            let mut address = leader;
+            let exit = loop {
+                if address != leader && leaders.contains(&address) {
+                    break Exit::Goto(address);
+                }
+                let decoded = match Decoded::new(program, address) {
+                    Some(decoded) => decoded,
+                    None => break Exit::Leave(Leave::Invalid(word(program, address))),
+                };
+                let delta = layout.deltas.get(&address).copied().flatten();
+                let step = if stores.contains(&address) {
+                    Step::Next(None)
+                } else if let Some(&(callee, _)) = layout.calls.get(&address) {
+                    Step::Next(Some(Stmt::Call(callee, delta)))
+                } else {
+                    work.convert(address, delta, &decoded)
+                };
+                match step {
+                    Step::Next(stmt) => stmts.extend(stmt),
+                    Step::Exit(exit) => break exit,
+                }
+                address += decoded.instruction.len();
+            };
+            work.blocks.insert(leader, Block { stmts, exit });
+        }
+        if entry != 0 {
+            work.find_signature();
+        }
+        work
+    }
+
+    fn convert(&self, address: i64, delta: Option<i64>, decoded: &Decoded) -> Step {
+        let value = |i: usize| match decoded.operands[i] {
+            (Parameter::Immediate, constant)
+                if decoded.instruction.write_parameter() != Some(i) =>
+            {
+                Value::Const(constant)
+            }
+            // Writing through an immediate parameter overwrites the
+            // parameter itself.
+            (Parameter::Immediate, _) => Value::Mem(address + 1 + i as i64),
+            (Parameter::Position, address) => Value::Mem(address),
+            (Parameter::Relative, offset) => match delta {
+                Some(delta) if self.entry == 0 => Value::Mem(delta + offset),
+                Some(delta) => Value::Slot(delta + offset),
+                None => Value::Rel(offset),
+            },
+        };
+        let operand = |i: usize| Expr::Value(value(i));
+        let binary = |op| {
+            Step::Next(Some(Stmt::Assign(
+                value(2),
+                Expr::binary(op, operand(0), operand(1)),
+            )))
+        };
+        match decoded.instruction {
+            Instruction::Addition(..) => binary(Op::Add),
+            Instruction::Multiplication(..) => binary(Op::Mul),
+            Instruction::LessThan(..) => binary(Op::Less),
+            Instruction::Equals(..) => binary(Op::Equal),
+            Instruction::Input(_) => Step::Next(Some(Stmt::Assign(value(0), Expr::Input))),
+            Instruction::Output(_) => Step::Next(Some(Stmt::Output(operand(0)))),
+            Instruction::AdjustRelativeBase(parameter) => {
+                if parameter == Parameter::Immediate && delta.is_some() {
+                    Step::Next(None)
+                } else {
+                    Step::Next(Some(Stmt::AdjustBase(operand(0))))
+                }
+            }
+            Instruction::JumpIfTrue(..) | Instruction::JumpIfFalse(..) => {
+                let (taken, target) = decoded.jump().unwrap();
+                let condition = match decoded.instruction {
+                    Instruction::JumpIfTrue(..) => operand(0),
+                    _ => operand(0).negate(),
+                };
+                let leave = || {
+                    let target = value(1);
+                    if Some(target) == self.ret {
+                        Leave::Return(None)
+                    } else {
+                        Leave::Indirect(Expr::Value(target))
+                    }
+                };
+                let next = address + 3;
+                Step::Exit(match (taken, target) {
+                    (Some(false), _) => return Step::Next(None),
+                    (Some(true), Some(target)) => Exit::Goto(target),
+                    (Some(true), None) => Exit::Leave(leave()),
+                    (None, Some(target)) => Exit::Branch(condition, target, next),
+                    (None, None) => Exit::CondLeave(condition, leave(), next),
+                })
+            }
+            Instruction::Stop => Step::Exit(Exit::Leave(Leave::Halt)),
+        }
+    }
+
+    // Arguments are slots read before they are written; the return value is
+    // the lowest slot that is only written.
+    fn find_signature(&mut self) {
+        let mut states: HashMap<i64, Assigned> = HashMap::new();
+        states.insert(
+            self.entry,
+            Assigned {
+                slots: BTreeSet::new(),
+                floor: None,
+            },
+        );
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (address, block) in &self.blocks {
+                let mut state = match states.get(address) {
+                    Some(state) => state.clone(),
+                    None => continue,
+                };
+                block.stmts.iter().for_each(|stmt| state.apply(stmt));
+                for successor in block.exit.successors() {
+                    let merged = match states.get(&successor) {
+                        Some(old) => old.meet(&state),
+                        None => state.clone(),
+                    };
+                    if states.get(&successor) != Some(&merged) {
+                        states.insert(successor, merged);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let ret = match self.ret {
+            Some(Value::Slot(offset)) => Some(offset),
+            _ => None,
+        };
+        let mut params = BTreeSet::new();
+        let mut read = BTreeSet::new();
+        let mut written = BTreeSet::new();
+        for (address, block) in &self.blocks {
+            let mut state = states.get(address).cloned();
+            for stmt in &block.stmts {
+                for slot in slots(stmt_reads(stmt)) {
+                    read.insert(slot);
+                    if state.as_ref().is_some_and(|state| !state.contains(slot)) {
+                        params.insert(slot);
+                    }
+                }
+                written.extend(stmt.target().into_iter().flat_map(|t| slots(vec![t])));
+                if let Some(state) = &mut state {
+                    state.apply(stmt);
+                }
+            }
+            for slot in slots(block.exit.reads()) {
+                read.insert(slot);
+                if state.as_ref().is_some_and(|state| !state.contains(slot)) {
+                    params.insert(slot);
+                }
+            }
+        }
+        params.remove(&ret.unwrap_or(i64::MIN));
+        self.params = params.into_iter().collect();
+
+        let lowest = read
+            .union(&written)
+            .copied()
+            .filter(|&slot| Some(slot) != ret)
+            .min();
+        self.result = lowest.filter(|slot| !read.contains(slot));
+    }
+
+    fn resolve_calls(&mut self, signatures: &HashMap<i64, (Vec<i64>, Option<i64>)>) {
+        let absolute = self.entry == 0;
+        let at = |frame: Option<i64>, offset: i64| match frame {
+            Some(frame) if absolute => Value::Mem(frame + offset),
+            Some(frame) => Value::Slot(frame + offset),
+            None => Value::Rel(offset),
+        };
+        for block in self.blocks.values_mut() {
+            let mut stmts = Vec::new();
+            let mut pending = std::mem::take(&mut block.stmts).into_iter().peekable();
+            while let Some(stmt) = pending.next() {
+                let (callee, frame) = match stmt {
+                    Stmt::Call(callee, frame) => (callee, frame),
+                    stmt => {
+                        stmts.push(stmt);
+                        continue;
+                    }
+                };
+                let (params, result) = &signatures[&callee];
+                let args = params
+                    .iter()
+                    .map(|&offset| Expr::Value(at(frame, offset)))
+                    .collect();
+                let call = Expr::Call(callee, args);
+                let result = result.map(|offset| Expr::Value(at(frame, offset)));
+                match pending.peek() {
+                    Some(Stmt::Assign(_, value)) if Some(value) == result.as_ref() => {
+                        let target = pending.next().unwrap().target().unwrap();
+                        stmts.push(Stmt::Assign(target, call));
+                    }
+                    _ => stmts.push(Stmt::Eval(call)),
+                }
+            }
+            block.stmts = stmts;
+        }
+        self.fold_arguments();
+    }
+
+    // Moves the expression stored into an argument cell into the call,
+    // when nothing else reads that cell.
+    fn fold_arguments(&mut self) {
+        let mut counts: HashMap<Value, usize> = HashMap::new();
+        for block in self.blocks.values() {
+            let reads = block.stmts.iter().flat_map(stmt_reads);
+            for value in reads.chain(block.exit.reads()) {
+                *counts.entry(value).or_insert(0) += 1;
+            }
+        }
+        for block in self.blocks.values_mut() {
+            let stmts = &mut block.stmts;
+            let mut i = 0;
+            while i < stmts.len() {
+                let args = match stmts[i].call_args() {
+                    Some(args) => args.clone(),
+                    None => {
+                        i += 1;
+                        continue;
+                    }
+                };
+                let mut folded = Vec::with_capacity(args.len());
+                for arg in args {
+                    let value = match arg {
+                        Expr::Value(value @ (Value::Slot(_) | Value::Mem(_))) => value,
+                        arg => {
+                            folded.push(arg);
+                            continue;
+                        }
+                    };
+                    match find_store(&stmts[..i], value) {
+                        Some(j) if counts.get(&value) == Some(&1) => {
+                            if let Stmt::Assign(_, expr) = stmts.remove(j) {
+                                folded.push(expr);
+                            }
+                            i -= 1;
+                        }
+                        _ => folded.push(Expr::Value(value)),
+                    }
+                }
+                *stmts[i].call_args().unwrap() = folded;
+                i += 1;
+            }
+        }
+    }
+
+    fn all_slots(&self) -> HashSet<i64> {
+        let mut all = HashSet::new();
+        for block in self.blocks.values() {
+            for stmt in &block.stmts {
+                all.extend(slots(stmt_reads(stmt)));
+                all.extend(stmt.target().into_iter().flat_map(|t| slots(vec![t])));
+            }
+            all.extend(slots(block.exit.reads()));
+        }
+        all
+    }
+
+    // Slots that may still be read after a block's exit.
+    fn live_out(&self, all: &HashSet<i64>) -> HashMap<i64, HashSet<i64>> {
+        let on_leave = |leave: &Leave| -> HashSet<i64> {
+            match leave {
+                Leave::Return(_) => self.result.into_iter().collect(),
+                Leave::Halt => HashSet::new(),
+                Leave::Indirect(_) | Leave::Invalid(_) => all.clone(),
+            }
+        };
+        let mut live_in: HashMap<i64, HashSet<i64>> = HashMap::new();
+        let mut live_out: HashMap<i64, HashSet<i64>> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (address, block) in self.blocks.iter().rev() {
+                let mut live: HashSet<i64> = block.exit.leave().map(on_leave).unwrap_or_default();
+                for successor in block.exit.successors() {
+                    live.extend(live_in.get(&successor).into_iter().flatten());
+                }
+                live_out.insert(*address, live.clone());
+                live.extend(slots(block.exit.reads()));
+                for stmt in block.stmts.iter().rev() {
+                    transfer(stmt, &mut live);
+                }
+                if live_in.get(address) != Some(&live) {
+                    live_in.insert(*address, live);
+                    changed = true;
+                }
+            }
+        }
+        live_out
+    }
+
+    // Folds a slot that is written and then read exactly once by the very
+    // next statement into that statement.
+    fn inline_temporaries(&mut self) {
+        let all = self.all_slots();
+        let live_out = self.live_out(&all);
+        for (address, block) in self.blocks.iter_mut() {
+            let end = &live_out[address];
+            while let Some(i) = inlinable(block, end) {
+                if let Stmt::Assign(Value::Slot(slot), expr) = block.stmts.remove(i) {
+                    let exprs = match block.stmts.get_mut(i) {
+                        Some(next) => next.exprs_mut(),
+                        None => block.exit.exprs_mut(),
+                    };
+                    for target in exprs {
+                        *target = target.clone().substitute(Value::Slot(slot), &expr);
+                    }
+                }
+            }
+        }
+    }
+
+    fn fold_returns(&mut self) {
+        let result = match self.result {
+            Some(result) => Value::Slot(result),
+            None => return,
+        };
+        for block in self.blocks.values_mut() {
+            if let Exit::Leave(Leave::Return(value @ None)) = &mut block.exit {
+                if let Some(Stmt::Assign(target, _)) = block.stmts.last() {
+                    if *target == result {
+                        if let Some(Stmt::Assign(_, expr)) = block.stmts.pop() {
+                            *value = Some(expr);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Function {
+        let mut structurer = Structurer::new(&self.blocks, self.entry);
+        let body = structurer.region(Some(self.entry), None, None);
+        let labels = structurer.gotos;
+        let body = simplify(remove_labels(body, &labels));
+
+        let mut used = BTreeSet::new();
+        collect_slots(&body, &mut used);
+        let mut names = HashMap::new();
+        let mut params = Vec::new();
+        for (i, &offset) in self.params.iter().enumerate() {
+            names.insert(offset, format!("arg{}", i));
+            params.push(format!("arg{}", i));
+        }
+        if let Some(Value::Slot(offset)) = self.ret {
+            names.insert(offset, "return_address".to_string());
+        }
+        if let Some(offset) = self.result {
+            names.insert(offset, "result".to_string());
+        }
+        let mut locals = Vec::new();
+        for offset in used {
+            names.entry(offset).or_insert_with(|| {
+                let name = format!("local{}", locals.len());
+                locals.push(name.clone());
+                name
+            });
+        }
+        Function {
+            address: self.entry,
+            name: function_name(self.entry),
+            params,
+            locals,
+            body,
+            names,
+        }
+    }
+}
+
+fn transfer(stmt: &Stmt, live: &mut HashSet<i64>) {
+    if let Some(Value::Slot(slot)) = stmt.target() {
+        live.remove(&slot);
+    }
+    live.extend(slots(stmt_reads(stmt)));
+}
+
+// The last store into `value` before the end of `stmts`, if nothing in
+// between would notice it moving there.
+fn find_store(stmts: &[Stmt], value: Value) -> Option<usize> {
+    for j in (0..stmts.len()).rev() {
+        if let Stmt::Assign(target, expr) = &stmts[j] {
+            if *target == value {
+                let mut reads = Vec::new();
+                expr.reads(&mut reads);
+                let undisturbed = stmts[j + 1..].iter().all(|stmt| {
+                    stmt.target().is_none_or(|target| !reads.contains(&target))
+                        && (expr.effects() == 0
+                            || stmt.exprs().iter().all(|expr| expr.effects() == 0))
+                });
+                return Some(j).filter(|_| undisturbed);
+            }
+        }
+        if stmt_reads(&stmts[j]).contains(&value) || matches!(stmts[j], Stmt::AdjustBase(_)) {
+            return None;
+        }
+    }
+    None
+}
+
+fn inlinable(block: &Block, end: &HashSet<i64>) -> Option<usize> {
+    let mut live = end.clone();
+    let mut after = vec![HashSet::new(); block.stmts.len()];
+    for (i, stmt) in block.stmts.iter().enumerate().rev() {
+        after[i] = live.clone();
+        transfer(stmt, &mut live);
+    }
+    let mut exit = block.exit.clone();
+    let exit_exprs: Vec<Expr> = exit.exprs_mut().into_iter().map(|e| e.clone()).collect();
+    for i in 0..block.stmts.len() {
+        let (slot, expr) = match &block.stmts[i] {
+            Stmt::Assign(Value::Slot(slot), expr) => (*slot, expr),
+            _ => continue,
+        };
+        let (next_exprs, dead) = match block.stmts.get(i + 1) {
+            Some(next) => (
+                next.exprs().into_iter().cloned().collect::<Vec<_>>(),
+                !after[i + 1].contains(&slot) || next.target() == Some(Value::Slot(slot)),
+            ),
+            None => (exit_exprs.clone(), !end.contains(&slot)),
+        };
+        let mut reads = Vec::new();
+        next_exprs.iter().for_each(|e| e.reads(&mut reads));
+        let uses = reads.iter().filter(|&&v| v == Value::Slot(slot)).count();
+        let first = next_exprs
+            .iter()
+            .find_map(|e| e.read_first(Value::Slot(slot)));
+        if uses == 1 && dead && (expr.effects() == 0 || first == Some(true)) {
+            return Some(i);
+        }
+    }
+    None
+}
+
+struct Loop {
+    body: HashSet<i64>,
+    follow: Option<i64>,
+}
+
+// Rebuilds if/else and loops from a function's control flow graph.
+struct Structurer<'a> {
+    blocks: &'a BTreeMap<i64, Block>,
+    ipdom: HashMap<i64, i64>,
+    loops: HashMap<i64, Loop>,
+    emitted: HashSet<i64>,
+    gotos: BTreeSet<i64>,
+}
+
+impl<'a> Structurer<'a> {
+    fn new(blocks: &'a BTreeMap<i64, Block>, entry: i64) -> Structurer<'a> {
+        let mut structurer = Structurer {
+            blocks,
+            ipdom: post_dominators(blocks),
+            loops: HashMap::new(),
+            emitted: HashSet::new(),
+            gotos: BTreeSet::new(),
+        };
+        structurer.find_loops(entry);
+        structurer
+    }
+
+    fn successors(&self, node: i64) -> Vec<i64> {
+        self.blocks[&node].exit.successors()
+    }
+
+    fn find_loops(&mut self, entry: i64) {
+        // Depth-first search for back edges, then the natural loop of each.
+        let mut back_edges: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+        let mut on_stack = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![(entry, 0)];
+        visited.insert(entry);
+        on_stack.insert(entry);
+        while let Some((node, index)) = stack.pop() {
+            let successors = self.successors(node);
+            if index == successors.len() {
+                on_stack.remove(&node);
+                continue;
+            }
+            stack.push((node, index + 1));
+            let successor = successors[index];
+            if on_stack.contains(&successor) {
+                back_edges.entry(successor).or_default().push(node);
+            } else if visited.insert(successor) {
+                on_stack.insert(successor);
+                stack.push((successor, 0));
+            }
+        }
+
+        let mut predecessors: HashMap<i64, Vec<i64>> = HashMap::new();
+        for &node in self.blocks.keys() {
+            for successor in self.successors(node) {
+                predecessors.entry(successor).or_default().push(node);
+            }
+        }
+        for (header, sources) in back_edges {
+            let mut body = HashSet::new();
+            body.insert(header);
+            let mut worklist = sources;
+            while let Some(node) = worklist.pop() {
+                if body.insert(node) {
+                    worklist.extend(predecessors.get(&node).into_iter().flatten());
+                }
+            }
+            let follow = match self.ipdom.get(&header) {
+                Some(follow) if !body.contains(follow) => Some(*follow),
+                _ => body
+                    .iter()
+                    .flat_map(|&node| self.successors(node))
+                    .filter(|node| !body.contains(node))
+                    .min(),
+            };
+            self.loops.insert(header, Loop { body, follow });
+        }
+    }
+
+    fn goto(&mut self, node: i64) -> Stmt {
+        self.gotos.insert(node);
+        Stmt::Goto(node)
+    }
+
+    fn region(&mut self, mut at: Option<i64>, stop: Option<i64>, header: Option<i64>) -> Vec<Stmt> {
+        let mut out = Vec::new();
+        while let Some(node) = at {
+            if Some(node) == stop {
+                break;
+            }
+            if let Some(header) = header {
+                let current = &self.loops[&header];
+                if node == header {
+                    out.push(Stmt::Continue);
+                    break;
+                }
+                if Some(node) == current.follow {
+                    out.push(Stmt::Break);
+                    break;
+                }
+                if !current.body.contains(&node) {
+                    out.push(self.goto(node));
+                    break;
+                }
+            }
+            if !self.emitted.insert(node) {
+                out.push(self.goto(node));
+                break;
+            }
+            out.push(Stmt::Label(node));
+            if self.loops.contains_key(&node) {
+                let mut body = Vec::new();
+                let next = self.block(node, None, Some(node), &mut body);
+                body.extend(self.region(next, None, Some(node)));
+                out.push(Stmt::Loop(body));
+                at = self.loops[&node].follow;
+            } else {
+                at = self.block(node, stop, header, &mut out);
+            }
+        }
+        out
+    }
+
+    fn block(
+        &mut self,
+        node: i64,
+        stop: Option<i64>,
+        header: Option<i64>,
+        out: &mut Vec<Stmt>,
+    ) -> Option<i64> {
+        let block = &self.blocks[&node];
+        out.extend(block.stmts.iter().cloned());
+        match block.exit.clone() {
+            Exit::Goto(target) => Some(target),
+            Exit::Leave(leave) => {
+                out.push(Stmt::Leave(leave));
+                None
+            }
+            Exit::CondLeave(condition, leave, next) => {
+                out.push(Stmt::If(condition, vec![Stmt::Leave(leave)], vec![]));
+                Some(next)
+            }
+            Exit::Branch(condition, taken, other) => {
+                // Branches only merge again inside the innermost loop.
+                let follow = self.ipdom.get(&node).copied().filter(|follow| {
+                    header.is_none_or(|header| self.loops[&header].body.contains(follow))
+                });
+                // The code that falls through reads as the `then` branch.
+                let inner = follow.or(stop);
+                let then = self.region(Some(other), inner, header);
+                let otherwise = self.region(Some(taken), inner, header);
+                out.push(Stmt::If(condition.negate(), then, otherwise));
+                follow
+            }
+        }
+    }
+}
+
+// Immediate post-dominators. Nodes that cannot reach an exit are treated as
+// exits themselves, so branches inside endless loops never merge.
+fn post_dominators(blocks: &BTreeMap<i64, Block>) -> HashMap<i64, i64> {
+    let nodes: Vec<i64> = blocks.keys().copied().collect();
+    let all: BTreeSet<i64> = nodes.iter().copied().collect();
+    let mut reaches_exit = HashSet::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (&node, block) in blocks {
+            let successors = block.exit.successors();
+            if !reaches_exit.contains(&node)
+                && (block.exit.leave().is_some()
+                    || successors.iter().any(|s| reaches_exit.contains(s)))
+            {
+                reaches_exit.insert(node);
+                changed = true;
+            }
+        }
+    }
+
+    let mut sets: HashMap<i64, BTreeSet<i64>> =
+        nodes.iter().map(|&node| (node, all.clone())).collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &node in nodes.iter().rev() {
+            let mut set = if reaches_exit.contains(&node) {
+                let exit = &blocks[&node].exit;
+                let mut successors = exit.successors().into_iter();
+                match (exit.leave(), successors.next()) {
+                    (Some(_), _) | (None, None) => BTreeSet::new(),
+                    (None, Some(first)) => successors.fold(sets[&first].clone(), |set, s| {
+                        set.intersection(&sets[&s]).copied().collect()
+                    }),
+                }
+            } else {
+                BTreeSet::new()
+            };
+            set.insert(node);
+            if sets[&node] != set {
+                sets.insert(node, set);
+                changed = true;
+            }
+        }
+    }
+
+    let mut ipdom = HashMap::new();
+    for (&node, set) in &sets {
+        // The closest strict post-dominator is the one with the most
+        // post-dominators of its own.
+        if let Some(&closest) = set
+            .iter()
+            .filter(|&&other| other != node)
+            .max_by_key(|&&other| sets[&other].len())
+        {
+            ipdom.insert(node, closest);
+        }
+    }
+    ipdom
+}
+
+fn remove_labels(stmts: Vec<Stmt>, keep: &BTreeSet<i64>) -> Vec<Stmt> {
+    stmts
+        .into_iter()
+        .filter(|stmt| !matches!(stmt, Stmt::Label(label) if !keep.contains(label)))
+        .map(|stmt| match stmt {
+            Stmt::If(condition, then, otherwise) => Stmt::If(
+                condition,
+                remove_labels(then, keep),
+                remove_labels(otherwise, keep),
+            ),
+            Stmt::Loop(body) => Stmt::Loop(remove_labels(body, keep)),
+            stmt => stmt,
+        })
+        .collect()
+}
+
+fn is_jump(stmt: Option<&Stmt>) -> bool {
+    matches!(
+        stmt,
+        Some(Stmt::Break | Stmt::Continue | Stmt::Leave(_) | Stmt::Goto(_))
+    )
+}
+
+fn size(stmts: &[Stmt]) -> usize {
+    stmts
+        .iter()
+        .map(|stmt| match stmt {
+            Stmt::If(_, then, otherwise) => 1 + size(then) + size(otherwise),
+            Stmt::Loop(body) | Stmt::While(_, body) => 1 + size(body),
+            _ => 1,
+        })
+        .sum()
+}
+
+fn simplify(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    let mut out = Vec::new();
+    for stmt in stmts {
+        match stmt {
+            Stmt::If(condition, then, otherwise) => {
+                let (then, otherwise) = (simplify(then), simplify(otherwise));
+                if then.is_empty() && otherwise.is_empty() {
+                    if condition.effects() > 0 {
+                        out.push(Stmt::Eval(condition));
+                    }
+                    continue;
+                }
+                // Put the branch that jumps away first, so the other one can
+                // follow the `if`.
+                let swap = then.is_empty()
+                    || match (is_jump(then.last()), is_jump(otherwise.last())) {
+                        (false, true) => true,
+                        (true, true) => size(&otherwise) < size(&then),
+                        _ => false,
+                    };
+                let (condition, then, otherwise) = if swap {
+                    (condition.negate(), otherwise, then)
+                } else {
+                    (condition, then, otherwise)
+                };
+                if is_jump(then.last()) {
+                    out.push(Stmt::If(condition, then, vec![]));
+                    out.extend(otherwise);
+                } else {
+                    out.push(Stmt::If(condition, then, otherwise));
+                }
+            }
+            Stmt::Loop(body) => {
+                let mut body = simplify(body);
+                while body.last() == Some(&Stmt::Continue) {
+                    body.pop();
+                }
+                match body.first() {
+                    Some(Stmt::If(condition, then, otherwise))
+                        if then[..] == [Stmt::Break] && otherwise.is_empty() =>
+                    {
+                        let condition = condition.clone().negate();
+                        out.push(Stmt::While(condition, body.split_off(1)));
+                    }
+                    _ => out.push(Stmt::Loop(body)),
+                }
+            }
+            Stmt::Assign(target, Expr::Value(value)) if target == value => {}
+            stmt => out.push(stmt),
+        }
+    }
+    out
+}
+
+fn collect_slots(stmts: &[Stmt], out: &mut BTreeSet<i64>) {
+    for stmt in stmts {
+        let mut reads = Vec::new();
+        match stmt {
+            Stmt::If(condition, then, otherwise) => {
+                condition.reads(&mut reads);
+                collect_slots(then, out);
+                collect_slots(otherwise, out);
+            }
+            Stmt::While(condition, body) => {
+                condition.reads(&mut reads);
+                collect_slots(body, out);
+            }
+            Stmt::Loop(body) => collect_slots(body, out),
+            Stmt::Leave(Leave::Return(Some(expr)) | Leave::Indirect(expr)) => {
+                expr.reads(&mut reads)
+            }
+            stmt => {
+                reads = stmt_reads(stmt);
+                reads.extend(stmt.target());
+            }
+        }
+        out.extend(slots(reads));
+    }
+}
+
+impl Function {
+    fn value(&self, value: Value) -> String {
+        match value {
+            Value::Const(constant) => constant.to_string(),
+            Value::Mem(address) => format!("mem[{}]", address),
+            Value::Slot(offset) => self.names[&offset].clone(),
+            Value::Rel(offset) => format!("rb[{}]", offset),
+        }
+    }
+
+    // Returns the text and its precedence: comparisons bind loosest, then
+    // sums, products, and negation.
+    fn expr(&self, expr: &Expr) -> (String, u8) {
+        use Value::Const;
+        match expr {
+            Expr::Value(Const(constant)) if *constant < 0 => (constant.to_string(), 4),
+            Expr::Value(value) => (self.value(*value), 5),
+            Expr::Input => ("input()".to_string(), 5),
+            Expr::Call(callee, args) => {
+                let args: Vec<String> = args.iter().map(|arg| self.expr(arg).0).collect();
+                (
+                    format!("{}({})", function_name(*callee), args.join(", ")),
+                    5,
+                )
+            }
+            Expr::Binary(Op::Add, left, right) => match &**right {
+                Expr::Value(Const(constant)) if *constant < 0 && *constant != i64::MIN => {
+                    (format!("{} - {}", self.operand(left, 2), -constant), 2)
+                }
+                Expr::Binary(Op::Mul, negated, minus_one)
+                    if **minus_one == Expr::Value(Const(-1)) =>
+                {
+                    (
+                        format!("{} - {}", self.operand(left, 2), self.operand(negated, 3)),
+                        2,
+                    )
+                }
+                _ => (
+                    format!("{} + {}", self.operand(left, 2), self.operand(right, 3)),
+                    2,
+                ),
+            },
+            Expr::Binary(Op::Mul, left, right) if **right == Expr::Value(Const(-1)) => {
+                (format!("-{}", self.operand(left, 4)), 4)
+            }
+            Expr::Binary(Op::Mul, left, right) => (
+                format!("{} * {}", self.operand(left, 3), self.operand(right, 4)),
+                3,
+            ),
+            Expr::Binary(op, left, right) => (self.comparison(*op, left, right, false), 1),
+            Expr::Not(inner) => match &**inner {
+                Expr::Binary(op, left, right) => (self.comparison(*op, left, right, true), 1),
+                inner => (format!("!{}", self.operand(inner, 4)), 4),
+            },
+        }
+    }
+
+    fn comparison(&self, op: Op, left: &Expr, right: &Expr, negated: bool) -> String {
+        let constant = |expr: &Expr| matches!(expr, Expr::Value(Value::Const(_)));
+        let (left, right, symbol) = match (op, negated) {
+            // Constants read better on the right.
+            (Op::Less, false) if constant(left) && !constant(right) => (right, left, ">"),
+            (Op::Less, true) if constant(left) && !constant(right) => (right, left, "<="),
+            (Op::Less, false) => (left, right, "<"),
+            (Op::Less, true) => (left, right, ">="),
+            (_, false) => (left, right, "=="),
+            (_, true) => (left, right, "!="),
+        };
+        format!(
+            "{} {} {}",
+            self.operand(left, 2),
+            symbol,
+            self.operand(right, 2)
+        )
+    }
+
+    fn operand(&self, expr: &Expr, precedence: u8) -> String {
+        let (text, own) = self.expr(expr);
+        if own < precedence {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn stmts(&self, stmts: &[Stmt], depth: usize, out: &mut String) {
+        for stmt in stmts {
+            self.stmt(stmt, depth, out);
+        }
+    }
+
+    fn stmt(&self, stmt: &Stmt, depth: usize, out: &mut String) {
+        let indent = "    ".repeat(depth);
+        let line = match stmt {
+            Stmt::Assign(target, expr) => {
+                format!("{} = {};", self.value(*target), self.expr(expr).0)
+            }
+            Stmt::Eval(expr) => format!("{};", self.expr(expr).0),
+            Stmt::Output(expr) => format!("output({});", self.expr(expr).0),
+            Stmt::AdjustBase(expr) => format!("rb += {};", self.expr(expr).0),
+            Stmt::Call(callee, _) => format!("{}();", function_name(*callee)),
+            Stmt::If(condition, then, otherwise) => {
+                out.push_str(&format!("{}if ({}) {{\n", indent, self.expr(condition).0));
+                self.stmts(then, depth + 1, out);
+                let mut otherwise = otherwise;
+                while let [Stmt::If(condition, then, rest)] = &otherwise[..] {
+                    out.push_str(&format!(
+                        "{}}} else if ({}) {{\n",
+                        indent,
+                        self.expr(condition).0
+                    ));
+                    self.stmts(then, depth + 1, out);
+                    otherwise = rest;
+                }
+                if !otherwise.is_empty() {
+                    out.push_str(&format!("{}}} else {{\n", indent));
+                    self.stmts(otherwise, depth + 1, out);
+                }
+                "}".to_string()
+            }
+            Stmt::Loop(body) => {
+                out.push_str(&format!("{}loop {{\n", indent));
+                self.stmts(body, depth + 1, out);
+                "}".to_string()
+            }
+            Stmt::While(condition, body) => {
+                out.push_str(&format!(
+                    "{}while ({}) {{\n",
+                    indent,
+                    self.expr(condition).0
+                ));
+                self.stmts(body, depth + 1, out);
+                "}".to_string()
+            }
+            Stmt::Break => "break;".to_string(),
+            Stmt::Continue => "continue;".to_string(),
+            Stmt::Leave(Leave::Return(None)) => "return;".to_string(),
+            Stmt::Leave(Leave::Return(Some(expr))) => format!("return {};", self.expr(expr).0),
+            Stmt::Leave(Leave::Halt) => "halt;".to_string(),
+            Stmt::Leave(Leave::Indirect(expr)) => format!("goto *{};", self.operand(expr, 5)),
+            Stmt::Leave(Leave::Invalid(word)) => format!("invalid({});", word),
+            Stmt::Goto(label) => format!("goto L{};", label),
+            Stmt::Label(label) => format!("L{}:", label),
+        };
+        out.push_str(&indent);
+        out.push_str(&line);
+        out.push('\n');
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "fn {}({}) {{", self.name, self.params.join(", "))?;
+        if !self.locals.is_empty() {
+            writeln!(f, "    var {};", self.locals.join(", "))?;
+        }
+        let mut body = String::new();
+        self.stmts(&self.body, 1, &mut body);
+        write!(f, "{}}}", body)?;
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::compile;
+    use crate::computer::parse_program;
+
+    fn function(program: &[i64], name: &str) -> String {
+        super::decompile(program)
+            .into_iter()
+            .find(|function| function.name == name)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_structure() {
+        let program = compile(
+            "fn main() {
+                let n = input();
+                let total = 0;
+                while (n > 0) {
+                    if (n == 3) {
+                        output(n);
+                    } else {
+                        total = total + n * 2;
+                    }
+                    n = n - 1;
+                }
+                output(total);
+            }",
+        )
+        .unwrap();
+        assert_eq!(
+            function(&program, "start"),
+            "fn start() {\n    sub_10();\n    halt;\n}\n"
+        );
+        assert_eq!(
+            function(&program, "sub_10"),
+            "fn sub_10() {
+    var local0, local1;
+    local0 = input();
+    local1 = 0;
+    while (local0 > 0) {
+        if (local0 == 3) {
+            output(local0);
+        } else {
+            local1 = local1 + local0 * 2;
+        }
+        local0 = local0 - 1;
+    }
+    output(local1);
+    return 0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_calls() {
+        let program = compile(
+            "fn square(x) { return x * x; }
+            fn main() {
+                output(square(input()) + 1);
+                output(-7 % 2);
+            }",
+        )
+        .unwrap();
+        let functions = super::decompile(&program);
+        let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names.len(), 6);
+        assert_eq!(
+            function(&program, names[1]),
+            format!(
+                "fn sub_10() {{
+    output({}(input()) + 1);
+    output({}(-7, 2));
+    return 0;
+}}
+",
+                names[5], names[2]
+            )
+        );
+        assert_eq!(
+            function(&program, names[5]),
+            format!("fn {}(arg0) {{\n    return arg0 * arg0;\n}}\n", names[5])
+        );
+        assert_eq!(
+            function(&program, names[2]),
+            format!(
+                "fn {}(arg0, arg1) {{\n    return arg0 - {}(arg0, arg1) * arg1;\n}}\n",
+                names[2], names[3]
+            )
+        );
+        let division = function(&program, names[3]);
+        assert!(division.starts_with(&format!("fn {}(arg0, arg1) {{", names[3])));
+        assert!(division.contains("    if (arg1 == 0) {\n        halt;\n    }\n"));
+    }
+
+    #[test]
+    fn test_puzzle_inputs() {
+        // BOOST moves the relative base by amounts read from memory, and
+        // computes its answer with a recursive function.
+        let program = parse_program(include_str!("../input/2019/day9.txt")).unwrap();
+        let text = super::pseudo_code(&program);
+        assert!(text.contains("rb += rb[6];"));
+        assert!(text.contains("fn sub_922(arg0) {"));
+        assert!(text.contains("        sub_922(local1);\n"));
+
+        // The diagnostic program patches cell 6 with its first input, so it
+        // only makes sense decompiled after that has happened.
+        let mut program = parse_program(include_str!("../input/2019/day5.txt")).unwrap();
+        assert!(super::pseudo_code(&program).contains("invalid(1100);"));
+        program[6] += 1;
+        let text = super::pseudo_code(&program);
+        assert!(text.contains("    mem[224] = mem[224] - 176;\n    output(mem[224]);\n"));
+        assert!(text.ends_with("    output(mem[223]);\n    halt;\n}\n"));
+    }
+}
@@ -0,0 +1,351 @@
+// Explores an unknown area with a remote controlled repair droid.
+//
+// The droid takes a movement command (1 north, 2 south, 3 west, 4 east) and
+// answers with a status: 0 if it hit a wall and stayed put, 1 if it moved, 2
+// if it moved and is now at the goal. Positions are relative to where the
+// droid starts, with y growing to the south.
+
+use crate::computer::{Computer, Fault};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Move {
+    North = 1,
+    South = 2,
+    West = 3,
+    East = 4,
+}
+
+impl Move {
+    pub const ALL: [Move; 4] = [Move::North, Move::South, Move::West, Move::East];
+
+    pub fn command(self) -> i64 {
+        self as i64
+    }
+
+    pub fn reverse(self) -> Move {
+        match self {
+            Move::North => Move::South,
+            Move::South => Move::North,
+            Move::West => Move::East,
+            Move::East => Move::West,
+        }
+    }
+
+    pub fn turn_left(self) -> Move {
+        match self {
+            Move::North => Move::West,
+            Move::West => Move::South,
+            Move::South => Move::East,
+            Move::East => Move::North,
+        }
+    }
+
+    pub fn turn_right(self) -> Move {
+        self.turn_left().reverse()
+    }
+
+    pub fn apply(self, (x, y): (i64, i64)) -> (i64, i64) {
+        match self {
+            Move::North => (x, y - 1),
+            Move::South => (x, y + 1),
+            Move::West => (x - 1, y),
+            Move::East => (x + 1, y),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cell {
+    Wall,
+    Open,
+    Goal,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ExploreError {
+    Fault(Fault),
+    Halted,
+    InvalidStatus(i64),
+    // The droid could not retrace a step it had already taken.
+    Blocked((i64, i64)),
+}
+
+impl fmt::Display for ExploreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExploreError::Fault(fault) => write!(f, "{}", fault),
+            ExploreError::Halted => write!(f, "droid halted during exploration"),
+            ExploreError::InvalidStatus(status) => write!(f, "invalid droid status {}", status),
+            ExploreError::Blocked((x, y)) => write!(f, "droid could not return to ({}, {})", x, y),
+        }
+    }
+}
+
+impl std::error::Error for ExploreError {}
+
+impl From<Fault> for ExploreError {
+    fn from(fault: Fault) -> ExploreError {
+        ExploreError::Fault(fault)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Map {
+    cells: HashMap<(i64, i64), Cell>,
+}
+
+impl Map {
+    pub fn get(&self, position: (i64, i64)) -> Option<Cell> {
+        self.cells.get(&position).cloned()
+    }
+
+    pub fn goal(&self) -> Option<(i64, i64)> {
+        self.cells
+            .iter()
+            .find(|(_, &cell)| cell == Cell::Goal)
+            .map(|(&position, _)| position)
+    }
+
+    // Breadth first distances to every reachable cell.
+    pub fn distances(&self, from: (i64, i64)) -> HashMap<(i64, i64), usize> {
+        let mut distances = HashMap::new();
+        if !self.is_open(from) {
+            return distances;
+        }
+        distances.insert(from, 0);
+        let mut queue = VecDeque::from(vec![from]);
+        while let Some(position) = queue.pop_front() {
+            let distance = distances[&position];
+            for &step in &Move::ALL {
+                let next = step.apply(position);
+                if self.is_open(next) && !distances.contains_key(&next) {
+                    distances.insert(next, distance + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+        distances
+    }
+
+    pub fn shortest_path(&self, from: (i64, i64), to: (i64, i64)) -> Option<Vec<Move>> {
+        // Walk back from the target along decreasing distances.
+        let distances = self.distances(from);
+        let mut position = to;
+        let mut distance = *distances.get(&to)?;
+        let mut path = Vec::with_capacity(distance);
+        while distance > 0 {
+            let step = Move::ALL.iter().find(|step| {
+                distances.get(&step.reverse().apply(position)) == Some(&(distance - 1))
+            })?;
+            path.push(*step);
+            position = step.reverse().apply(position);
+            distance -= 1;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    // Steps needed for something spreading one cell per step from `from` to
+    // fill every reachable cell.
+    pub fn fill_time(&self, from: (i64, i64)) -> Option<usize> {
+        self.distances(from).values().max().cloned()
+    }
+
+    pub fn render(&self, droid: (i64, i64)) -> String {
+        if self.cells.is_empty() {
+            return String::new();
+        }
+        let min_x = self.cells.keys().map(|p| p.0).min().unwrap();
+        let max_x = self.cells.keys().map(|p| p.0).max().unwrap();
+        let min_y = self.cells.keys().map(|p| p.1).min().unwrap();
+        let max_y = self.cells.keys().map(|p| p.1).max().unwrap();
+        let mut result = String::new();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                result.push(match self.get((x, y)) {
+                    _ if (x, y) == droid => 'D',
+                    Some(Cell::Wall) => '#',
+                    Some(Cell::Open) => '.',
+                    Some(Cell::Goal) => 'O',
+                    None => ' ',
+                });
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    fn is_open(&self, position: (i64, i64)) -> bool {
+        matches!(self.get(position), Some(Cell::Open) | Some(Cell::Goal))
+    }
+}
+
+pub struct Explorer {
+    computer: Computer,
+    position: (i64, i64),
+    map: Map,
+}
+
+impl Explorer {
+    pub fn new(program: &[i64]) -> Explorer {
+        let mut map = Map::default();
+        map.cells.insert((0, 0), Cell::Open);
+        Explorer {
+            computer: Computer::new(program),
+            position: (0, 0),
+            map,
+        }
+    }
+
+    pub fn position(&self) -> (i64, i64) {
+        self.position
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    // Sends one command and records what the droid found.
+    pub fn step(&mut self, step: Move) -> Result<Cell, ExploreError> {
+        let target = step.apply(self.position);
+        let cell = match self.computer.try_compute(&[step.command()])? {
+            Some(0) => Cell::Wall,
+            Some(1) => Cell::Open,
+            Some(2) => Cell::Goal,
+            Some(status) => return Err(ExploreError::InvalidStatus(status)),
+            None => return Err(ExploreError::Halted),
+        };
+        self.map.cells.insert(target, cell);
+        if cell != Cell::Wall {
+            self.position = target;
+        }
+        Ok(cell)
+    }
+
+    // Visits every reachable cell with a backtracking depth first search and
+    // returns the droid to where the exploration started.
+    pub fn explore(&mut self) -> Result<&Map, ExploreError> {
+        let mut trail: Vec<Move> = Vec::new();
+        loop {
+            let unknown = Move::ALL
+                .iter()
+                .find(|step| self.map.get(step.apply(self.position)).is_none());
+            if let Some(&step) = unknown {
+                if self.step(step)? != Cell::Wall {
+                    trail.push(step);
+                }
+            } else if let Some(step) = trail.pop() {
+                let back = step.reverse();
+                if self.step(back)? == Cell::Wall {
+                    return Err(ExploreError::Blocked(back.apply(self.position)));
+                }
+            } else {
+                return Ok(&self.map);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cell, Explorer, Move};
+    use crate::compiler::compile;
+
+    // A droid in a hard-coded maze. Rows are bit masks of walls, x = 0 being
+    // the lowest bit; the droid starts at (1, 1) and the goal is at (1, 5).
+    //
+    //     #######
+    //     #..#..#
+    //     #.##.##
+    //     #.....#
+    //     ##.##.#
+    //     #G....#
+    //     #######
+    const DROID: &str = "
+        fn row(y) {
+            if (y == 1) { return 73; }
+            if (y == 2) { return 109; }
+            if (y == 3) { return 65; }
+            if (y == 4) { return 91; }
+            if (y == 5) { return 65; }
+            return 127;
+        }
+
+        fn wall(x, y) {
+            let bits = row(y);
+            while (x > 0) {
+                bits = bits / 2;
+                x = x - 1;
+            }
+            return bits % 2;
+        }
+
+        fn main() {
+            let x = 1;
+            let y = 1;
+            while (1) {
+                let command = input();
+                let nx = x;
+                let ny = y;
+                if (command == 1) { ny = y - 1; }
+                if (command == 2) { ny = y + 1; }
+                if (command == 3) { nx = x - 1; }
+                if (command == 4) { nx = x + 1; }
+                if (wall(nx, ny)) {
+                    output(0);
+                } else {
+                    x = nx;
+                    y = ny;
+                    output(1 + (x == 1 && y == 5));
+                }
+            }
+        }";
+
+    #[test]
+    fn test_explore() {
+        let program = compile(DROID).unwrap();
+        let mut explorer = Explorer::new(&program);
+        let map = explorer.explore().unwrap();
+        assert_eq!(map.goal(), Some((0, 4)));
+        assert_eq!(map.get((1, 0)), Some(Cell::Open));
+        assert_eq!(map.get((0, 3)), Some(Cell::Wall));
+        assert_eq!(map.get((-1, -1)), None);
+        assert_eq!(
+            map.render((0, 0)),
+            concat!(
+                " ## ## \n",
+                "#D.#..#\n",
+                "#.##.# \n",
+                "#.....#\n",
+                " #.##.#\n",
+                "#O....#\n",
+                " ##### \n",
+            )
+        );
+        assert_eq!(explorer.position(), (0, 0));
+    }
+
+    #[test]
+    fn test_paths() {
+        let program = compile(DROID).unwrap();
+        let mut explorer = Explorer::new(&program);
+        let map = explorer.explore().unwrap();
+        let goal = map.goal().unwrap();
+        assert_eq!(
+            map.shortest_path((0, 0), goal),
+            Some(vec![
+                Move::South,
+                Move::South,
+                Move::East,
+                Move::South,
+                Move::South,
+                Move::West
+            ])
+        );
+        assert_eq!(map.shortest_path((0, 0), (0, 3)), None);
+        assert_eq!(map.fill_time(goal), Some(8));
+        assert_eq!(map.fill_time((4, 0)), Some(9));
+    }
+}
@@ -0,0 +1,141 @@
+// The interface the puzzle solutions use to run Intcode, so that they do not
+// depend on how a program is executed. `Computer` is the reference engine;
+// others (pre-decoded, compiled, sandboxed, instrumented) implement the same
+// trait and are held to the same behaviour by the conformance corpus.
+//
+// The day modules never name an engine. They call `boot`, which builds an
+// `Engine`, and the engine behind that name is chosen here when the crate is
+// built.
+
+use crate::computer::{Computer, Fault, Observer, Status};
+
+pub trait IntcodeEngine {
+    fn name(&self) -> &str;
+
+    // Replaces whatever was running with a fresh copy of `program`: counter
+    // and relative base at 0, no queued input, no step limit.
+    fn load(&mut self, program: &[i64]);
+
+    fn provide_input(&mut self, value: i64);
+
+    // Runs until the program outputs a value, stops, or needs input that has
+    // not been provided. After `NeedsInput`, providing input and calling this
+    // again carries on where the program left off.
+    fn run_until_io(&mut self) -> Result<Status, Fault>;
+
+    // Address of the next instruction.
+    fn counter(&self) -> i64;
+
+    // Reads a cell without affecting the run. Negative addresses read as 0.
+    fn memory(&self, address: i64) -> i64;
+
+    // Caps the number of instructions executed since the last `load`.
+    // Going over is a `Fault::StepLimit`.
+    fn set_step_limit(&mut self, limit: Option<u64>);
+
+    fn provide_inputs(&mut self, values: &[i64]) {
+        for &value in values {
+            self.provide_input(value);
+        }
+    }
+
+    // Runs until the next output, returning `None` once the program stops.
+    // Running out of input is a `Fault::MissingInput`.
+    fn take_output(&mut self) -> Result<Option<i64>, Fault> {
+        match self.run_until_io()? {
+            Status::Output(value) => Ok(Some(value)),
+            Status::Halted => Ok(None),
+            Status::NeedsInput => Err(Fault::MissingInput {
+                address: self.counter(),
+            }),
+        }
+    }
+
+    // Runs until the program stops, returning everything it output.
+    fn run_to_halt(&mut self) -> Result<Vec<i64>, Fault> {
+        let mut outputs = Vec::new();
+        while let Some(value) = self.take_output()? {
+            outputs.push(value);
+        }
+        Ok(outputs)
+    }
+}
+
+impl<O: Observer> IntcodeEngine for Computer<O> {
+    fn name(&self) -> &str {
+        "computer"
+    }
+
+    fn load(&mut self, program: &[i64]) {
+        self.reset(program);
+        self.set_step_limit(None);
+    }
+
+    fn provide_input(&mut self, value: i64) {
+        self.push_input(value);
+    }
+
+    fn run_until_io(&mut self) -> Result<Status, Fault> {
+        self.resume()
+    }
+
+    fn counter(&self) -> i64 {
+        Computer::counter(self)
+    }
+
+    fn memory(&self, address: i64) -> i64 {
+        Computer::memory(self, address)
+    }
+
+    fn set_step_limit(&mut self, limit: Option<u64>) {
+        let mut limits = self.limits();
+        limits.max_steps = limit;
+        self.set_limits(limits);
+    }
+}
+
+// The engine the puzzle solutions run on. Building with the `jit` feature
+// swaps the interpreter for the compiler where it is supported.
+#[cfg(not(all(feature = "jit", target_arch = "x86_64", target_os = "linux")))]
+pub type Engine = Computer;
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+pub type Engine = crate::jit::Jit;
+
+pub fn boot(program: &[i64]) -> Engine {
+    let mut engine = Engine::default();
+    engine.load(program);
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IntcodeEngine;
+    use crate::computer::{Computer, Fault, Status};
+
+    #[test]
+    fn test_engine() {
+        // outputs the sum of each pair of inputs, stopping at a 0
+        let program = vec![
+            3, 20, 1006, 20, 16, 3, 21, 1, 20, 21, 22, 4, 22, 1105, 1, 0, 99,
+        ];
+        let mut engine = super::boot(&program);
+        assert_eq!(engine.run_until_io(), Ok(Status::NeedsInput));
+        engine.provide_inputs(&[2, 3]);
+        assert_eq!(engine.take_output(), Ok(Some(5)));
+        assert_eq!(
+            engine.take_output(),
+            Err(Fault::MissingInput { address: 0 })
+        );
+        engine.provide_inputs(&[-4, 10, 0]);
+        assert_eq!(engine.run_to_halt(), Ok(vec![6]));
+
+        let engine: &mut dyn IntcodeEngine = &mut Computer::new(&[]);
+        engine.load(&program);
+        engine.set_step_limit(Some(3));
+        engine.provide_inputs(&[1, 1, 0]);
+        assert_eq!(engine.run_to_halt(), Err(Fault::StepLimit(3)));
+        engine.load(&program);
+        engine.provide_inputs(&[1, 1, 0]);
+        assert_eq!(engine.run_to_halt(), Ok(vec![2]));
+    }
+}
@@ -0,0 +1,849 @@
+// An Intcode engine that compiles hot straight-line code to x86-64 machine
+// code. Built with the `jit` feature, on x86-64 Linux only.
+//
+// A `Jit` wraps a `Computer`, which interprets everything the compiler does
+// not handle: input and output, halting, cells past the end of memory and any
+// instruction that faults. Every address the interpreter executes has a heat
+// counter; when it reaches the threshold, the instructions from there to the
+// next jump are compiled into a block and from then on run natively.
+//
+// A block only covers arithmetic, comparisons, relative base adjustments and
+// the jump that ends it. Instruction words and operands are baked into the
+// machine code, so every cell a block was compiled from is marked in a code
+// map. Compiled code checks the map after each write, and the interpreter's
+// observer does the same; a write to a marked cell deoptimizes the machine:
+// compiled code is thrown away and the `Computer` carries on alone.
+//
+// Native code can only reach memory that is one flat array, so the image is
+// padded with zeros once the first block is compiled. Any access outside it
+// exits the block before the instruction runs and leaves the instruction to
+// the interpreter, which handles extended memory and faults exactly as it
+// always does. So does arithmetic that overflows, which the interpreter
+// turns into a fault.
+//
+// A block is entered with a pointer to a `Frame` and keeps its state in
+// registers:
+//
+//     rdi  frame            rcx  code map
+//     rsi  memory           r8   relative base
+//     rdx  memory length    rax, r9, r10, r11  scratch
+//
+// A block that ends normally looks up the next counter in a table of
+// compiled entry points and jumps straight there, so a hot loop stays in
+// machine code until it needs the interpreter. Each block checks on entry
+// that it fits in the step budget. On exit the relative base, the next
+// counter, the number of instructions executed and the reason for stopping
+// are written back to the frame.
+
+use crate::computer::{Computer, Fault, Instruction, Observer, Parameter, Status};
+use crate::engine::IntcodeEngine;
+use std::convert::TryFrom;
+use std::os::raw::{c_int, c_long, c_void};
+use std::ptr;
+
+// Executions of an address before the code from there on is compiled.
+pub const HOT_THRESHOLD: u32 = 16;
+
+const MAX_BLOCK_LENGTH: usize = 64;
+const CODE_SIZE: usize = 1 << 20;
+// Zero cells added after the image, so that stacks and scratch space just
+// past the program stay within reach of compiled code.
+const PADDING: usize = 4096;
+
+// The instruction at the counter must be interpreted.
+const EXIT_BAIL: i64 = 0;
+// The block ran to its end.
+const EXIT_END: i64 = 1;
+// The block wrote to compiled code.
+const EXIT_DEOPT: i64 = 2;
+
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const PROT_EXEC: c_int = 4;
+const MAP_PRIVATE: c_int = 2;
+const MAP_ANONYMOUS: c_int = 0x20;
+
+extern "C" {
+    fn mmap(
+        address: *mut c_void,
+        length: usize,
+        protection: c_int,
+        flags: c_int,
+        file: c_int,
+        offset: c_long,
+    ) -> *mut c_void;
+    fn mprotect(address: *mut c_void, length: usize, protection: c_int) -> c_int;
+    fn munmap(address: *mut c_void, length: usize) -> c_int;
+}
+
+// Cells that compiled code was built from; a write to one of them means the
+// code is out of date.
+#[derive(Default)]
+pub struct CodeWatch {
+    code: Vec<u8>,
+    written: bool,
+}
+
+impl Observer for CodeWatch {
+    fn on_write(&mut self, address: i64, _value: i64) {
+        if self.code.get(address as usize) == Some(&1) {
+            self.written = true;
+        }
+    }
+}
+
+#[repr(C)]
+struct Frame {
+    memory: *mut i64,
+    length: i64,
+    code: *const u8,
+    relative_base: i64,
+    counter: i64,
+    steps: i64,
+    exit: i64,
+    table: *const u64,
+    budget: i64,
+}
+
+const FRAME_MEMORY: i32 = 0;
+const FRAME_LENGTH: i32 = 8;
+const FRAME_CODE: i32 = 16;
+const FRAME_RELATIVE_BASE: i32 = 24;
+const FRAME_COUNTER: i32 = 32;
+const FRAME_STEPS: i32 = 40;
+const FRAME_EXIT: i32 = 48;
+const FRAME_TABLE: i32 = 56;
+const FRAME_BUDGET: i32 = 64;
+
+// Memory mapped for compiled code. It is only writable while a block is
+// being added and only executable otherwise.
+struct CodeBuffer {
+    base: *mut u8,
+    used: usize,
+}
+
+impl CodeBuffer {
+    fn new() -> Option<CodeBuffer> {
+        let base = unsafe {
+            mmap(
+                ptr::null_mut(),
+                CODE_SIZE,
+                PROT_READ,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if base as isize == -1 {
+            return None;
+        }
+        Some(CodeBuffer {
+            base: base as *mut u8,
+            used: 0,
+        })
+    }
+
+    // Copies `code` in and returns its offset, or `None` when full.
+    fn append(&mut self, code: &[u8]) -> Option<usize> {
+        if self.used + code.len() > CODE_SIZE {
+            return None;
+        }
+        let offset = self.used;
+        unsafe {
+            if mprotect(self.base as *mut c_void, CODE_SIZE, PROT_READ | PROT_WRITE) != 0 {
+                return None;
+            }
+            ptr::copy_nonoverlapping(code.as_ptr(), self.base.add(offset), code.len());
+            if mprotect(self.base as *mut c_void, CODE_SIZE, PROT_READ | PROT_EXEC) != 0 {
+                return None;
+            }
+        }
+        self.used += code.len();
+        Some(offset)
+    }
+
+    // Safety: `offset` must be the start of a block, and the frame must point
+    // at memory and a code map at least `frame.length` cells long.
+    unsafe fn call(&self, offset: usize, frame: &mut Frame) {
+        let entry: extern "sysv64" fn(*mut Frame) = std::mem::transmute(self.base.add(offset));
+        entry(frame);
+    }
+}
+
+impl Drop for CodeBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.base as *mut c_void, CODE_SIZE);
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Slot {
+    Cold(u32),
+    // Offset of the block in the code buffer.
+    Compiled(usize),
+    Uncompilable,
+}
+
+pub struct Jit {
+    computer: Computer<CodeWatch>,
+    slots: Vec<Slot>,
+    // Where blocks that are already running continue, by counter; 0 for
+    // counters without a block.
+    table: Vec<u64>,
+    buffer: Option<CodeBuffer>,
+    threshold: u32,
+    deoptimized: bool,
+}
+
+impl Default for Jit {
+    fn default() -> Jit {
+        Jit {
+            computer: Computer::default(),
+            slots: Vec::new(),
+            table: Vec::new(),
+            buffer: None,
+            threshold: HOT_THRESHOLD,
+            deoptimized: false,
+        }
+    }
+}
+
+impl Jit {
+    pub fn new(program: &[i64]) -> Jit {
+        let mut jit = Jit::default();
+        jit.load(program);
+        jit
+    }
+
+    // Compiles code after it has run `threshold` times; 1 compiles
+    // everything as soon as it is reached.
+    pub fn with_threshold(mut self, threshold: u32) -> Jit {
+        self.threshold = threshold.max(1);
+        self
+    }
+
+    // Whether the machine has given up on compiled code because a program
+    // wrote to it.
+    pub fn deoptimized(&self) -> bool {
+        self.deoptimized
+    }
+
+    // Number of blocks compiled since the last `load`.
+    pub fn compiled_blocks(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| matches!(slot, Slot::Compiled(_)))
+            .count()
+    }
+
+    fn deoptimize(&mut self) {
+        self.deoptimized = true;
+        self.slots = Vec::new();
+        self.table = Vec::new();
+        self.buffer = None;
+    }
+
+    // Counts an execution of `address`, compiling the code there once it is
+    // hot.
+    fn block_at(&mut self, address: i64) -> Option<usize> {
+        let slot = self.slots.get_mut(address as usize)?;
+        match *slot {
+            Slot::Compiled(offset) => Some(offset),
+            Slot::Uncompilable => None,
+            Slot::Cold(heat) if heat + 1 < self.threshold => {
+                *slot = Slot::Cold(heat + 1);
+                None
+            }
+            Slot::Cold(_) => {
+                let compiled = self.compile(address);
+                self.slots[address as usize] = compiled.map_or(Slot::Uncompilable, Slot::Compiled);
+                compiled
+            }
+        }
+    }
+
+    fn compile(&mut self, address: i64) -> Option<usize> {
+        if self.buffer.is_none() {
+            self.buffer = CodeBuffer::new();
+        }
+        // Most runs are too short to compile anything, so the image is only
+        // padded once something is.
+        if self.computer.observer().code.is_empty() {
+            let length = self.slots.len() + PADDING;
+            self.computer.grow_image(length);
+            self.computer.observer_mut().code = vec![0; length];
+            self.slots.resize(length, Slot::Cold(0));
+            self.table = vec![0; length];
+        }
+        let (code, chain, end) = compile_block(self.computer.image_mut(), address)?;
+        let buffer = self.buffer.as_mut()?;
+        let offset = buffer.append(&code)?;
+        self.table[address as usize] = buffer.base as u64 + (offset + chain) as u64;
+        let map = &mut self.computer.observer_mut().code;
+        for cell in address..end {
+            map[cell as usize] = 1;
+        }
+        Some(offset)
+    }
+
+    fn execute(&mut self, offset: usize) -> i64 {
+        let steps = self.computer.steps();
+        let budget = self.computer.limits().max_steps.map_or(i64::MAX, |limit| {
+            i64::try_from(limit - steps).unwrap_or(i64::MAX)
+        });
+        let code = self.computer.observer().code.as_ptr();
+        let image = self.computer.image_mut();
+        let mut frame = Frame {
+            memory: image.as_mut_ptr(),
+            length: image.len() as i64,
+            code,
+            relative_base: self.computer.relative_base(),
+            counter: 0,
+            steps: 0,
+            exit: EXIT_BAIL,
+            table: self.table.as_ptr(),
+            budget,
+        };
+        // The code map and the table are as long as the image, and blocks
+        // only reach cells they have checked against the length.
+        unsafe { self.buffer.as_ref().unwrap().call(offset, &mut frame) };
+        self.computer.set_registers(
+            frame.counter,
+            frame.relative_base,
+            steps + frame.steps as u64,
+        );
+        frame.exit
+    }
+}
+
+impl IntcodeEngine for Jit {
+    fn name(&self) -> &str {
+        "jit"
+    }
+
+    fn load(&mut self, program: &[i64]) {
+        self.computer.reset(program);
+        self.computer.set_step_limit(None);
+        *self.computer.observer_mut() = CodeWatch::default();
+        self.slots.clear();
+        self.slots.resize(program.len(), Slot::Cold(0));
+        self.table.clear();
+        if let Some(buffer) = &mut self.buffer {
+            buffer.used = 0;
+        }
+        self.deoptimized = false;
+    }
+
+    fn provide_input(&mut self, value: i64) {
+        self.computer.push_input(value);
+    }
+
+    fn run_until_io(&mut self) -> Result<Status, Fault> {
+        while !self.deoptimized {
+            if let Some(offset) = self.block_at(self.computer.counter()) {
+                match self.execute(offset) {
+                    EXIT_END => continue,
+                    EXIT_DEOPT => {
+                        self.deoptimize();
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            let status = self.computer.step()?;
+            if self.computer.observer().written {
+                self.deoptimize();
+            }
+            if let Some(status) = status {
+                return Ok(status);
+            }
+        }
+        self.computer.resume()
+    }
+
+    fn counter(&self) -> i64 {
+        self.computer.counter()
+    }
+
+    fn memory(&self, address: i64) -> i64 {
+        self.computer.memory(address)
+    }
+
+    fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.computer.set_step_limit(limit);
+    }
+}
+
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RSI: u8 = 6;
+const RDI: u8 = 7;
+const R8: u8 = 8;
+const R9: u8 = 9;
+const R10: u8 = 10;
+const R11: u8 = 11;
+
+// Condition codes, as used by jcc, setcc and cmovcc.
+const OVERFLOW: u8 = 0x0;
+const ABOVE_OR_EQUAL: u8 = 0x3;
+const EQUAL: u8 = 0x4;
+const NOT_EQUAL: u8 = 0x5;
+const LESS: u8 = 0xC;
+const GREATER: u8 = 0xF;
+
+// `[base + index * scale + displacement]`
+#[derive(Copy, Clone)]
+struct Memory {
+    base: u8,
+    index: Option<(u8, u8)>,
+    displacement: i32,
+}
+
+fn at(base: u8, displacement: i32) -> Memory {
+    Memory {
+        base,
+        index: None,
+        displacement,
+    }
+}
+
+fn indexed(base: u8, index: u8, scale: u8, displacement: i32) -> Memory {
+    Memory {
+        base,
+        index: Some((index, scale)),
+        displacement,
+    }
+}
+
+// Just enough of an x86-64 assembler for the blocks. Jumps only go forward
+// to exit stubs, which are placed after the body.
+#[derive(Default)]
+struct Assembler {
+    bytes: Vec<u8>,
+    // Positions of 32-bit jump displacements and the stub each targets.
+    fixups: Vec<(usize, usize)>,
+}
+
+impl Assembler {
+    fn rex(&mut self, wide: bool, reg: u8, index: u8, base: u8) {
+        let rex = 0x40 | (wide as u8) << 3 | (reg >> 3) << 2 | (index >> 3) << 1 | base >> 3;
+        if rex != 0x40 {
+            self.bytes.push(rex);
+        }
+    }
+
+    // An instruction with a register and a register operand.
+    fn register(&mut self, opcode: &[u8], reg: u8, rm: u8) {
+        self.rex(true, reg, 0, rm);
+        self.bytes.extend_from_slice(opcode);
+        self.bytes.push(0xC0 | (reg & 7) << 3 | rm & 7);
+    }
+
+    // An instruction with a register and a memory operand, always encoded
+    // with a SIB byte and a 32-bit displacement.
+    fn memory(&mut self, wide: bool, opcode: &[u8], reg: u8, memory: Memory) {
+        let (index, scale) = memory.index.unwrap_or((4, 1));
+        self.rex(wide, reg, index, memory.base);
+        self.bytes.extend_from_slice(opcode);
+        self.bytes.push(0x80 | (reg & 7) << 3 | 4);
+        let scale = scale.trailing_zeros() as u8;
+        self.bytes
+            .push(scale << 6 | (index & 7) << 3 | memory.base & 7);
+        self.bytes
+            .extend_from_slice(&memory.displacement.to_le_bytes());
+    }
+
+    fn mov_immediate(&mut self, reg: u8, value: i64) {
+        self.rex(true, 0, 0, reg);
+        self.bytes.push(0xB8 | reg & 7);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn mov(&mut self, destination: u8, source: u8) {
+        self.register(&[0x89], source, destination);
+    }
+
+    fn load(&mut self, reg: u8, memory: Memory) {
+        self.memory(true, &[0x8B], reg, memory);
+    }
+
+    fn store(&mut self, memory: Memory, reg: u8) {
+        self.memory(true, &[0x89], reg, memory);
+    }
+
+    fn add(&mut self, destination: u8, source: u8) {
+        self.register(&[0x01], source, destination);
+    }
+
+    fn add_to_memory(&mut self, memory: Memory, value: i32) {
+        self.memory(true, &[0x81], 0, memory);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn add_immediate(&mut self, reg: u8, value: i32) {
+        self.register(&[0x81], 0, reg);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn imul(&mut self, destination: u8, source: u8) {
+        self.register(&[0x0F, 0xAF], destination, source);
+    }
+
+    fn cmp(&mut self, left: u8, right: u8) {
+        self.register(&[0x39], right, left);
+    }
+
+    fn cmp_memory(&mut self, reg: u8, memory: Memory) {
+        self.memory(true, &[0x3B], reg, memory);
+    }
+
+    fn test(&mut self, reg: u8) {
+        self.register(&[0x85], reg, reg);
+    }
+
+    // rax = 1 if the condition holds, else 0
+    fn set(&mut self, condition: u8) {
+        self.bytes
+            .extend_from_slice(&[0x0F, 0x90 | condition, 0xC0]);
+        self.register(&[0x0F, 0xB6], RAX, RAX);
+    }
+
+    fn cmov(&mut self, condition: u8, destination: u8, source: u8) {
+        self.register(&[0x0F, 0x40 | condition], destination, source);
+    }
+
+    fn cmp_byte_zero(&mut self, memory: Memory) {
+        self.memory(false, &[0x80], 7, memory);
+        self.bytes.push(0);
+    }
+
+    fn jump_if(&mut self, condition: u8, stub: usize) {
+        self.bytes.extend_from_slice(&[0x0F, 0x80 | condition]);
+        self.fixups.push((self.bytes.len(), stub));
+        self.bytes.extend_from_slice(&[0; 4]);
+    }
+
+    fn jump_to(&mut self, reg: u8) {
+        self.register(&[0xFF], 4, reg);
+    }
+
+    fn ret(&mut self) {
+        self.bytes.push(0xC3);
+    }
+}
+
+// How a block leaves: the counter is either a constant or held in r11, and
+// `steps` instructions are added to the count.
+#[derive(Copy, Clone)]
+struct Exit {
+    counter: Option<i64>,
+    steps: i64,
+    reason: i64,
+}
+
+fn emit_exit(assembler: &mut Assembler, exit: Exit) {
+    assembler.store(at(RDI, FRAME_RELATIVE_BASE), R8);
+    if let Some(counter) = exit.counter {
+        assembler.mov_immediate(R11, counter);
+    }
+    assembler.store(at(RDI, FRAME_COUNTER), R11);
+    if exit.steps != 0 {
+        assembler.add_to_memory(at(RDI, FRAME_STEPS), exit.steps as i32);
+    }
+    assembler.mov_immediate(RAX, exit.reason);
+    assembler.store(at(RDI, FRAME_EXIT), RAX);
+    assembler.ret();
+}
+
+// Whether the compiler handles an instruction whose operands are `operands`
+// in a memory of `length` cells.
+fn compilable(instruction: &Instruction, operands: &[i64], length: usize) -> bool {
+    match instruction {
+        Instruction::Input(_) | Instruction::Output(_) | Instruction::Stop => return false,
+        _ => {}
+    }
+    let writes = instruction.write_parameter();
+    instruction
+        .parameters()
+        .iter()
+        .zip(operands)
+        .enumerate()
+        .all(|(index, (&parameter, &operand))| match parameter {
+            Parameter::Immediate => writes != Some(index),
+            Parameter::Position => (0..length as i64).contains(&operand),
+            Parameter::Relative => i32::try_from(operand).is_ok(),
+        })
+}
+
+struct Compiler {
+    assembler: Assembler,
+    exits: Vec<Exit>,
+}
+
+impl Compiler {
+    fn stub(&mut self, exit: Exit) -> usize {
+        self.exits.push(exit);
+        self.exits.len() - 1
+    }
+
+    // Counts the block's instructions, then continues with the block for the
+    // counter in r11 if there is one.
+    fn chain(&mut self, steps: i64) {
+        let end = self.stub(Exit {
+            counter: None,
+            steps: 0,
+            reason: EXIT_END,
+        });
+        let assembler = &mut self.assembler;
+        assembler.add_to_memory(at(RDI, FRAME_STEPS), steps as i32);
+        assembler.cmp(R11, RDX);
+        assembler.jump_if(ABOVE_OR_EQUAL, end);
+        assembler.load(R9, at(RDI, FRAME_TABLE));
+        assembler.load(RAX, indexed(R9, R11, 8, 0));
+        assembler.test(RAX);
+        assembler.jump_if(EQUAL, end);
+        assembler.jump_to(RAX);
+    }
+
+    // Leaves the address of a relative operand in r9, bailing out if it is
+    // outside memory.
+    fn relative_address(&mut self, operand: i64, bail: usize) {
+        self.assembler.mov(R9, R8);
+        self.assembler.add_immediate(R9, operand as i32);
+        self.assembler.cmp(R9, RDX);
+        // unsigned, so negative addresses count as too large
+        self.assembler.jump_if(ABOVE_OR_EQUAL, bail);
+    }
+
+    fn read(&mut self, reg: u8, parameter: Parameter, operand: i64, bail: usize) {
+        match parameter {
+            Parameter::Immediate => self.assembler.mov_immediate(reg, operand),
+            Parameter::Position => self.assembler.load(reg, at(RSI, operand as i32 * 8)),
+            Parameter::Relative => {
+                self.relative_address(operand, bail);
+                self.assembler.load(reg, indexed(RSI, R9, 8, 0));
+            }
+        }
+    }
+
+    // Stores rax, then leaves the block if the cell holds compiled code.
+    fn write(&mut self, parameter: Parameter, operand: i64, bail: usize, deopt: usize) {
+        let (cell, flag) = match parameter {
+            Parameter::Relative => {
+                self.relative_address(operand, bail);
+                (indexed(RSI, R9, 8, 0), indexed(RCX, R9, 1, 0))
+            }
+            _ => (at(RSI, operand as i32 * 8), at(RCX, operand as i32)),
+        };
+        self.assembler.store(cell, RAX);
+        self.assembler.cmp_byte_zero(flag);
+        self.assembler.jump_if(NOT_EQUAL, deopt);
+    }
+}
+
+// Compiles the instructions from `start` up to and including the next jump.
+// Returns the code, the offset where other blocks continue into it and the
+// end of the cells it was built from, or `None` if the first instruction is
+// not compilable.
+fn compile_block(memory: &[i64], start: i64) -> Option<(Vec<u8>, usize, i64)> {
+    // Position operands become 32-bit displacements.
+    let length = memory.len().min(i32::MAX as usize / 8);
+    let mut compiler = Compiler {
+        assembler: Assembler::default(),
+        exits: Vec::new(),
+    };
+    let assembler = &mut compiler.assembler;
+    assembler.load(RSI, at(RDI, FRAME_MEMORY));
+    assembler.load(RDX, at(RDI, FRAME_LENGTH));
+    assembler.load(RCX, at(RDI, FRAME_CODE));
+    assembler.load(R8, at(RDI, FRAME_RELATIVE_BASE));
+
+    let chain = assembler.bytes.len();
+    let over_budget = compiler.stub(Exit {
+        counter: Some(start),
+        steps: 0,
+        reason: EXIT_BAIL,
+    });
+    let assembler = &mut compiler.assembler;
+    assembler.load(RAX, at(RDI, FRAME_STEPS));
+    // the length of the block, filled in at the end
+    assembler.add_immediate(RAX, 0);
+    let length_position = assembler.bytes.len() - 4;
+    assembler.cmp_memory(RAX, at(RDI, FRAME_BUDGET));
+    assembler.jump_if(GREATER, over_budget);
+
+    let mut address = start;
+    let mut count = 0;
+    let mut ended = false;
+    while count < MAX_BLOCK_LENGTH && !ended {
+        let instruction = match memory
+            .get(address as usize)
+            .and_then(|&word| Instruction::new(word))
+        {
+            Some(instruction) => instruction,
+            None => break,
+        };
+        let next = address + instruction.len();
+        let operands = match memory.get(address as usize + 1..next as usize) {
+            Some(operands) => operands,
+            None => break,
+        };
+        if !compilable(&instruction, operands, length) {
+            break;
+        }
+        let bail = compiler.stub(Exit {
+            counter: Some(address),
+            steps: count as i64,
+            reason: EXIT_BAIL,
+        });
+        let deopt = compiler.stub(Exit {
+            counter: Some(next),
+            steps: count as i64 + 1,
+            reason: EXIT_DEOPT,
+        });
+        let parameters = instruction.parameters();
+        match instruction {
+            Instruction::Addition(..)
+            | Instruction::Multiplication(..)
+            | Instruction::LessThan(..)
+            | Instruction::Equals(..) => {
+                compiler.read(RAX, parameters[0], operands[0], bail);
+                compiler.read(R10, parameters[1], operands[1], bail);
+                let assembler = &mut compiler.assembler;
+                match instruction {
+                    Instruction::Addition(..) => {
+                        assembler.add(RAX, R10);
+                        assembler.jump_if(OVERFLOW, bail);
+                    }
+                    Instruction::Multiplication(..) => {
+                        assembler.imul(RAX, R10);
+                        assembler.jump_if(OVERFLOW, bail);
+                    }
+                    Instruction::LessThan(..) => {
+                        assembler.cmp(RAX, R10);
+                        assembler.set(LESS);
+                    }
+                    _ => {
+                        assembler.cmp(RAX, R10);
+                        assembler.set(EQUAL);
+                    }
+                }
+                compiler.write(parameters[2], operands[2], bail, deopt);
+            }
+            Instruction::AdjustRelativeBase(_) => {
+                compiler.read(RAX, parameters[0], operands[0], bail);
+                let assembler = &mut compiler.assembler;
+                assembler.mov(R9, R8);
+                assembler.add(R9, RAX);
+                assembler.jump_if(OVERFLOW, bail);
+                assembler.mov(R8, R9);
+            }
+            Instruction::JumpIfTrue(..) | Instruction::JumpIfFalse(..) => {
+                compiler.read(RAX, parameters[0], operands[0], bail);
+                compiler.read(R10, parameters[1], operands[1], bail);
+                let assembler = &mut compiler.assembler;
+                assembler.mov_immediate(R11, next);
+                assembler.test(RAX);
+                let taken = match instruction {
+                    Instruction::JumpIfTrue(..) => NOT_EQUAL,
+                    _ => EQUAL,
+                };
+                assembler.cmov(taken, R11, R10);
+                compiler.chain(count as i64 + 1);
+                ended = true;
+            }
+            _ => unreachable!(),
+        }
+        address = next;
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+    if !ended {
+        compiler.assembler.mov_immediate(R11, address);
+        compiler.chain(count as i64);
+    }
+
+    let mut stubs = Vec::with_capacity(compiler.exits.len());
+    for &exit in &compiler.exits {
+        stubs.push(compiler.assembler.bytes.len());
+        emit_exit(&mut compiler.assembler, exit);
+    }
+    let mut assembler = compiler.assembler;
+    let length = (count as i32).to_le_bytes();
+    assembler.bytes[length_position..length_position + 4].copy_from_slice(&length);
+    for &(position, stub) in &assembler.fixups {
+        let displacement = (stubs[stub] - (position + 4)) as i32;
+        assembler.bytes[position..position + 4].copy_from_slice(&displacement.to_le_bytes());
+    }
+    Some((assembler.bytes, chain, address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Jit;
+    use crate::computer::{parse_program, Fault};
+    use crate::conformance;
+    use crate::engine::IntcodeEngine;
+
+    #[test]
+    fn test_conformance() {
+        let corpus = conformance::corpus();
+        for &threshold in &[1, 2, super::HOT_THRESHOLD] {
+            let mut jit = Jit::default().with_threshold(threshold);
+            let failures = conformance::check(&mut jit, &corpus);
+            assert!(failures.is_empty(), "{}: {:?}", threshold, failures);
+        }
+    }
+
+    #[test]
+    fn test_compiled_loops() {
+        // day 9 computes its answer in a long, recursive loop
+        let program = parse_program(include_str!("../input/2019/day9.txt")).unwrap();
+        let mut jit = Jit::new(&program);
+        jit.provide_input(2);
+        let mut computer = crate::computer::Computer::new(&program);
+        computer.provide_input(2);
+        assert_eq!(jit.run_to_halt(), computer.run_to_halt());
+        assert!(jit.compiled_blocks() > 0);
+        assert!(!jit.deoptimized());
+
+        // a loop that never ends still stops exactly at the step limit
+        let mut jit = Jit::new(&[1001, 7, 1, 7, 1105, 1, 0, 0]);
+        jit.set_step_limit(Some(1001));
+        assert_eq!(jit.run_until_io(), Err(Fault::StepLimit(1001)));
+        assert_eq!(jit.memory(7), 501);
+        assert_eq!(jit.counter(), 4);
+
+        // a compiled loop that overflows faults where the interpreter does
+        let program = [1002, 7, 2, 7, 1105, 1, 0, 1];
+        let mut jit = Jit::new(&program).with_threshold(1);
+        assert_eq!(jit.run_until_io(), Err(Fault::Overflow { address: 0 }));
+        assert_eq!(jit.memory(7), 1 << 62);
+        assert!(jit.compiled_blocks() > 0);
+    }
+
+    #[test]
+    fn test_deoptimization() {
+        // The diagnostic program patches an instruction before reaching it,
+        // so the patched code is what gets compiled.
+        let program = parse_program(include_str!("../input/2019/day5.txt")).unwrap();
+        let mut jit = Jit::new(&program).with_threshold(1);
+        jit.provide_input(5);
+        assert_eq!(jit.run_to_halt(), Ok(vec![9386583]));
+        assert!(!jit.deoptimized());
+
+        // adds 5, 6, 7, ... to a total until it reaches 30, by incrementing
+        // the immediate operand of its own add
+        let program = vec![
+            1001, 20, 5, 20, 1001, 2, 1, 2, 1007, 20, 30, 21, 1005, 21, 0, 4, 20, 99, 0, 0, 0, 0,
+        ];
+        let mut jit = Jit::new(&program).with_threshold(1);
+        assert_eq!(jit.run_to_halt(), Ok(vec![35]));
+        assert!(jit.deoptimized());
+    }
+}
@@ -2,7 +2,15 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod adventure;
+pub mod amplifiers;
+pub mod arcade;
+pub mod async_computer;
+pub mod compiler;
 pub mod computer;
+pub mod conformance;
+pub mod container;
+pub mod cycle;
 pub mod day01;
 pub mod day02;
 pub mod day03;
@@ -15,6 +23,20 @@ pub mod day09;
 pub mod day10;
 pub mod day11;
 pub mod day12;
+pub mod decompiler;
+pub mod droid;
+pub mod engine;
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+pub mod jit;
+pub mod linter;
+pub mod memory_diff;
+pub mod memory_map;
+pub mod monitor;
+pub mod optimizer;
+pub mod pool;
+pub mod scaffold;
+pub mod springscript;
+pub mod symbolic;
 
 #[macro_use]
 extern crate aoc_runner_derive;
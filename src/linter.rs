@@ -0,0 +1,230 @@
+// Static checks for Intcode programs, run before executing a program that may
+// be broken or hostile.
+//
+// Only code reachable from address 0 through fall-through and immediate-mode
+// jump targets is checked. Cells written through a constant position-mode
+// address are treated as self-modifying code: they are not decoded, and
+// analysis stops there.
+
+use crate::computer::{Instruction, Parameter};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Problem {
+    InvalidInstruction { address: i64, word: i64 },
+    ImmediateWrite { address: i64 },
+    NegativeAddress { address: i64, target: i64 },
+    JumpOutOfRange { address: i64, target: i64 },
+    FallsOffEnd { address: i64 },
+}
+
+impl Problem {
+    pub fn address(&self) -> i64 {
+        match *self {
+            Problem::InvalidInstruction { address, .. }
+            | Problem::ImmediateWrite { address }
+            | Problem::NegativeAddress { address, .. }
+            | Problem::JumpOutOfRange { address, .. }
+            | Problem::FallsOffEnd { address } => address,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::InvalidInstruction { address, word } => {
+                write!(f, "{}: invalid instruction {}", address, word)
+            }
+            Problem::ImmediateWrite { address } => {
+                write!(f, "{}: writes through an immediate-mode parameter", address)
+            }
+            Problem::NegativeAddress { address, target } => {
+                write!(f, "{}: accesses negative address {}", address, target)
+            }
+            Problem::JumpOutOfRange { address, target } => {
+                write!(f, "{}: jumps outside the program to {}", address, target)
+            }
+            Problem::FallsOffEnd { address } => {
+                write!(f, "{}: runs off the end of the program", address)
+            }
+        }
+    }
+}
+
+pub fn lint(program: &[i64]) -> Vec<Problem> {
+    // Self-modified cells are only discovered while walking the program, so
+    // walk again until the set of written cells stops growing.
+    let mut modified = HashSet::new();
+    loop {
+        let (problems, writes) = walk(program, &modified);
+        if writes.is_subset(&modified) {
+            let mut problems: Vec<Problem> = problems.into_iter().collect();
+            problems.sort_by_key(Problem::address);
+            return problems;
+        }
+        modified.extend(writes);
+    }
+}
+
+fn walk(program: &[i64], modified: &HashSet<i64>) -> (BTreeSet<Problem>, HashSet<i64>) {
+    let end = program.len() as i64;
+    let word = |address: i64| {
+        if address >= 0 && address < end {
+            program[address as usize]
+        } else {
+            0
+        }
+    };
+    let mut problems = BTreeSet::new();
+    let mut writes = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut worklist = vec![0];
+
+    while let Some(address) = worklist.pop() {
+        if !visited.insert(address) || modified.contains(&address) {
+            continue;
+        }
+        let instruction = match Instruction::new(word(address)) {
+            Some(instruction) => instruction,
+            None => {
+                problems.insert(Problem::InvalidInstruction {
+                    address,
+                    word: word(address),
+                });
+                continue;
+            }
+        };
+        if address + instruction.len() > end {
+            problems.insert(Problem::FallsOffEnd { address });
+        }
+
+        for (i, parameter) in instruction.parameters().into_iter().enumerate() {
+            let cell = address + 1 + i as i64;
+            let is_write = instruction.write_parameter() == Some(i);
+            match parameter {
+                Parameter::Immediate if is_write => {
+                    problems.insert(Problem::ImmediateWrite { address });
+                }
+                Parameter::Position if !modified.contains(&cell) => {
+                    let target = word(cell);
+                    if target < 0 {
+                        problems.insert(Problem::NegativeAddress { address, target });
+                    } else if is_write {
+                        writes.insert(target);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let next = address + instruction.len();
+        match instruction {
+            Instruction::Stop => {}
+            Instruction::JumpIfTrue(condition, target)
+            | Instruction::JumpIfFalse(condition, target) => {
+                let jumps_on_true = matches!(instruction, Instruction::JumpIfTrue(..));
+                let taken = match condition {
+                    Parameter::Immediate if !modified.contains(&(address + 1)) => {
+                        Some((word(address + 1) != 0) == jumps_on_true)
+                    }
+                    _ => None,
+                };
+                if taken != Some(false)
+                    && target == Parameter::Immediate
+                    && !modified.contains(&(address + 2))
+                {
+                    let target = word(address + 2);
+                    if target < 0 || target >= end {
+                        problems.insert(Problem::JumpOutOfRange { address, target });
+                    } else {
+                        worklist.push(target);
+                    }
+                }
+                if taken != Some(true) {
+                    fall_through(&mut problems, &mut worklist, address, next, end);
+                }
+            }
+            _ => fall_through(&mut problems, &mut worklist, address, next, end),
+        }
+    }
+    (problems, writes)
+}
+
+fn fall_through(
+    problems: &mut BTreeSet<Problem>,
+    worklist: &mut Vec<i64>,
+    address: i64,
+    next: i64,
+    end: i64,
+) {
+    if next >= end {
+        problems.insert(Problem::FallsOffEnd { address });
+    } else {
+        worklist.push(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Problem;
+
+    #[test]
+    fn test_clean_programs() {
+        let program = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        assert_eq!(super::lint(&program), vec![]);
+
+        let program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        assert_eq!(super::lint(&program), vec![]);
+    }
+
+    #[test]
+    fn test_problems() {
+        let program = vec![11101, 1, 2, 3, 42];
+        assert_eq!(
+            super::lint(&program),
+            vec![
+                Problem::ImmediateWrite { address: 0 },
+                Problem::InvalidInstruction {
+                    address: 4,
+                    word: 42
+                },
+            ]
+        );
+
+        let program = vec![1, -1, 11, 11, 1105, 1, 100, 1105, 1, -5, 104, 0];
+        assert_eq!(
+            super::lint(&program),
+            vec![
+                Problem::NegativeAddress {
+                    address: 0,
+                    target: -1
+                },
+                Problem::JumpOutOfRange {
+                    address: 4,
+                    target: 100
+                },
+            ]
+        );
+
+        let program = vec![1106, 0, 4, 99, 104, 7];
+        assert_eq!(
+            super::lint(&program),
+            vec![Problem::FallsOffEnd { address: 4 }]
+        );
+        assert_eq!(
+            super::lint(&program)[0].to_string(),
+            "4: runs off the end of the program"
+        );
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // 1100 is patched to 1101 before it runs
+        let program = vec![1101, 1, 1100, 4, 1100, 0, 0, 9, 99, 0];
+        assert_eq!(super::lint(&program), vec![]);
+    }
+}
@@ -0,0 +1,225 @@
+// Compares the memory of two Intcode machines cell by cell, to find where a
+// program keeps a variable: snapshot a game before and after the score goes
+// up, or run the same program on two inputs, and look at what differs.
+//
+// Memory is compared by value, as machine equality does, so a cell that was
+// never touched and one that holds 0 are the same. A `WriteTracker` remembers
+// which instruction last wrote each cell; when the second machine has one,
+// every change says where it came from.
+//
+// The report has one line per changed cell, in address order:
+//
+//     <address>: <old> -> <new>
+//     <address>: <old> -> <new> (written by <instruction address>)
+
+use crate::computer::{Computer, Fault, Limits, Observer, Status};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Clone, Debug, Default)]
+pub struct WriteTracker {
+    instruction: i64,
+    writers: HashMap<i64, i64>,
+}
+
+impl WriteTracker {
+    // Address of the instruction that last wrote `address`.
+    pub fn last_writer(&self, address: i64) -> Option<i64> {
+        self.writers.get(&address).copied()
+    }
+}
+
+impl Observer for WriteTracker {
+    fn on_fetch(&mut self, address: i64, _word: i64) {
+        self.instruction = address;
+    }
+
+    fn on_write(&mut self, address: i64, _value: i64) {
+        self.writers.insert(address, self.instruction);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Change {
+    pub address: i64,
+    pub old: i64,
+    pub new: i64,
+    pub writer: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryDiff {
+    pub changes: Vec<Change>,
+}
+
+impl MemoryDiff {
+    pub fn new<O: Observer, P: Observer>(before: &Computer<O>, after: &Computer<P>) -> MemoryDiff {
+        let old = before.nonzero_cells();
+        let new = after.nonzero_cells();
+        let (mut i, mut j) = (0, 0);
+        let mut changes = Vec::new();
+        while i < old.len() || j < new.len() {
+            let address = match (old.get(i), new.get(j)) {
+                (Some(&(a, _)), Some(&(b, _))) => a.min(b),
+                (Some(&(a, _)), None) => a,
+                (None, Some(&(b, _))) => b,
+                (None, None) => unreachable!(),
+            };
+            let take = |cells: &[(i64, i64)], index: &mut usize| match cells.get(*index) {
+                Some(&(cell, value)) if cell == address => {
+                    *index += 1;
+                    value
+                }
+                _ => 0,
+            };
+            let (old, new) = (take(&old, &mut i), take(&new, &mut j));
+            if old != new {
+                changes.push(Change {
+                    address,
+                    old,
+                    new,
+                    writer: None,
+                });
+            }
+        }
+        MemoryDiff { changes }
+    }
+
+    // Notes which instruction last wrote each changed cell.
+    pub fn with_writers(mut self, tracker: &WriteTracker) -> MemoryDiff {
+        for change in &mut self.changes {
+            change.writer = tracker.last_writer(change.address);
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn change(&self, address: i64) -> Option<&Change> {
+        self.changes
+            .binary_search_by_key(&address, |change| change.address)
+            .ok()
+            .map(|index| &self.changes[index])
+    }
+}
+
+impl fmt::Display for MemoryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            write!(f, "{}: {} -> {}", change.address, change.old, change.new)?;
+            if let Some(writer) = change.writer {
+                write!(f, " (written by {})", writer)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// Runs `program` on `input` within `limits` until it halts or asks for more
+// input than it was given, so interactive programs can be compared at the
+// point they stop.
+pub fn run(
+    program: &[i64],
+    input: &[i64],
+    limits: Limits,
+) -> Result<Computer<WriteTracker>, Fault> {
+    let mut computer =
+        Computer::with_observer(program, WriteTracker::default()).with_limits(limits);
+    for &value in input {
+        computer.push_input(value);
+    }
+    loop {
+        match computer.resume()? {
+            Status::Output(_) => {}
+            Status::NeedsInput | Status::Halted => return Ok(computer),
+        }
+    }
+}
+
+// Runs `program` once on each input and compares where the runs end up,
+// crediting each change to the instruction that wrote it in the second run.
+pub fn diff_runs(
+    program: &[i64],
+    first: &[i64],
+    second: &[i64],
+    limits: Limits,
+) -> Result<MemoryDiff, Fault> {
+    let before = run(program, first, limits)?;
+    let after = run(program, second, limits)?;
+    Ok(MemoryDiff::new(&before, &after).with_writers(after.observer()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Change, MemoryDiff};
+    use crate::computer::{parse_program, Computer, Fault, Limits, Status};
+
+    #[test]
+    fn test_snapshots() {
+        // counts its inputs in cell 17 and keeps the last one at 1000
+        let program = vec![
+            3, 1000, 1001, 17, 1, 17, 1008, 1000, 0, 16, 1006, 16, 0, 4, 17, 99, 0, 0,
+        ];
+        let mut computer = Computer::with_observer(&program, super::WriteTracker::default());
+        computer.push_input(5);
+        computer.push_input(7);
+        assert_eq!(computer.resume(), Ok(Status::NeedsInput));
+        let snapshot = computer.clone();
+        assert!(MemoryDiff::new(&snapshot, &computer).is_empty());
+
+        computer.push_input(0);
+        assert_eq!(computer.resume(), Ok(Status::Output(3)));
+        let diff = MemoryDiff::new(&snapshot, &computer).with_writers(computer.observer());
+        assert_eq!(
+            diff.to_string(),
+            "16: 0 -> 1 (written by 6)\n17: 2 -> 3 (written by 2)\n1000: 7 -> 0 (written by 0)\n"
+        );
+        assert_eq!(
+            diff.change(1000),
+            Some(&Change {
+                address: 1000,
+                old: 7,
+                new: 0,
+                writer: Some(0),
+            })
+        );
+        assert_eq!(diff.change(15), None);
+
+        // the other way round, and without writers
+        let diff = MemoryDiff::new(&computer, &Computer::new(&program));
+        assert_eq!(diff.to_string(), "16: 1 -> 0\n17: 3 -> 0\n");
+    }
+
+    #[test]
+    fn test_runs() {
+        // The diagnostic program patches the 1100 at cell 6 with the ID of
+        // the system under test, and keeps its diagnostic code at 223.
+        let program = parse_program(include_str!("../input/2019/day5.txt")).unwrap();
+        let diff = super::diff_runs(&program, &[1], &[5], Limits::default()).unwrap();
+        assert_eq!(
+            diff.to_string(),
+            "0: 3 -> 314 (written by 304)
+6: 1101 -> 1105 (written by 2)
+223: 16489636 -> 9386583 (written by 670)
+224: 4 -> 0 (written by 659)
+225: 139 -> 20 (written by 300)
+"
+        );
+        assert!(super::diff_runs(&program, &[1], &[1], Limits::default())
+            .unwrap()
+            .is_empty());
+
+        // a program that never halts runs out of steps instead of hanging
+        let limits = Limits {
+            max_steps: Some(100),
+            ..Limits::default()
+        };
+        assert_eq!(
+            super::run(&[1105, 1, 0], &[], limits).err(),
+            Some(Fault::StepLimit(100))
+        );
+    }
+}
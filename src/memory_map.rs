@@ -0,0 +1,328 @@
+// Works out which cells of a program are code and which are data by running
+// it, since Intcode keeps both in the same memory and static guesses go wrong
+// as soon as a program reads its own instructions or patches them.
+//
+// A `Profiler` watches a machine and records, for every cell, whether it was
+// executed (as an opcode or as a parameter of an executed instruction), read
+// as data, or written. A cell that is both executed and written is
+// self-modifying code.
+//
+// The report lists ranges of consecutive cells with the same access pattern,
+// one per line:
+//
+//     <first>..<last> <flags> <label>
+//
+// `first` and `last` are inclusive decimal addresses. `flags` has three
+// characters, `x` for executed, `r` for read and `w` for written, each
+// replaced by `-` if the cell was not accessed that way. The label follows
+// from the flags:
+//
+//     ---  unused          cell of the initial image that was never touched
+//     x--  code
+//     xr-  code-read       code that is also read as data
+//     x-w  self-modifying  code that is written, whether before or after it
+//     xrw  self-modifying  runs
+//     -r-  const           data that is only read
+//     --w  write-only
+//     -rw  data
+//
+// Cells beyond the initial image appear only if they were accessed. Lines
+// starting with `#` are comments; the report starts with one giving the size
+// of the image.
+
+use crate::computer::{Computer, Fault, Instruction, Limits, Observer, Status};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    pub executed: bool,
+    pub read: bool,
+    pub written: bool,
+}
+
+impl Access {
+    pub fn is_self_modifying(&self) -> bool {
+        self.executed && self.written
+    }
+
+    pub fn label(&self) -> &'static str {
+        match (self.executed, self.read, self.written) {
+            (false, false, false) => "unused",
+            (true, false, false) => "code",
+            (true, true, false) => "code-read",
+            (true, _, true) => "self-modifying",
+            (false, true, false) => "const",
+            (false, false, true) => "write-only",
+            (false, true, true) => "data",
+        }
+    }
+
+    fn flags(&self) -> String {
+        [(self.executed, 'x'), (self.read, 'r'), (self.written, 'w')]
+            .iter()
+            .map(|&(set, flag)| if set { flag } else { '-' })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    cells: BTreeMap<i64, Access>,
+}
+
+impl Profiler {
+    pub fn access(&self, address: i64) -> Access {
+        self.cells.get(&address).copied().unwrap_or_default()
+    }
+
+    fn mark(&mut self, address: i64) -> &mut Access {
+        self.cells.entry(address).or_default()
+    }
+}
+
+impl Observer for Profiler {
+    fn on_fetch(&mut self, address: i64, word: i64) {
+        let length = Instruction::new(word).map_or(1, |instruction| instruction.len());
+        for cell in address..address + length {
+            self.mark(cell).executed = true;
+        }
+    }
+
+    fn on_read(&mut self, address: i64, _value: i64) {
+        self.mark(address).read = true;
+    }
+
+    fn on_write(&mut self, address: i64, _value: i64) {
+        self.mark(address).written = true;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub first: i64,
+    pub last: i64,
+    pub access: Access,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryMap {
+    pub image_size: i64,
+    pub regions: Vec<Region>,
+}
+
+impl MemoryMap {
+    pub fn new(image_size: usize, profiler: &Profiler) -> MemoryMap {
+        let image_size = image_size as i64;
+        let mut regions: Vec<Region> = Vec::new();
+        let mut add = |address: i64, access: Access| match regions.last_mut() {
+            Some(region) if region.last + 1 == address && region.access == access => {
+                region.last = address;
+            }
+            _ => regions.push(Region {
+                first: address,
+                last: address,
+                access,
+            }),
+        };
+        for address in 0..image_size {
+            add(address, profiler.access(address));
+        }
+        for (&address, &access) in profiler.cells.range(image_size..) {
+            add(address, access);
+        }
+        MemoryMap {
+            image_size,
+            regions,
+        }
+    }
+
+    pub fn access(&self, address: i64) -> Access {
+        self.regions
+            .iter()
+            .find(|region| region.first <= address && address <= region.last)
+            .map_or_else(Access::default, |region| region.access)
+    }
+
+    pub fn self_modifying(&self) -> impl Iterator<Item = &Region> {
+        self.regions
+            .iter()
+            .filter(|region| region.access.is_self_modifying())
+    }
+}
+
+impl fmt::Display for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# image size {}", self.image_size)?;
+        for region in &self.regions {
+            writeln!(
+                f,
+                "{}..{} {} {}",
+                region.first,
+                region.last,
+                region.access.flags(),
+                region.access.label()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MapError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl FromStr for MemoryMap {
+    type Err = MapError;
+
+    // Reads a report back. Comments other than the image size are ignored.
+    fn from_str(text: &str) -> Result<MemoryMap, MapError> {
+        let mut image_size = None;
+        let mut regions = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: &str| MapError {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let line = line.trim();
+            if let Some(comment) = line.strip_prefix('#') {
+                if let Some(size) = comment.trim().strip_prefix("image size ") {
+                    image_size = Some(size.parse().map_err(|_| error("invalid image size"))?);
+                }
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (range, flags, label) = match fields[..] {
+                [range, flags, label] => (range, flags, label),
+                _ => return Err(error("expected a range, flags and a label")),
+            };
+            let (first, last) = range
+                .split_once("..")
+                .and_then(|(first, last)| Some((first.parse().ok()?, last.parse().ok()?)))
+                .filter(|(first, last)| first <= last)
+                .ok_or_else(|| error("invalid range"))?;
+            let access = match flags.as_bytes() {
+                [x, r, w]
+                    if [(*x, b'x'), (*r, b'r'), (*w, b'w')]
+                        .iter()
+                        .all(|&(c, flag)| c == flag || c == b'-') =>
+                {
+                    Access {
+                        executed: *x == b'x',
+                        read: *r == b'r',
+                        written: *w == b'w',
+                    }
+                }
+                _ => return Err(error("invalid flags")),
+            };
+            if access.label() != label {
+                return Err(error("label does not match the flags"));
+            }
+            regions.push(Region {
+                first,
+                last,
+                access,
+            });
+        }
+        let image_size = image_size.ok_or(MapError {
+            line: 1,
+            message: "missing image size".to_string(),
+        })?;
+        Ok(MemoryMap {
+            image_size,
+            regions,
+        })
+    }
+}
+
+// Runs `program` on `input` within `limits` until it halts and maps its memory.
+pub fn profile(program: &[i64], input: &[i64], limits: Limits) -> Result<MemoryMap, Fault> {
+    let mut computer = Computer::with_observer(program, Profiler::default()).with_limits(limits);
+    for &value in input {
+        computer.push_input(value);
+    }
+    match computer.run_until_blocked()? {
+        (_, Status::NeedsInput) => Err(Fault::MissingInput {
+            address: computer.counter(),
+        }),
+        _ => Ok(MemoryMap::new(program.len(), computer.observer())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Access, MemoryMap};
+    use crate::computer::{parse_program, Fault, Limits};
+
+    #[test]
+    fn test_profile() {
+        // reads cell 13, patches the 1100 at 6 into 1101, writes to 1000
+        let program = vec![3, 13, 1, 13, 6, 6, 1100, 5, 6, 1000, 99, 0, 0, 0, 7];
+        let map = super::profile(&program, &[1], Limits::default()).unwrap();
+        assert_eq!(
+            map.to_string(),
+            "# image size 15
+0..5 x-- code
+6..6 xrw self-modifying
+7..10 x-- code
+11..12 --- unused
+13..13 -rw data
+14..14 --- unused
+1000..1000 --w write-only
+"
+        );
+        assert_eq!(map.self_modifying().count(), 1);
+        assert_eq!(map.access(1000).label(), "write-only");
+        assert_eq!(map.access(500), Access::default());
+        assert_eq!(map.to_string().parse::<MemoryMap>(), Ok(map));
+
+        // a program that never halts runs out of steps instead of hanging
+        let limits = Limits {
+            max_steps: Some(100),
+            ..Limits::default()
+        };
+        assert_eq!(
+            super::profile(&[1105, 1, 0], &[], limits),
+            Err(Fault::StepLimit(100))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = "0..3 x-- code\n4..2 -r- const\n"
+            .parse::<MemoryMap>()
+            .unwrap_err();
+        assert_eq!(error.to_string(), "line 2: invalid range");
+        let error = "# comment\n0..3 x-- data\n"
+            .parse::<MemoryMap>()
+            .unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!("0..3 xw- code\n".parse::<MemoryMap>().is_err());
+    }
+
+    #[test]
+    fn test_diagnostic_program() {
+        // The diagnostic program turns the 1100 at cell 6 into an addition
+        // with its first input.
+        let program = parse_program(include_str!("../input/2019/day5.txt")).unwrap();
+        let map = super::profile(&program, &[1], Limits::default()).unwrap();
+        let patched: Vec<i64> = map.self_modifying().map(|region| region.first).collect();
+        assert!(patched.contains(&6));
+        assert!(map.access(225).written);
+        assert_eq!(map.access(0).label(), "code");
+    }
+}
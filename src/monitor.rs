@@ -0,0 +1,580 @@
+// A full-screen terminal view of an Intcode machine as it runs, drawn with
+// plain ANSI escapes.
+//
+// The screen shows the registers, the instructions around the counter (the
+// ones just executed above it, the ones coming up below), a memory pane that
+// highlights recent writes, the input queue and the output so far:
+//
+//     space   pause or resume      >  <   run faster or slower
+//     s       execute one step     h      memory in hex or decimal
+//     [  ]    scroll memory        f      memory follows the last write
+//     0-9 -   type an input value  enter  queue it
+//     q       quit, as does ctrl-c
+//
+// `Monitor` holds everything the view needs and draws a frame with `render`;
+// `watch` puts the terminal in raw mode and drives it from the keyboard.
+
+use crate::computer::{decode_ascii, Computer, Fault, Instruction, Observer, Parameter};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Writes this many steps old or newer are highlighted.
+const RECENT: u64 = 16;
+const HISTORY: usize = 64;
+const FRAME: Duration = Duration::from_millis(40);
+const MAX_SPEED: u64 = 1 << 20;
+// Cells per memory row, and the width of each.
+const COLUMNS: i64 = 4;
+const CELL_WIDTH: usize = 10;
+const MEMORY_WIDTH: usize = 7 + COLUMNS as usize * (CELL_WIDTH + 1);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Up,
+    Down,
+}
+
+// Remembers what the view highlights: when each cell was last written, and
+// everything the program has output.
+#[derive(Default)]
+struct Recorder {
+    clock: u64,
+    writes: HashMap<i64, u64>,
+    last_write: Option<i64>,
+    outputs: Vec<i64>,
+}
+
+impl Observer for Recorder {
+    fn on_fetch(&mut self, _address: i64, _word: i64) {
+        self.clock += 1;
+    }
+
+    fn on_write(&mut self, address: i64, _value: i64) {
+        self.writes.insert(address, self.clock);
+        self.last_write = Some(address);
+    }
+
+    fn on_output(&mut self, value: i64) {
+        self.outputs.push(value);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum State {
+    Running,
+    Paused,
+    WaitingForInput,
+    Halted,
+    Faulted(Fault),
+}
+
+pub struct Monitor {
+    computer: Computer<Recorder>,
+    // Input not yet handed to the machine; it gets one value at a time, when
+    // it is about to read it.
+    inputs: VecDeque<i64>,
+    typed: String,
+    history: VecDeque<i64>,
+    paused: bool,
+    waiting: bool,
+    finished: Option<State>,
+    // Steps per second.
+    speed: u64,
+    hex: bool,
+    // First memory row shown, or `None` to follow the last write.
+    scroll: Option<i64>,
+    quit: bool,
+}
+
+impl Monitor {
+    pub fn new(program: &[i64]) -> Monitor {
+        Monitor {
+            computer: Computer::with_observer(program, Recorder::default()),
+            inputs: VecDeque::new(),
+            typed: String::new(),
+            history: VecDeque::with_capacity(HISTORY),
+            paused: true,
+            waiting: false,
+            finished: None,
+            speed: 8,
+            hex: false,
+            scroll: None,
+            quit: false,
+        }
+    }
+
+    pub fn with_inputs(mut self, inputs: &[i64]) -> Monitor {
+        self.inputs.extend(inputs);
+        self
+    }
+
+    pub fn state(&self) -> State {
+        if let Some(state) = &self.finished {
+            state.clone()
+        } else if self.waiting {
+            State::WaitingForInput
+        } else if self.paused {
+            State::Paused
+        } else {
+            State::Running
+        }
+    }
+
+    pub fn speed(&self) -> u64 {
+        self.speed
+    }
+
+    pub fn outputs(&self) -> &[i64] {
+        &self.computer.observer().outputs
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    pub fn handle(&mut self, key: Key) {
+        match key {
+            Key::Char('q') | Key::Char('\x03') => self.quit = true,
+            Key::Char(' ') => self.paused = !self.paused,
+            Key::Char('s') => {
+                self.paused = true;
+                self.step();
+            }
+            Key::Char('>') => self.speed = (self.speed * 2).min(MAX_SPEED),
+            Key::Char('<') => self.speed = (self.speed / 2).max(1),
+            Key::Char('h') => self.hex = !self.hex,
+            Key::Char('f') => self.scroll = None,
+            Key::Char('[') | Key::Up => self.scroll = Some(self.memory_top().saturating_sub(1)),
+            Key::Char(']') | Key::Down => self.scroll = Some(self.memory_top() + 1),
+            Key::Char(c) if c.is_ascii_digit() || c == '-' => self.typed.push(c),
+            Key::Backspace => {
+                self.typed.pop();
+            }
+            Key::Enter => {
+                if let Ok(value) = self.typed.parse() {
+                    self.inputs.push_back(value);
+                    self.waiting = false;
+                }
+                self.typed.clear();
+            }
+            _ => {}
+        }
+    }
+
+    // Executes one instruction unless the machine has stopped or is waiting
+    // for input nobody has typed yet.
+    pub fn step(&mut self) {
+        if self.finished.is_some() {
+            return;
+        }
+        let counter = self.computer.counter();
+        if self.computer.memory(counter) % 100 == 3 {
+            match self.inputs.pop_front() {
+                Some(value) => self.computer.push_input(value),
+                None => {
+                    self.waiting = true;
+                    return;
+                }
+            }
+        }
+        self.waiting = false;
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(counter);
+        match self.computer.step() {
+            Ok(Some(crate::computer::Status::Halted)) => self.finished = Some(State::Halted),
+            Ok(_) => {}
+            Err(fault) => self.finished = Some(State::Faulted(fault)),
+        }
+    }
+
+    // Runs `steps` instructions, stopping early if the machine can not go on.
+    pub fn advance(&mut self, steps: u64) {
+        for _ in 0..steps {
+            self.step();
+            if self.finished.is_some() || self.waiting {
+                break;
+            }
+        }
+    }
+
+    fn memory_top(&self) -> i64 {
+        self.scroll
+            .unwrap_or_else(|| {
+                let focus = self
+                    .computer
+                    .observer()
+                    .last_write
+                    .unwrap_or_else(|| self.computer.counter());
+                focus / COLUMNS - 2
+            })
+            .max(0)
+    }
+
+    // Draws the whole screen for a terminal `width` columns wide and
+    // `height` lines high, starting from the top left corner.
+    pub fn render(&self, width: usize, height: usize) -> String {
+        let width = width.max(80);
+        let height = height.max(12);
+        let code_width = (width - MEMORY_WIDTH - 3).min(40);
+        let mut screen = String::from("\x1b[H");
+        let state = match self.state() {
+            State::Running => "running".to_string(),
+            State::Paused => "paused".to_string(),
+            State::WaitingForInput => "waiting for input".to_string(),
+            State::Halted => "halted".to_string(),
+            State::Faulted(fault) => format!("fault: {}", fault),
+        };
+        let title = format!(
+            " intcode monitor | {} | {} steps/s | space pause  s step  </> speed  h hex  q quit",
+            state, self.speed
+        );
+        screen += &format!("\x1b[7m{}\x1b[0m\x1b[K\r\n", fit(&title, width));
+        let registers = format!(
+            " counter {}   relative base {}   steps {}",
+            self.computer.counter(),
+            self.computer.relative_base(),
+            self.computer.steps()
+        );
+        screen += &format!("{}\x1b[K\r\n\x1b[K\r\n", fit(&registers, width));
+
+        let rows = height - 7;
+        let code = self.code_lines(rows);
+        let memory = self.memory_lines(rows);
+        for ((text, style), memory) in code.iter().zip(&memory) {
+            screen += &format!(
+                "{}{}\x1b[0m | {}\x1b[K\r\n",
+                style,
+                fit(text, code_width),
+                memory
+            );
+        }
+
+        let queued: Vec<String> = self.inputs.iter().map(|value| value.to_string()).collect();
+        let input = format!(" input  [{}] > {}_", queued.join(", "), self.typed);
+        screen += &format!("\x1b[K\r\n{}\x1b[K\r\n", fit(&input, width));
+        let outputs = self.outputs();
+        let shown: Vec<String> = outputs.iter().map(|value| value.to_string()).collect();
+        let mut output = shown.join(", ");
+        let room = width.saturating_sub(9);
+        if output.len() > room {
+            output = format!("...{}", &output[output.len() + 3 - room..]);
+        }
+        screen += &format!(" output {}\x1b[K\r\n", output);
+        let text = decode_ascii(outputs);
+        let last_line: String = text
+            .lines()
+            .last()
+            .unwrap_or("")
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect();
+        screen += &format!(" text   {}\x1b[K", fit(&last_line, width.saturating_sub(8)));
+        screen
+    }
+
+    // Recently executed instructions, the current one and those after it,
+    // each with the style to draw it in.
+    fn code_lines(&self, rows: usize) -> Vec<(String, &'static str)> {
+        let mut lines = Vec::with_capacity(rows);
+        let before = (rows / 3).min(self.history.len());
+        for &address in self.history.iter().skip(self.history.len() - before) {
+            lines.push((format!("  {}", self.disassemble(address).0), "\x1b[2m"));
+        }
+        let mut address = self.computer.counter();
+        let mut current = true;
+        while lines.len() < rows {
+            let (text, length) = self.disassemble(address);
+            if current {
+                lines.push((format!("> {}", text), "\x1b[7m"));
+                current = false;
+            } else {
+                lines.push((format!("  {}", text), ""));
+            }
+            address += length;
+        }
+        lines
+    }
+
+    fn disassemble(&self, address: i64) -> (String, i64) {
+        let word = self.computer.memory(address);
+        let instruction = match Instruction::new(word) {
+            Some(instruction) => instruction,
+            None => return (format!("{:5}  data {}", address, word), 1),
+        };
+        let mnemonic = match instruction {
+            Instruction::Addition(..) => "add",
+            Instruction::Multiplication(..) => "mul",
+            Instruction::Input(_) => "in",
+            Instruction::Output(_) => "out",
+            Instruction::JumpIfTrue(..) => "jnz",
+            Instruction::JumpIfFalse(..) => "jz",
+            Instruction::LessThan(..) => "lt",
+            Instruction::Equals(..) => "eq",
+            Instruction::AdjustRelativeBase(_) => "arb",
+            Instruction::Stop => "halt",
+        };
+        let operands: Vec<String> = instruction
+            .parameters()
+            .iter()
+            .enumerate()
+            .map(|(index, &parameter)| {
+                let operand = self.computer.memory(address + 1 + index as i64);
+                match parameter {
+                    Parameter::Position => format!("[{}]", operand),
+                    Parameter::Immediate => operand.to_string(),
+                    Parameter::Relative if operand < 0 => format!("[rb{}]", operand),
+                    Parameter::Relative => format!("[rb+{}]", operand),
+                }
+            })
+            .collect();
+        let text = format!("{:5}  {:4} {}", address, mnemonic, operands.join(", "));
+        (text, instruction.len())
+    }
+
+    // A heading and rows of memory cells, with cells written in the last few
+    // steps in yellow and the most recent write in reverse video.
+    fn memory_lines(&self, rows: usize) -> Vec<String> {
+        let recorder = self.computer.observer();
+        let top = self.memory_top();
+        let mut lines = vec![format!("memory ({})", if self.hex { "hex" } else { "dec" })];
+        for row in 0..rows as i64 - 1 {
+            let start = (top + row) * COLUMNS;
+            let mut line = format!("{:6}:", start);
+            for address in start..start + COLUMNS {
+                let value = self.computer.memory(address);
+                let text = if self.hex {
+                    let sign = if value < 0 { "-" } else { "" };
+                    format!("{}{:x}", sign, value.unsigned_abs())
+                } else {
+                    value.to_string()
+                };
+                let style = match recorder.writes.get(&address) {
+                    _ if recorder.last_write == Some(address) => "\x1b[7m",
+                    Some(&clock) if recorder.clock - clock < RECENT => "\x1b[33m",
+                    _ => "",
+                };
+                line += &format!(" {}{:>width$}\x1b[0m", style, text, width = CELL_WIDTH);
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+// Pads or cuts `text` to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().take(width).collect();
+    let length = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - length));
+    fitted
+}
+
+// Puts the terminal in raw mode on the alternate screen, and undoes both
+// when dropped. Ctrl-C arrives as a key rather than a signal, so quitting
+// with it still restores the terminal.
+struct Terminal {
+    saved: String,
+}
+
+impl Terminal {
+    fn new() -> io::Result<Terminal> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        io::stdout().flush()?;
+        Ok(Terminal {
+            saved: saved.trim().to_string(),
+        })
+    }
+
+    // Columns and lines, falling back to 80 by 24.
+    fn size(&self) -> (usize, usize) {
+        stty(&["size"])
+            .ok()
+            .and_then(|size| {
+                let mut numbers = size.split_whitespace().map(|number| number.parse().ok());
+                let lines = numbers.next()??;
+                let columns = numbers.next()??;
+                Some((columns, lines))
+            })
+            .unwrap_or((80, 24))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+fn stty(arguments: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(arguments)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            "stty failed; is standard input a terminal?",
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Reads keys on a separate thread so the machine keeps running between
+// presses.
+fn read_keys() -> Receiver<Key> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut bytes = io::stdin().lock().bytes().map_while(Result::ok);
+        while let Some(byte) = bytes.next() {
+            let key = match byte {
+                b'\r' | b'\n' => Key::Enter,
+                8 | 127 => Key::Backspace,
+                0x1b => match (bytes.next(), bytes.next()) {
+                    (Some(b'['), Some(b'A')) => Key::Up,
+                    (Some(b'['), Some(b'B')) => Key::Down,
+                    _ => continue,
+                },
+                byte => Key::Char(byte as char),
+            };
+            if sender.send(key).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// Shows `program` running on `inputs` until the user quits. It starts
+// paused.
+pub fn watch(program: &[i64], inputs: &[i64]) -> io::Result<()> {
+    let terminal = Terminal::new()?;
+    let keys = read_keys();
+    let mut monitor = Monitor::new(program).with_inputs(inputs);
+    let mut owed = 0.0;
+    let mut last = Instant::now();
+    while !monitor.should_quit() {
+        for key in keys.try_iter() {
+            monitor.handle(key);
+        }
+        let now = Instant::now();
+        if monitor.state() == State::Running {
+            owed += monitor.speed() as f64 * (now - last).as_secs_f64();
+            monitor.advance(owed as u64);
+            owed = owed.fract();
+        } else {
+            owed = 0.0;
+        }
+        last = now;
+        let (width, height) = terminal.size();
+        print!("{}", monitor.render(width, height));
+        io::stdout().flush()?;
+        thread::sleep(FRAME);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Key, Monitor, State};
+
+    // the screen without escapes, one string per line
+    fn lines(screen: &str) -> Vec<String> {
+        let mut plain = String::new();
+        let mut escape = false;
+        for c in screen.chars() {
+            match c {
+                '\x1b' => escape = true,
+                c if escape => escape = !c.is_ascii_alphabetic(),
+                '\r' => {}
+                c => plain.push(c),
+            }
+        }
+        plain
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_stepping() {
+        // reads a value, doubles it into cell 9 and outputs it
+        let program = vec![3, 9, 1002, 9, 2, 9, 4, 9, 99, 0];
+        let mut monitor = Monitor::new(&program);
+        assert_eq!(monitor.state(), State::Paused);
+        monitor.handle(Key::Char('s'));
+        assert_eq!(monitor.state(), State::WaitingForInput);
+
+        for key in "-21".chars() {
+            monitor.handle(Key::Char(key));
+        }
+        let screen = lines(&monitor.render(100, 20));
+        assert!(screen.contains(&" input  [] > -21_".to_string()));
+        monitor.handle(Key::Enter);
+        monitor.handle(Key::Char('s'));
+        monitor.handle(Key::Char('s'));
+
+        let screen = monitor.render(100, 20);
+        assert!(screen.contains("\x1b[7m       -42\x1b[0m"));
+        let screen = lines(&screen);
+        assert_eq!(screen[1].trim(), "counter 6   relative base 0   steps 2");
+        assert!(screen[3].starts_with("      0  in   [9]"));
+        assert!(screen[4].starts_with("      2  mul  [9], 2, [9]"));
+        assert!(screen[5].starts_with(">     6  out  [9]"));
+        assert!(screen[6].starts_with("      8  halt"));
+        assert!(screen[7].starts_with("      9  data -42"));
+
+        monitor.handle(Key::Char(' '));
+        assert_eq!(monitor.state(), State::Running);
+        monitor.advance(10);
+        assert_eq!(monitor.state(), State::Halted);
+        assert_eq!(monitor.outputs(), &[-42]);
+    }
+
+    #[test]
+    fn test_controls() {
+        // moves the relative base up by 10 and stores 300 there, forever
+        let program = vec![109, 10, 21101, 100, 200, 0, 1105, 1, 0];
+        let mut monitor = Monitor::new(&program).with_inputs(&[5]);
+        monitor.handle(Key::Char('>'));
+        assert_eq!(monitor.speed(), 16);
+        monitor.handle(Key::Char('<'));
+        monitor.handle(Key::Char('<'));
+        assert_eq!(monitor.speed(), 4);
+        monitor.handle(Key::Char('h'));
+        monitor.advance(2);
+        let screen = lines(&monitor.render(100, 20));
+        assert!(screen[2].is_empty());
+        assert!(screen[3].ends_with("| memory (hex)"));
+        assert!(screen[4].contains("2  add  100, 200, [rb+0]"));
+        assert!(screen[4].ends_with("     0:         6d          a       526d         64"));
+        assert!(screen[6].ends_with("     8:          0          0        12c          0"));
+        assert!(screen.contains(&" input  [5] > _".to_string()));
+
+        monitor.handle(Key::Char(']'));
+        let screen = lines(&monitor.render(100, 20));
+        assert!(screen[4].ends_with("     4:         c8          0        451          1"));
+        monitor.handle(Key::Char('f'));
+        monitor.advance(3);
+        let screen = lines(&monitor.render(100, 20));
+        assert!(screen[4].ends_with("    12:          0          0          0          0"));
+        assert!(screen[6].ends_with("    20:        12c          0          0          0"));
+        monitor.handle(Key::Char('q'));
+        assert!(monitor.should_quit());
+
+        let mut monitor = Monitor::new(&program);
+        monitor.handle(Key::Char('\x03'));
+        assert!(monitor.should_quit());
+    }
+}
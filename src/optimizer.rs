@@ -0,0 +1,713 @@
+// Rewrites an Intcode program into an equivalent one that executes fewer
+// instructions. Equivalent means the same outputs for the same inputs;
+// unreachable cells are zeroed, so final memory can differ.
+//
+// The analysis follows control flow from address 0 and records, cell by cell,
+// how the program uses memory. Cells it reads or writes as data are never
+// modified, and neither is an instruction with a cell the program writes. If
+// the program patches an opcode the instruction may become anything, so the
+// analysis carries on after it at every length an instruction can have and at
+// the jump targets its operands could give. Everything else is rewritten:
+//
+// - position-mode reads of cells that are never written, or that were given a
+//   constant earlier in the same straight-line code, become immediates;
+// - arithmetic and comparisons on two immediates become constant stores;
+// - a jump goes straight to where the code at its target leads, skipping
+//   jumps with a constant condition, stores of the value a cell already holds
+//   and stores that are overwritten before anything reads them;
+// - a run of such code is replaced by a single jump over it;
+// - code that can not be reached is zeroed.
+//
+// A program could touch any cell if it picks the cells it uses by writing its
+// own operands, jumps to an address it computed, or moves its relative base
+// and has relative operands; such a program is left as it is. Until the base
+// moves, relative operands address the same cells as position ones. The one
+// thing assumed is that an opcode the program writes has no relative
+// operands, though it may move the base.
+
+use crate::computer::{Instruction, Parameter};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const MAX_LENGTH: i64 = 4;
+
+// Each round of rewriting can expose more work for the next.
+const MAX_ROUNDS: usize = 16;
+
+// How many instructions ahead to look for the next write of a stored cell.
+const STORE_WINDOW: usize = 64;
+
+pub fn optimize(program: &[i64]) -> Vec<i64> {
+    let original = Analysis::new(program);
+    let mut optimized = program.to_vec();
+    for _ in 0..MAX_ROUNDS {
+        let before = optimized.clone();
+        // Rewrites remove reads and jumps, so the flow is worked out again,
+        // but whatever the original program touches stays pinned.
+        let analysis = Analysis::new(&optimized).pinning(&original);
+        fold_operands(&mut optimized, &analysis);
+        propagate_constants(&mut optimized, &analysis);
+        fold_arithmetic(&mut optimized, &analysis);
+        thread_jumps(&mut optimized, &analysis);
+        if optimized == before {
+            break;
+        }
+    }
+    eliminate_unreachable(&mut optimized, &original);
+    optimized
+}
+
+struct Analysis {
+    image: i64,
+    // Reachable instructions and their lengths.
+    starts: BTreeMap<i64, i64>,
+    // Instructions with a cell the program writes.
+    patched: HashSet<i64>,
+    // Instructions that share cells with another one.
+    overlapping: HashSet<i64>,
+    // Addresses control can reach other than by falling through.
+    entries: HashSet<i64>,
+    code: HashSet<i64>,
+    reads: HashSet<i64>,
+    writes: HashSet<i64>,
+    pinned: HashSet<i64>,
+}
+
+impl Analysis {
+    // Explores again until the cells found to be written stop growing, since
+    // every write into code changes how that code is explored.
+    fn new(program: &[i64]) -> Analysis {
+        let mut written = HashSet::new();
+        loop {
+            let analysis = Analysis::explore(program, &written);
+            if analysis.writes.is_subset(&written) {
+                return analysis;
+            }
+            written.extend(analysis.writes);
+        }
+    }
+
+    fn explore(program: &[i64], written: &HashSet<i64>) -> Analysis {
+        let word = |address: i64| word(program, address);
+        let mut analysis = Analysis {
+            image: program.len() as i64,
+            starts: BTreeMap::new(),
+            patched: HashSet::new(),
+            overlapping: HashSet::new(),
+            entries: HashSet::new(),
+            code: HashSet::new(),
+            reads: HashSet::new(),
+            writes: HashSet::new(),
+            pinned: HashSet::new(),
+        };
+        let mut visited = HashSet::new();
+        let mut restarted = false;
+        let mut worklist = vec![0];
+        analysis.entries.insert(0);
+        // Jumps through memory: whether there are any, the cells they read
+        // their target from, and the constants stored where they could look.
+        let mut indirect = false;
+        let mut jump_cells = HashSet::new();
+        let mut stored = Vec::new();
+        // Cells given values that are not constants.
+        let mut computed = HashSet::new();
+        // Whether an operand the program writes picks the cell to use.
+        let mut anywhere = false;
+        // Relative operands address the same cells as position ones as long
+        // as nothing moves the relative base.
+        let mut relative = false;
+        let mut moves_base = false;
+
+        loop {
+            while let Some(address) = worklist.pop() {
+                // The first instruction runs as loaded, and could be anything
+                // if control comes back to it after the program writes it.
+                let returned = address == 0 && visited.contains(&0);
+                if returned && !restarted {
+                    restarted = true;
+                } else if address < 0 || !visited.insert(address) {
+                    continue;
+                }
+                let changed = |cell: i64| written.contains(&cell) && (address != 0 || returned);
+                let decoded = Instruction::new(word(address)).filter(|_| address < analysis.image);
+                // An instruction cut off by the end of the image takes its
+                // last operands from memory the analysis does not follow.
+                let cut_off =
+                    decoded.is_some_and(|instruction| address + instruction.len() > analysis.image);
+                let instruction = match decoded {
+                    Some(instruction) if !changed(address) && !cut_off => instruction,
+                    // Running off the end or into an invalid opcode stops the
+                    // machine, unless the program writes an opcode there. The
+                    // word that stops it must stay as it is.
+                    None if !changed(address) => {
+                        analysis.reads.insert(address);
+                        continue;
+                    }
+                    _ => {
+                        analysis.explore_unknown(program, address, &mut worklist);
+                        jump_cells.insert(word(address + 2));
+                        computed.insert(word(address + 1));
+                        computed.insert(word(address + 3));
+                        anywhere |= (address + 1..address + MAX_LENGTH).any(changed);
+                        moves_base = true;
+                        indirect = true;
+                        continue;
+                    }
+                };
+                let length = instruction.len();
+                analysis.starts.insert(address, length);
+                analysis.code.extend(address..address + length);
+                let patched = (address..address + length).any(|cell| written.contains(&cell));
+                if patched {
+                    analysis.patched.insert(address);
+                }
+
+                let parameters = instruction.parameters();
+                for (i, &parameter) in parameters.iter().enumerate() {
+                    let cell = address + 1 + i as i64;
+                    let is_write = instruction.write_parameter() == Some(i);
+                    anywhere |= parameter != Parameter::Immediate && changed(cell);
+                    relative |= parameter == Parameter::Relative;
+                    match parameter {
+                        Parameter::Immediate if is_write => {
+                            analysis.writes.insert(cell);
+                        }
+                        Parameter::Immediate => {}
+                        _ if is_write => {
+                            analysis.writes.insert(word(cell));
+                        }
+                        _ => {
+                            analysis.reads.insert(word(cell));
+                        }
+                    }
+                }
+
+                match instruction {
+                    Instruction::Stop => {}
+                    Instruction::JumpIfTrue(..) | Instruction::JumpIfFalse(..) => {
+                        let jumps_on_true = matches!(instruction, Instruction::JumpIfTrue(..));
+                        let condition = match parameters[0] {
+                            Parameter::Immediate if !changed(address + 1) => {
+                                Some((word(address + 1) != 0) == jumps_on_true)
+                            }
+                            _ => None,
+                        };
+                        let target = match parameters[1] {
+                            Parameter::Immediate => word(address + 2),
+                            _ => {
+                                jump_cells.insert(word(address + 2));
+                                word(word(address + 2))
+                            }
+                        };
+                        if condition != Some(false) {
+                            if changed(address + 2) {
+                                indirect = true;
+                            }
+                            analysis.entries.insert(target);
+                            worklist.push(target);
+                        }
+                        if condition != Some(true) {
+                            worklist.push(address + 3);
+                        }
+                    }
+                    _ => {
+                        moves_base |= matches!(instruction, Instruction::AdjustRelativeBase(_));
+                        if let Some(i) = instruction.write_parameter() {
+                            let cell = address + 1 + i as i64;
+                            let destination = match parameters[i] {
+                                Parameter::Immediate => cell,
+                                _ => word(cell),
+                            };
+                            match evaluate(program, address).filter(|_| !patched) {
+                                Some(value) => stored.push((destination, value)),
+                                None => {
+                                    computed.insert(destination);
+                                }
+                            }
+                        }
+                        worklist.push(address + length);
+                    }
+                }
+            }
+
+            if jump_cells.iter().any(|cell| written.contains(cell)) {
+                indirect = true;
+            }
+            if !indirect {
+                break;
+            }
+            let targets: Vec<i64> = stored
+                .iter()
+                .filter(|(destination, _)| jump_cells.contains(destination))
+                .map(|&(_, value)| value)
+                .filter(|target| !analysis.entries.contains(target))
+                .collect();
+            if targets.is_empty() {
+                break;
+            }
+            analysis.entries.extend(&targets);
+            worklist.extend(targets);
+        }
+
+        let mut reach = (i64::MIN, 0);
+        for (&address, &length) in &analysis.starts {
+            if address < reach.0 {
+                analysis.overlapping.insert(address);
+                analysis.overlapping.insert(reach.1);
+            }
+            if address + length > reach.0 {
+                reach = (address + length, address);
+            }
+        }
+        // So could a jump to a computed address, or a relative operand once
+        // the base moves.
+        anywhere |= relative && moves_base;
+        if anywhere || jump_cells.iter().any(|cell| computed.contains(cell)) {
+            analysis.pinned.extend(0..analysis.image);
+        }
+        analysis.pinned.extend(&analysis.reads);
+        analysis.pinned.extend(&analysis.writes);
+        for &address in &analysis.patched {
+            analysis
+                .pinned
+                .extend(address..address + analysis.starts[&address]);
+        }
+        analysis
+    }
+
+    // An instruction whose opcode the program writes could be any of them, on
+    // the operands it has now. So could one whose operands run past the end.
+    fn explore_unknown(&mut self, program: &[i64], address: i64, worklist: &mut Vec<i64>) {
+        let word = |address: i64| word(program, address);
+        self.starts.insert(address, MAX_LENGTH);
+        self.patched.insert(address);
+        self.code.extend(address..address + MAX_LENGTH);
+        // an input writes its first operand, arithmetic its third
+        self.writes.insert(word(address + 1));
+        self.writes.insert(word(address + 3));
+        self.reads.insert(word(address + 1));
+        self.reads.insert(word(address + 2));
+        let targets = [word(address + 2), word(word(address + 2))];
+        for next in (address + 2..=address + MAX_LENGTH).chain(targets.iter().copied()) {
+            self.entries.insert(next);
+            worklist.push(next);
+        }
+    }
+
+    // Adds the cells another analysis pinned to this one's.
+    fn pinning(mut self, other: &Analysis) -> Analysis {
+        self.pinned.extend(&other.pinned);
+        self.writes.extend(&other.writes);
+        self
+    }
+
+    fn can_rewrite(&self, address: i64) -> bool {
+        match self.starts.get(&address) {
+            Some(&length) => {
+                address + length <= self.image
+                    && !self.patched.contains(&address)
+                    && !self.overlapping.contains(&address)
+                    && (address..address + length).all(|cell| !self.pinned.contains(&cell))
+            }
+            None => false,
+        }
+    }
+
+    fn rewritable(&self, program: &[i64], address: i64) -> Option<Instruction> {
+        if self.can_rewrite(address) {
+            Instruction::new(program[address as usize])
+        } else {
+            None
+        }
+    }
+}
+
+fn word(program: &[i64], address: i64) -> i64 {
+    if address >= 0 && address < program.len() as i64 {
+        program[address as usize]
+    } else {
+        0
+    }
+}
+
+fn set_mode(program: &mut [i64], address: i64, index: usize, mode: Parameter) {
+    let scale = 10i64.pow(2 + index as u32);
+    let opcode = &mut program[address as usize];
+    *opcode += (mode as i64 - *opcode / scale % 10) * scale;
+}
+
+// The value an arithmetic or comparison instruction on two immediates stores.
+fn evaluate(program: &[i64], address: i64) -> Option<i64> {
+    let instruction = Instruction::new(word(program, address))?;
+    let (x, y) = (word(program, address + 1), word(program, address + 2));
+    match instruction {
+        Instruction::Addition(Parameter::Immediate, Parameter::Immediate, _) => x.checked_add(y),
+        Instruction::Multiplication(Parameter::Immediate, Parameter::Immediate, _) => {
+            x.checked_mul(y)
+        }
+        Instruction::LessThan(Parameter::Immediate, Parameter::Immediate, _) => {
+            Some((x < y) as i64)
+        }
+        Instruction::Equals(Parameter::Immediate, Parameter::Immediate, _) => Some((x == y) as i64),
+        _ => None,
+    }
+}
+
+// Position-mode reads of cells that are never written become immediates.
+fn fold_operands(program: &mut [i64], analysis: &Analysis) {
+    for &address in analysis.starts.keys() {
+        let instruction = match analysis.rewritable(program, address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        for (i, parameter) in instruction.parameters().into_iter().enumerate() {
+            if parameter != Parameter::Position || instruction.write_parameter() == Some(i) {
+                continue;
+            }
+            let cell = (address + 1 + i as i64) as usize;
+            let target = program[cell];
+            if target < 0 || target >= analysis.image || analysis.writes.contains(&target) {
+                continue;
+            }
+            program[cell] = program[target as usize];
+            set_mode(program, address, i, Parameter::Immediate);
+        }
+    }
+}
+
+// Within straight-line code, a cell given a constant holds it until the next
+// write, so reads of it become immediates.
+fn propagate_constants(program: &mut [i64], analysis: &Analysis) {
+    let mut known: HashMap<i64, i64> = HashMap::new();
+    let mut follows = None;
+    for (&address, &length) in &analysis.starts {
+        if follows != Some(address) || analysis.entries.contains(&address) {
+            known.clear();
+        }
+        follows = None;
+        if analysis.patched.contains(&address) {
+            continue;
+        }
+        let instruction = match Instruction::new(program[address as usize]) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        let parameters = instruction.parameters();
+        if analysis.can_rewrite(address) {
+            for (i, &parameter) in parameters.iter().enumerate() {
+                let cell = (address + 1 + i as i64) as usize;
+                if parameter != Parameter::Position || instruction.write_parameter() == Some(i) {
+                    continue;
+                }
+                if let Some(&value) = known.get(&program[cell]) {
+                    program[cell] = value;
+                    set_mode(program, address, i, Parameter::Immediate);
+                }
+            }
+        }
+        if let Some(i) = instruction.write_parameter() {
+            let cell = address + 1 + i as i64;
+            match parameters[i] {
+                Parameter::Position => {
+                    let target = program[cell as usize];
+                    match evaluate(program, address) {
+                        Some(value) => known.insert(target, value),
+                        None => known.remove(&target),
+                    };
+                }
+                Parameter::Immediate => {
+                    known.remove(&cell);
+                }
+                Parameter::Relative => known.clear(),
+            }
+        }
+        follows = match instruction {
+            Instruction::Stop => None,
+            Instruction::JumpIfTrue(Parameter::Immediate, _) => {
+                Some(address + 3).filter(|_| program[address as usize + 1] == 0)
+            }
+            Instruction::JumpIfFalse(Parameter::Immediate, _) => {
+                Some(address + 3).filter(|_| program[address as usize + 1] != 0)
+            }
+            _ => Some(address + length),
+        };
+    }
+}
+
+// Arithmetic and comparisons on two immediates become `dest = result + 0`.
+fn fold_arithmetic(program: &mut [i64], analysis: &Analysis) {
+    for &address in analysis.starts.keys() {
+        if analysis.rewritable(program, address).is_none() {
+            continue;
+        }
+        if let Some(result) = evaluate(program, address) {
+            let a = address as usize;
+            program[a] = 1 + program[a] / 100 * 100;
+            program[a + 1] = result;
+            program[a + 2] = 0;
+        }
+    }
+}
+
+// Where control goes after the instruction at `address`, if that instruction
+// has no effect: a jump with a constant condition, or a constant store to a
+// cell that is overwritten before anything reads it.
+fn skip(program: &[i64], analysis: &Analysis, address: i64) -> Option<i64> {
+    if !analysis.starts.contains_key(&address) || analysis.patched.contains(&address) {
+        return None;
+    }
+    let operand = |offset: i64| word(program, address + offset);
+    match Instruction::new(operand(0))? {
+        Instruction::JumpIfTrue(Parameter::Immediate, target)
+        | Instruction::JumpIfFalse(Parameter::Immediate, target) => {
+            let jumps_on_true = operand(0) % 100 == 5;
+            if (operand(1) != 0) != jumps_on_true {
+                Some(address + 3)
+            } else if target == Parameter::Immediate {
+                Some(operand(2))
+            } else {
+                None
+            }
+        }
+        Instruction::AdjustRelativeBase(Parameter::Immediate) if operand(1) == 0 => {
+            Some(address + 2)
+        }
+        Instruction::Addition(..) | Instruction::Multiplication(..)
+            if stores_itself(program, address) =>
+        {
+            Some(address + 4)
+        }
+        Instruction::Addition(.., Parameter::Position)
+        | Instruction::Multiplication(.., Parameter::Position)
+        | Instruction::LessThan(.., Parameter::Position)
+        | Instruction::Equals(.., Parameter::Position)
+            if evaluate(program, address).is_some()
+                && is_dead_store(program, analysis, address + 4, operand(3)) =>
+        {
+            Some(address + 4)
+        }
+        _ => None,
+    }
+}
+
+// Whether the instruction at `address` is `x = x + 0` or `x = x * 1`.
+fn stores_itself(program: &[i64], address: i64) -> bool {
+    let instruction = match Instruction::new(word(program, address)) {
+        Some(instruction) => instruction,
+        None => return false,
+    };
+    let identity = match instruction {
+        Instruction::Addition(..) => 0,
+        Instruction::Multiplication(..) => 1,
+        _ => return false,
+    };
+    let parameters = instruction.parameters();
+    let operand = |i: usize| word(program, address + 1 + i as i64);
+    // a negative position faults, which has to be kept
+    let stored = parameters[2] == Parameter::Relative || operand(2) >= 0;
+    stored
+        && (0..2).any(|i| {
+            parameters[i] == parameters[2]
+                && operand(i) == operand(2)
+                && parameters[1 - i] == Parameter::Immediate
+                && operand(1 - i) == identity
+        })
+}
+
+// Whether, going on from `address`, `cell` is written before it can be read.
+// Code the analysis can not follow counts as reading it.
+fn is_dead_store(program: &[i64], analysis: &Analysis, mut address: i64, cell: i64) -> bool {
+    if cell < 0 || cell >= analysis.image || analysis.code.contains(&cell) {
+        return false;
+    }
+    for _ in 0..STORE_WINDOW {
+        if !analysis.starts.contains_key(&address) || analysis.patched.contains(&address) {
+            return false;
+        }
+        let instruction = match Instruction::new(word(program, address)) {
+            Some(instruction) => instruction,
+            None => return false,
+        };
+        let parameters = instruction.parameters();
+        for (i, &parameter) in parameters.iter().enumerate() {
+            let operand = word(program, address + 1 + i as i64);
+            if parameter != Parameter::Immediate && operand == cell {
+                return parameter == Parameter::Position
+                    && instruction.write_parameter() == Some(i);
+            }
+        }
+        address = match instruction {
+            Instruction::Stop => return true,
+            Instruction::JumpIfTrue(..) | Instruction::JumpIfFalse(..) => {
+                match skip(program, analysis, address) {
+                    Some(next) => next,
+                    None => return false,
+                }
+            }
+            _ => address + instruction.len(),
+        };
+    }
+    false
+}
+
+// Follows code without effect from `address`, returning where control gets
+// to and how many instructions that skips.
+fn follow(program: &[i64], analysis: &Analysis, address: i64) -> (i64, usize) {
+    let mut visited = HashSet::new();
+    let mut address = address;
+    while visited.insert(address) {
+        match skip(program, analysis, address) {
+            Some(next) => address = next,
+            None => break,
+        }
+    }
+    (address, visited.len() - 1)
+}
+
+// A jump is retargeted past the code without effect at its target, and a run
+// of such code becomes one jump over it.
+fn thread_jumps(program: &mut [i64], analysis: &Analysis) {
+    for &address in analysis.starts.keys() {
+        let instruction = match analysis.rewritable(program, address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        let a = address as usize;
+        if let Instruction::JumpIfTrue(_, Parameter::Immediate)
+        | Instruction::JumpIfFalse(_, Parameter::Immediate) = instruction
+        {
+            program[a + 2] = follow(program, analysis, program[a + 2]).0;
+        }
+        if instruction.len() >= 3 {
+            let (end, skipped) = follow(program, analysis, address);
+            if skipped >= 2 {
+                program[a..a + 3].copy_from_slice(&[1105, 1, end]);
+            }
+        }
+    }
+}
+
+fn eliminate_unreachable(program: &mut [i64], original: &Analysis) {
+    let analysis = Analysis::new(program).pinning(original);
+    for (address, cell) in program.iter_mut().enumerate() {
+        let address = address as i64;
+        if !analysis.code.contains(&address) && !analysis.pinned.contains(&address) {
+            *cell = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::computer::{parse_program, Computer};
+
+    // The outputs, and how many instructions it took.
+    fn run(program: &[i64], input: &[i64]) -> (Vec<i64>, u64) {
+        let mut computer = Computer::new(program);
+        let mut result = Vec::new();
+        let mut input = input;
+        while let Some(output) = computer.compute(input) {
+            result.push(output);
+            input = &[];
+        }
+        (result, computer.steps())
+    }
+
+    #[test]
+    fn test_fold() {
+        let program = vec![1, 9, 10, 11, 4, 11, 99, 7, 7, 5, 6, 0];
+        let optimized = super::optimize(&program);
+        assert_eq!(optimized, vec![1101, 11, 0, 11, 104, 11, 99, 0, 0, 5, 6, 0]);
+        assert_eq!(run(&optimized, &[]).0, vec![11]);
+    }
+
+    #[test]
+    fn test_fewer_steps() {
+        // computes (2 + 3) * 4 + 1 through cells 17 and 18 and outputs it
+        let program = vec![
+            1101, 2, 3, 17, 1002, 17, 4, 18, 1001, 18, 1, 18, 4, 18, 99, 0, 0, 0, 0,
+        ];
+        let optimized = super::optimize(&program);
+        assert_eq!(
+            optimized,
+            vec![1105, 1, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 104, 21, 99, 0, 0, 0, 0]
+        );
+        assert_eq!(run(&program, &[]), (vec![21], 5));
+        assert_eq!(run(&optimized, &[]), (vec![21], 3));
+    }
+
+    #[test]
+    fn test_thread_jumps() {
+        let program = vec![1105, 1, 7, 99, 5, 5, 5, 1106, 0, 10, 104, 42, 99];
+        let optimized = super::optimize(&program);
+        assert_eq!(
+            optimized,
+            vec![1105, 1, 10, 0, 0, 0, 0, 0, 0, 0, 104, 42, 99]
+        );
+
+        let program = vec![3, 12, 1005, 12, 6, 99, 1106, 1, 3, 1105, 1, 5, 0];
+        let optimized = super::optimize(&program);
+        assert_eq!(optimized[4], 5);
+        for input in &[0, 1] {
+            assert_eq!(run(&optimized, &[*input]).0, run(&program, &[*input]).0);
+        }
+    }
+
+    #[test]
+    fn test_pinned_cells() {
+        // writes its own opcode
+        let program = vec![1, 0, 0, 0, 99];
+        assert_eq!(super::optimize(&program), program);
+        // reads data through a relative base it moved
+        let program = vec![109, 5, 204, 0, 99, 42];
+        assert_eq!(super::optimize(&program), program);
+        assert_eq!(run(&program, &[]).0, vec![42]);
+        // reads data through the relative base before it moves
+        let program = vec![204, 3, 99, 42];
+        assert_eq!(run(&super::optimize(&program), &[]).0, vec![42]);
+        // jumps onto its own target operand, which is not an instruction
+        let program = vec![20205, 11, 13, 14, 102, 20107, 1208, -3, 7, 8, 9, 3, 0, 2];
+        assert_eq!(super::optimize(&program)[2], 13);
+        // jumps to an address from its input
+        let program = vec![3, 7, 5, 7, 7, 99, 99, 0];
+        assert_eq!(super::optimize(&program), program);
+
+        // The first addition patches an operand of the second, which is left
+        // alone; the rest is still folded.
+        let program = vec![
+            1, 20, 21, 6, 1101, 0, 0, 19, 1, 20, 21, 22, 4, 19, 4, 22, 99, 0, 0, 0, 3, 4, 0,
+        ];
+        let optimized = super::optimize(&program);
+        assert_eq!(
+            optimized,
+            vec![
+                1101, 7, 0, 6, 1101, 0, 0, 19, 1101, 7, 0, 22, 4, 19, 104, 7, 99, 0, 0, 0, 3, 4, 0
+            ]
+        );
+        assert_eq!(run(&optimized, &[]).0, vec![7, 7]);
+    }
+
+    #[test]
+    fn test_cut_off() {
+        // the last instruction takes operands from past the end
+        for program in &[vec![5, -1, 2, 2, 0], vec![2, 1005, 1002, 1107, 3, 1005, 1]] {
+            assert_eq!(&super::optimize(program), program);
+        }
+    }
+
+    #[test]
+    fn test_equivalence() {
+        // Day 5 patches its own code and still gets shorter runs. Day 9 keeps
+        // a stack through the relative base, so it is left alone.
+        let day5 = parse_program(include_str!("../input/2019/day5.txt")).unwrap();
+        let optimized = super::optimize(&day5);
+        assert_ne!(optimized, day5);
+        for input in &[1, 5] {
+            let (output, steps) = run(&optimized, &[*input]);
+            let (expected, original_steps) = run(&day5, &[*input]);
+            assert_eq!(output, expected);
+            assert!(steps < original_steps, "{} >= {}", steps, original_steps);
+        }
+        let day9 = parse_program(include_str!("../input/2019/day9.txt")).unwrap();
+        assert_eq!(super::optimize(&day9), day9);
+    }
+}
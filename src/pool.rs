@@ -0,0 +1,156 @@
+// Runs the same program over and over on different inputs, like the drone
+// that is sent out once per coordinate to probe a tractor beam.
+//
+// A `Pool` keeps the program's initial image and one machine that is reset
+// to it before every run, so no memory is allocated per query once the
+// machine has grown to its working size. Answers can also be cached by input,
+// which pays off when the same queries come up repeatedly.
+
+use crate::computer::{Computer, Fault, Limits, Status};
+use std::collections::HashMap;
+
+#[derive(Clone)]
+pub struct Pool {
+    image: Vec<i64>,
+    machine: Computer,
+    cache: Option<HashMap<Vec<i64>, Vec<i64>>>,
+    runs: u64,
+    hits: u64,
+}
+
+impl Pool {
+    pub fn new(program: &[i64]) -> Pool {
+        Pool {
+            image: program.to_vec(),
+            machine: Computer::new(program),
+            cache: None,
+            runs: 0,
+            hits: 0,
+        }
+    }
+
+    pub fn with_cache(mut self) -> Pool {
+        self.cache = Some(HashMap::new());
+        self
+    }
+
+    // Applied to every run separately.
+    pub fn with_limits(mut self, limits: Limits) -> Pool {
+        self.machine.set_limits(limits);
+        self
+    }
+
+    // Number of times the program was actually run.
+    pub fn runs(&self) -> u64 {
+        self.runs
+    }
+
+    // Number of queries answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn clear_cache(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
+        }
+    }
+
+    // Runs a fresh copy of the program on `input` and returns every output
+    // up to the point it halts. Like `Computer::compute`, an invalid
+    // instruction ends the run and any other fault panics.
+    pub fn probe(&mut self, input: &[i64]) -> Vec<i64> {
+        match self.try_probe(input) {
+            Ok(outputs) => outputs,
+            Err(fault) => panic!("{}", fault),
+        }
+    }
+
+    // Asking for more input than given is a `Fault::MissingInput`. Failed
+    // runs are not cached.
+    pub fn try_probe(&mut self, input: &[i64]) -> Result<Vec<i64>, Fault> {
+        if let Some(outputs) = self.cache.as_ref().and_then(|cache| cache.get(input)) {
+            self.hits += 1;
+            return Ok(outputs.clone());
+        }
+
+        self.runs += 1;
+        self.machine.reset(&self.image);
+        for &value in input {
+            self.machine.push_input(value);
+        }
+        let mut outputs = Vec::new();
+        loop {
+            match self.machine.resume() {
+                Ok(Status::Output(value)) => outputs.push(value),
+                Ok(Status::Halted) | Err(Fault::InvalidInstruction { .. }) => break,
+                Ok(Status::NeedsInput) => {
+                    return Err(Fault::MissingInput {
+                        address: self.machine.counter(),
+                    })
+                }
+                Err(fault) => return Err(fault),
+            }
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.insert(input.to_vec(), outputs.clone());
+        }
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pool;
+    use crate::compiler::compile;
+    use crate::computer::{Fault, Limits};
+
+    #[test]
+    fn test_fresh_memory() {
+        // increments cell 13 and cell 1000, then outputs both
+        let program = vec![1001, 13, 1, 13, 1001, 1000, 1, 1000, 4, 13, 4, 1000, 99, 5];
+        let mut pool = Pool::new(&program);
+        for _ in 0..3 {
+            assert_eq!(pool.probe(&[]), vec![6, 1]);
+        }
+        assert_eq!(pool.runs(), 3);
+        assert_eq!(pool.hits(), 0);
+    }
+
+    #[test]
+    fn test_cache() {
+        let beam = compile(
+            "fn main() {
+                let x = input();
+                let y = input();
+                output(x * 3 <= y * 2 && y <= x * 2);
+            }",
+        )
+        .unwrap();
+        let mut pool = Pool::new(&beam).with_cache();
+        let mut count = 0;
+        for _ in 0..2 {
+            for y in 0..10 {
+                for x in 0..10 {
+                    count += pool.probe(&[x, y])[0];
+                }
+            }
+        }
+        assert_eq!(count, 2 * 12);
+        assert_eq!(pool.runs(), 100);
+        assert_eq!(pool.hits(), 100);
+
+        assert!(matches!(
+            pool.try_probe(&[1]),
+            Err(Fault::MissingInput { .. })
+        ));
+        assert_eq!(pool.runs(), 101);
+        let limits = Limits {
+            max_steps: Some(5),
+            ..Limits::default()
+        };
+        let mut pool = Pool::new(&beam).with_limits(limits);
+        assert_eq!(pool.try_probe(&[1, 1]), Err(Fault::StepLimit(5)));
+        assert_eq!(pool.try_probe(&[1, 1]), Err(Fault::StepLimit(5)));
+    }
+}
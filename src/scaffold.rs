@@ -0,0 +1,346 @@
+// Tools for the vacuum robot that walks the scaffolding outside the ship.
+//
+// The program first prints an ASCII camera image: `#` is scaffold, `.` is
+// open space, and `^`, `v`, `<` or `>` is the robot standing on scaffold and
+// facing that way (`X` if it has fallen off). Woken up by setting address 0 to
+// 2, it instead reads a movement program as ASCII lines: a main routine made
+// of calls to the functions A, B and C, each function's body of turns (`L`,
+// `R`) and forward moves, and `y` or `n` for a continuous video feed. Every
+// line is limited to 20 characters.
+
+use crate::computer::{decode_ascii, Computer, Fault, Status};
+use crate::droid::Move;
+use std::collections::HashSet;
+use std::fmt;
+
+pub const LINE_LIMIT: usize = 20;
+pub const MAX_FUNCTIONS: usize = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Left,
+    Right,
+    Forward(usize),
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Left => write!(f, "L"),
+            Command::Right => write!(f, "R"),
+            Command::Forward(steps) => write!(f, "{}", steps),
+        }
+    }
+}
+
+pub fn format_commands(commands: &[Command]) -> String {
+    commands
+        .iter()
+        .map(|command| command.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pose {
+    pub position: (i64, i64),
+    pub facing: Move,
+}
+
+#[derive(Debug, Default)]
+pub struct Camera {
+    scaffold: HashSet<(i64, i64)>,
+    robot: Option<Pose>,
+}
+
+impl Camera {
+    // Parses the image up to the first blank line.
+    pub fn parse(image: &str) -> Camera {
+        let mut camera = Camera::default();
+        for (y, line) in image.lines().take_while(|l| !l.is_empty()).enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                let position = (x as i64, y as i64);
+                let facing = match c {
+                    '#' => None,
+                    '^' => Some(Move::North),
+                    'v' => Some(Move::South),
+                    '<' => Some(Move::West),
+                    '>' => Some(Move::East),
+                    _ => continue,
+                };
+                camera.scaffold.insert(position);
+                if let Some(facing) = facing {
+                    camera.robot = Some(Pose { position, facing });
+                }
+            }
+        }
+        camera
+    }
+
+    pub fn robot(&self) -> Option<Pose> {
+        self.robot
+    }
+
+    pub fn is_scaffold(&self, position: (i64, i64)) -> bool {
+        self.scaffold.contains(&position)
+    }
+
+    // Scaffold cells with scaffold on all four sides.
+    pub fn intersections(&self) -> Vec<(i64, i64)> {
+        let mut intersections: Vec<(i64, i64)> = self
+            .scaffold
+            .iter()
+            .filter(|&&p| Move::ALL.iter().all(|step| self.is_scaffold(step.apply(p))))
+            .cloned()
+            .collect();
+        intersections.sort_unstable_by_key(|&(x, y)| (y, x));
+        intersections
+    }
+
+    pub fn alignment(&self) -> i64 {
+        self.intersections().iter().map(|(x, y)| x * y).sum()
+    }
+
+    // The route that goes straight across intersections and turns only at
+    // corners, ending at the first dead end.
+    pub fn path(&self) -> Vec<Command> {
+        let mut path = Vec::new();
+        let Pose {
+            mut position,
+            mut facing,
+        } = match self.robot {
+            Some(pose) => pose,
+            None => return path,
+        };
+        let mut corners = HashSet::new();
+        loop {
+            let mut steps = 0;
+            while self.is_scaffold(facing.apply(position)) {
+                position = facing.apply(position);
+                steps += 1;
+            }
+            if steps > 0 {
+                path.push(Command::Forward(steps));
+            }
+            // a closed loop would otherwise go round forever
+            if !corners.insert((position, facing)) {
+                break;
+            }
+            if self.is_scaffold(facing.turn_left().apply(position)) {
+                path.push(Command::Left);
+                facing = facing.turn_left();
+            } else if self.is_scaffold(facing.turn_right().apply(position)) {
+                path.push(Command::Right);
+                facing = facing.turn_right();
+            } else if path.is_empty() && self.is_scaffold(facing.reverse().apply(position)) {
+                path.extend(&[Command::Right, Command::Right]);
+                facing = facing.reverse();
+            } else {
+                break;
+            }
+        }
+        path
+    }
+}
+
+// Runs the program until it asks for input and parses what it printed.
+pub fn view(program: &[i64]) -> Result<Camera, Fault> {
+    let (outputs, _) = Computer::new(program).run_until_blocked()?;
+    Ok(Camera::parse(&decode_ascii(&outputs)))
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Routines {
+    // Indices into `functions`.
+    pub main: Vec<usize>,
+    pub functions: Vec<Vec<Command>>,
+}
+
+impl Routines {
+    pub fn main_routine(&self) -> String {
+        self.main
+            .iter()
+            .map(|&i| ((b'A' + i as u8) as char).to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn expand(&self) -> Vec<Command> {
+        self.main
+            .iter()
+            .flat_map(|&i| self.functions[i].iter().cloned())
+            .collect()
+    }
+
+    // The lines to send to the program. Functions that are not needed are
+    // sent as empty lines, since the program always asks for all three.
+    pub fn input(&self, video: bool) -> String {
+        let mut lines = vec![self.main_routine()];
+        for i in 0..self.functions.len().max(MAX_FUNCTIONS) {
+            lines.push(
+                self.functions
+                    .get(i)
+                    .map_or(String::new(), |f| format_commands(f)),
+            );
+        }
+        lines.push(if video { "y" } else { "n" }.to_string());
+        lines.join("\n") + "\n"
+    }
+}
+
+// Splits `path` into a main routine and at most `max_functions` functions,
+// with no line longer than `limit` characters.
+pub fn compress(path: &[Command], max_functions: usize, limit: usize) -> Option<Routines> {
+    let mut routines = Routines {
+        main: Vec::new(),
+        functions: Vec::new(),
+    };
+    if search(path, max_functions, limit, &mut routines) {
+        Some(routines)
+    } else {
+        None
+    }
+}
+
+fn search(path: &[Command], max_functions: usize, limit: usize, routines: &mut Routines) -> bool {
+    if path.is_empty() {
+        return true;
+    }
+    // the main routine needs a comma between calls
+    if 2 * routines.main.len() + 1 > limit {
+        return false;
+    }
+    for i in 0..routines.functions.len() {
+        let length = routines.functions[i].len();
+        if path.starts_with(&routines.functions[i]) {
+            routines.main.push(i);
+            if search(&path[length..], max_functions, limit, routines) {
+                return true;
+            }
+            routines.main.pop();
+        }
+    }
+    if routines.functions.len() < max_functions {
+        for length in 1..=path.len() {
+            if format_commands(&path[..length]).len() > limit {
+                break;
+            }
+            routines.main.push(routines.functions.len());
+            routines.functions.push(path[..length].to_vec());
+            if search(&path[length..], max_functions, limit, routines) {
+                return true;
+            }
+            routines.functions.pop();
+            routines.main.pop();
+        }
+    }
+    false
+}
+
+// Wakes the robot up, sends it the routines and returns the last value it
+// outputs, which is not ASCII if the robot made it to the end.
+pub fn run(program: &[i64], routines: &Routines, video: bool) -> Result<Option<i64>, Fault> {
+    let mut program = program.to_vec();
+    if let Some(mode) = program.get_mut(0) {
+        *mode = 2;
+    }
+    let mut computer = Computer::new(&program);
+    computer.push_ascii(&routines.input(video));
+    match computer.run_until_blocked()? {
+        (outputs, Status::Halted) => Ok(outputs.last().cloned()),
+        _ => Err(Fault::MissingInput {
+            address: computer.counter(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compress, format_commands, Camera, Command, Pose, Routines, LINE_LIMIT, MAX_FUNCTIONS,
+    };
+    use crate::computer::Fault;
+    use crate::droid::Move;
+
+    #[test]
+    fn test_alignment() {
+        let camera = Camera::parse(concat!(
+            "..#..........\n",
+            "..#..........\n",
+            "#######...###\n",
+            "#.#...#...#.#\n",
+            "#############\n",
+            "..#...#...#..\n",
+            "..#####...^..\n",
+        ));
+        assert_eq!(
+            camera.intersections(),
+            vec![(2, 2), (2, 4), (6, 4), (10, 4)]
+        );
+        assert_eq!(camera.alignment(), 76);
+        assert_eq!(
+            camera.robot(),
+            Some(Pose {
+                position: (10, 6),
+                facing: Move::North
+            })
+        );
+    }
+
+    #[test]
+    fn test_path_and_compress() {
+        let camera = Camera::parse(concat!(
+            "#######...#####\n",
+            "#.....#...#...#\n",
+            "#.....#...#...#\n",
+            "......#...#...#\n",
+            "......#...###.#\n",
+            "......#.....#.#\n",
+            "^########...#.#\n",
+            "......#.#...#.#\n",
+            "......#########\n",
+            "........#...#..\n",
+            "....#########..\n",
+            "....#...#......\n",
+            "....#...#......\n",
+            "....#...#......\n",
+            "....#####......\n",
+            "\n",
+            "Main:\n",
+        ));
+        let path = camera.path();
+        assert_eq!(
+            format_commands(&path),
+            "R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2"
+        );
+
+        let routines = compress(&path, MAX_FUNCTIONS, LINE_LIMIT).unwrap();
+        assert_eq!(routines.expand(), path);
+        assert!(routines.functions.len() <= 3);
+        assert!(routines.main_routine().len() <= 20);
+        for function in &routines.functions {
+            assert!(format_commands(function).len() <= 20);
+        }
+        assert_eq!(compress(&path, 1, 20), None);
+    }
+
+    #[test]
+    fn test_input() {
+        let routines = Routines {
+            main: vec![0, 1, 0],
+            functions: vec![
+                vec![Command::Right, Command::Forward(12)],
+                vec![Command::Left, Command::Forward(4), Command::Left],
+            ],
+        };
+        assert_eq!(routines.input(false), "A,B,A\nR,12\nL,4,L\n\nn\n");
+        // an empty program faults on its first instruction
+        assert_eq!(
+            super::run(&[], &routines, false),
+            Err(Fault::InvalidInstruction {
+                address: 0,
+                word: 0
+            })
+        );
+    }
+}
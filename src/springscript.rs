@@ -0,0 +1,509 @@
+// Springscript, the language of the springdroid that crosses the hull.
+//
+//     NOT A J
+//     NOT C T
+//     AND D T
+//     OR T J
+//     WALK
+//
+// `A` to `I` read the ground 1 to 9 tiles ahead, true meaning there is
+// ground. `T` and `J` are writable and start false. `AND X Y` sets `Y` to
+// `X and Y`, `OR X Y` to `X or Y`, and `NOT X Y` to `not X`. The program runs
+// once per step; if `J` ends up true the droid jumps and lands 4 tiles ahead.
+// `WALK` only has the sensors `A` to `D`, `RUN` has all nine. At most 15
+// instructions fit in the droid's memory.
+//
+// Hulls are written as the droid's camera shows them: `#` for ground and `.`
+// for a hole, with the droid starting on the first tile. Past the end of the
+// string there is ground.
+
+use crate::computer::{decode_ascii, Computer, Fault, Status};
+use std::collections::HashMap;
+use std::fmt;
+
+pub const MAX_INSTRUCTIONS: usize = 15;
+const JUMP_LENGTH: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Walk,
+    Run,
+}
+
+impl Mode {
+    pub fn sensors(self) -> usize {
+        match self {
+            Mode::Walk => 4,
+            Mode::Run => 9,
+        }
+    }
+}
+
+// Sensors are numbered from 0 for `A`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Register {
+    Sensor(usize),
+    T,
+    J,
+}
+
+impl Register {
+    fn parse(token: &str) -> Option<Register> {
+        match token {
+            "T" => Some(Register::T),
+            "J" => Some(Register::J),
+            _ if token.len() == 1 && ("A"..="I").contains(&token) => {
+                Some(Register::Sensor((token.as_bytes()[0] - b'A') as usize))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::Sensor(i) => write!(f, "{}", (b'A' + *i as u8) as char),
+            Register::T => write!(f, "T"),
+            Register::J => write!(f, "J"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    And(Register, Register),
+    Or(Register, Register),
+    Not(Register, Register),
+}
+
+impl Instruction {
+    fn registers(self) -> (Register, Register) {
+        match self {
+            Instruction::And(x, y) | Instruction::Or(x, y) | Instruction::Not(x, y) => (x, y),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::And(x, y) => write!(f, "AND {} {}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR {} {}", x, y),
+            Instruction::Not(x, y) => write!(f, "NOT {} {}", x, y),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[derive(Debug, PartialEq)]
+pub enum Report {
+    // The droid made it across and reported the hull damage.
+    Damage(i64),
+    // The droid fell into space; this is the last moments it printed.
+    Fell(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Script {
+    pub instructions: Vec<Instruction>,
+    pub mode: Mode,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script, ScriptError> {
+        let mut instructions = Vec::new();
+        let mut mode = None;
+        for (i, line) in source.lines().enumerate() {
+            let error = |message: String| ScriptError {
+                line: i + 1,
+                message,
+            };
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.is_empty() {
+                continue;
+            }
+            if mode.is_some() {
+                return Err(error("instruction after the mode".to_string()));
+            }
+            let instruction = match tokens[..] {
+                ["WALK"] => {
+                    mode = Some(Mode::Walk);
+                    continue;
+                }
+                ["RUN"] => {
+                    mode = Some(Mode::Run);
+                    continue;
+                }
+                [op, x, y] => {
+                    let register = |token| {
+                        Register::parse(token)
+                            .ok_or_else(|| error(format!("unknown register {}", token)))
+                    };
+                    let (x, y) = (register(x)?, register(y)?);
+                    match op {
+                        "AND" => Instruction::And(x, y),
+                        "OR" => Instruction::Or(x, y),
+                        "NOT" => Instruction::Not(x, y),
+                        _ => return Err(error(format!("unknown instruction {}", op))),
+                    }
+                }
+                _ => return Err(error(format!("cannot parse '{}'", line.trim()))),
+            };
+            instructions.push(instruction);
+        }
+        let script = Script {
+            instructions,
+            mode: mode.ok_or(ScriptError {
+                line: source.lines().count(),
+                message: "missing WALK or RUN".to_string(),
+            })?,
+        };
+        script.validate()?;
+        Ok(script)
+    }
+
+    // Checks the registers against the mode and the instruction limit. Lines
+    // are counted as if the script had been printed by `Display`.
+    pub fn validate(&self) -> Result<(), ScriptError> {
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            let error = |message: String| {
+                Err(ScriptError {
+                    line: i + 1,
+                    message,
+                })
+            };
+            let (x, y) = instruction.registers();
+            if let Register::Sensor(_) = y {
+                return error(format!("{} is not writable", y));
+            }
+            if let Register::Sensor(sensor) = x {
+                if sensor >= self.mode.sensors() {
+                    return error(format!("{} is not available in this mode", x));
+                }
+            }
+            if i == MAX_INSTRUCTIONS {
+                return error(format!("more than {} instructions", MAX_INSTRUCTIONS));
+            }
+        }
+        Ok(())
+    }
+
+    // Whether the droid jumps when it sees `ground`, where bit `i` is set if
+    // there is ground under sensor `i`.
+    pub fn jumps(&self, ground: u16) -> bool {
+        let (mut t, mut j) = (false, false);
+        for instruction in &self.instructions {
+            let (x, y) = instruction.registers();
+            let x = match x {
+                Register::Sensor(i) => ground & (1 << i) != 0,
+                Register::T => t,
+                Register::J => j,
+            };
+            let y = match y {
+                Register::T => &mut t,
+                _ => &mut j,
+            };
+            *y = match instruction {
+                Instruction::And(..) => x && *y,
+                Instruction::Or(..) => x || *y,
+                Instruction::Not(..) => !x,
+            };
+        }
+        j
+    }
+
+    // Walks the droid across `hull`, returning the tile it fell at.
+    pub fn simulate(&self, hull: &str) -> Result<(), usize> {
+        let hull = hull.as_bytes();
+        let mut position = 0;
+        while position < hull.len() {
+            if hull[position] != b'#' {
+                return Err(position);
+            }
+            if self.jumps(sense(hull, position, self.mode)) {
+                position += JUMP_LENGTH;
+            } else {
+                position += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // Sends the script to the springdroid program.
+    pub fn run(&self, program: &[i64]) -> Result<Report, Fault> {
+        let mut computer = Computer::new(program);
+        computer.push_ascii(&self.to_string());
+        match computer.run_until_blocked()? {
+            (outputs, Status::Halted) => match outputs.last() {
+                Some(&damage) if damage > 127 => Ok(Report::Damage(damage)),
+                _ => Ok(Report::Fell(decode_ascii(&outputs))),
+            },
+            _ => Err(Fault::MissingInput {
+                address: computer.counter(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+        match self.mode {
+            Mode::Walk => writeln!(f, "WALK"),
+            Mode::Run => writeln!(f, "RUN"),
+        }
+    }
+}
+
+fn sense(hull: &[u8], position: usize, mode: Mode) -> u16 {
+    (0..mode.sensors())
+        .filter(|i| hull.get(position + 1 + i).is_none_or(|&tile| tile == b'#'))
+        .fold(0, |ground, i| ground | 1 << i)
+}
+
+// Looks for a script that gets the droid across every hull. Whenever the
+// droid sees something it has not seen before, both walking on and jumping
+// are tried; each set of decisions that works is turned into a script, and
+// the first one that fits in memory is returned.
+pub fn synthesize(mode: Mode, hulls: &[&str]) -> Option<Script> {
+    let hulls: Vec<&[u8]> = hulls.iter().map(|hull| hull.as_bytes()).collect();
+    decide(mode, &hulls, &mut HashMap::new())
+}
+
+fn decide(mode: Mode, hulls: &[&[u8]], decisions: &mut HashMap<u16, bool>) -> Option<Script> {
+    let ground = match undecided(mode, hulls, decisions) {
+        Err(()) => return None,
+        Ok(Some(ground)) => ground,
+        Ok(None) => {
+            let script = express(mode, decisions);
+            return if script.instructions.len() <= MAX_INSTRUCTIONS {
+                Some(script)
+            } else {
+                None
+            };
+        }
+    };
+    for &jump in &[false, true] {
+        decisions.insert(ground, jump);
+        if let Some(script) = decide(mode, hulls, decisions) {
+            return Some(script);
+        }
+    }
+    decisions.remove(&ground);
+    None
+}
+
+// Follows the decisions made so far, returning the first view that has no
+// decision yet, or an error if the droid falls.
+fn undecided(
+    mode: Mode,
+    hulls: &[&[u8]],
+    decisions: &HashMap<u16, bool>,
+) -> Result<Option<u16>, ()> {
+    for hull in hulls {
+        let mut position = 0;
+        while position < hull.len() {
+            if hull[position] != b'#' {
+                return Err(());
+            }
+            let ground = sense(hull, position, mode);
+            match decisions.get(&ground) {
+                Some(true) => position += JUMP_LENGTH,
+                Some(false) => position += 1,
+                None => return Ok(Some(ground)),
+            }
+        }
+    }
+    Ok(None)
+}
+
+// A conjunction of sensor readings: the sensors in `mask` must read `value`.
+#[derive(Copy, Clone)]
+struct Term {
+    mask: u16,
+    value: u16,
+}
+
+impl Term {
+    fn matches(self, ground: u16) -> bool {
+        ground & self.mask == self.value
+    }
+}
+
+// Builds `J` as a disjunction of terms that covers every view the droid must
+// jump on and none it must walk on. Views that never came up can go either
+// way. Terms are picked greedily by views covered per instruction.
+fn express(mode: Mode, decisions: &HashMap<u16, bool>) -> Script {
+    let mut jump: Vec<u16> = decisions.iter().filter(|d| *d.1).map(|d| *d.0).collect();
+    let walk: Vec<u16> = decisions.iter().filter(|d| !*d.1).map(|d| *d.0).collect();
+    jump.sort_unstable();
+
+    let sensors = mode.sensors() as u32;
+    let mut terms = Vec::new();
+    for mask in 0..1u16 << sensors {
+        let mut value = mask;
+        loop {
+            let term = Term { mask, value };
+            if !walk.iter().any(|&ground| term.matches(ground)) {
+                terms.push(term);
+            }
+            if value == 0 {
+                break;
+            }
+            value = (value - 1) & mask;
+        }
+    }
+
+    let mut instructions = Vec::new();
+    while !jump.is_empty() {
+        let first = instructions.is_empty();
+        let (term, _, code) = terms
+            .iter()
+            .map(|&term| {
+                let covered = jump.iter().filter(|&&ground| term.matches(ground)).count();
+                (term, covered, emit(term, first, sensors))
+            })
+            .filter(|(_, covered, _)| *covered > 0)
+            .max_by(|(_, a, x), (_, b, y)| {
+                (a * y.len())
+                    .cmp(&(b * x.len()))
+                    .then(y.len().cmp(&x.len()))
+            })
+            .unwrap();
+        jump.retain(|&ground| !term.matches(ground));
+        instructions.extend(code);
+    }
+    Script { instructions, mode }
+}
+
+// Code that ORs `term` into `J`. The first term is built in `J` directly,
+// while it and `T` are still false; later ones are built in `T`.
+fn emit(term: Term, first: bool, sensors: u32) -> Vec<Instruction> {
+    use self::Instruction::{And, Not, Or};
+    use self::Register::{Sensor, J, T};
+
+    // negated readings first, as the first one costs a single NOT
+    let mut readings: Vec<(usize, bool)> = (0..sensors as usize)
+        .filter(|&i| term.mask & (1 << i) != 0)
+        .map(|i| (i, term.value & (1 << i) != 0))
+        .collect();
+    readings.sort_by_key(|&(i, ground)| (ground, i));
+
+    let mut code = Vec::new();
+    let target = if first { J } else { T };
+    match readings.first() {
+        None if first => return vec![Not(T, J)],
+        None => return vec![Not(J, T), Or(J, T), Or(T, J)],
+        Some(&(i, false)) => code.push(Not(Sensor(i), target)),
+        Some(&(i, true)) if first => code.push(Or(Sensor(i), J)),
+        Some(&(i, true)) => code.extend(&[Not(Sensor(i), T), Not(T, T)]),
+    }
+    for &(i, ground) in &readings[1..] {
+        match (ground, first) {
+            (true, _) => code.push(And(Sensor(i), target)),
+            (false, true) => code.extend(&[Not(Sensor(i), T), And(T, J)]),
+            (false, false) => code.extend(&[Not(T, T), Or(Sensor(i), T), Not(T, T)]),
+        }
+    }
+    if !first {
+        code.push(Or(T, J));
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{synthesize, Mode, Script, ScriptError};
+
+    const WALK_HULLS: [&str; 3] = [
+        "#####...##.#.####",
+        "#####..#.########",
+        "#####...##.#.####",
+    ];
+
+    #[test]
+    fn test_parse() {
+        let source = "NOT A J\nNOT B T\nOR T J\nNOT C T\nOR T J\nAND D J\nWALK\n";
+        let script = Script::parse(source).unwrap();
+        assert_eq!(script.mode, Mode::Walk);
+        assert_eq!(script.instructions.len(), 6);
+        assert_eq!(script.to_string(), source);
+
+        let error = |source: &str| Script::parse(source).unwrap_err();
+        assert_eq!(
+            error("NOT A J\nAND J B\nWALK"),
+            ScriptError {
+                line: 2,
+                message: "B is not writable".to_string()
+            }
+        );
+        assert_eq!(
+            error("NOT E J\nWALK").message,
+            "E is not available in this mode"
+        );
+        assert!(Script::parse("NOT E J\nRUN").is_ok());
+        assert_eq!(error("XOR A J\nWALK").message, "unknown instruction XOR");
+        assert_eq!(error("NOT A J").message, "missing WALK or RUN");
+        let long = "OR A J\n".repeat(16);
+        assert_eq!(error(&long).message, "missing WALK or RUN");
+        let long = long + "WALK";
+        assert_eq!(
+            error(&long),
+            ScriptError {
+                line: 16,
+                message: "more than 15 instructions".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_simulate() {
+        let script =
+            Script::parse("NOT A J\nNOT B T\nOR T J\nNOT C T\nOR T J\nAND D J\nWALK").unwrap();
+        for hull in &WALK_HULLS {
+            assert_eq!(script.simulate(hull), Ok(()));
+        }
+        let script = Script::parse("NOT A J\nWALK").unwrap();
+        assert_eq!(script.simulate(WALK_HULLS[0]), Ok(()));
+        assert_eq!(script.simulate(WALK_HULLS[1]), Err(8));
+    }
+
+    #[test]
+    fn test_synthesize() {
+        let script = synthesize(Mode::Walk, &WALK_HULLS).unwrap();
+        for hull in &WALK_HULLS {
+            assert_eq!(script.simulate(hull), Ok(()));
+        }
+        assert_eq!(Script::parse(&script.to_string()), Ok(script));
+
+        let hulls = [
+            "#####.#.##..#####",
+            "#####.##.##...###",
+            "#####...##.#.####",
+            "#####.#..########",
+        ];
+        let script = synthesize(Mode::Run, &hulls).unwrap();
+        for hull in &hulls {
+            assert_eq!(script.simulate(hull), Ok(()));
+        }
+        assert_eq!(Script::parse(&script.to_string()), Ok(script));
+
+        assert_eq!(synthesize(Mode::Walk, &["#####....#####"]), None);
+    }
+}
@@ -0,0 +1,695 @@
+// Finds inputs that make an Intcode program produce a given value.
+//
+// Some memory cells or inputs are marked as variables, each with a range of
+// allowed values. The program is first run symbolically: every value is an
+// expression over the variables, built up by `Addition`, `Multiplication`,
+// `LessThan` and `Equals`. This works as long as the instructions executed,
+// the addresses used and the jumps taken don't depend on a variable. If the
+// target value then turns out to be a polynomial, it is solved for one of the
+// variables directly, only enumerating the others. Otherwise the solver falls
+// back to search: evaluating the expression, or, if symbolic execution gave
+// up, running the program on every combination.
+
+use crate::computer::{Computer, Fault, Instruction, Parameter, Status};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
+
+const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Const(i64),
+    // Index into the solver's variables.
+    Var(usize),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+    Equals(Box<Expr>, Box<Expr>),
+    // Read through an address that depends on a variable. Harmless unless
+    // it ends up somewhere that matters.
+    Unknown,
+}
+
+impl Expr {
+    fn add(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const(a.wrapping_add(b)),
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (Expr::Const(0), e) | (e, Expr::Const(0)) => e,
+            (a, b) => Expr::Add(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn mul(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const(a.wrapping_mul(b)),
+            (Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (Expr::Const(1), e) | (e, Expr::Const(1)) => e,
+            (a, b) => Expr::Mul(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn less_than(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const((a < b) as i64),
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (a, b) => Expr::LessThan(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn equals(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const((a == b) as i64),
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (a, b) => Expr::Equals(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn constant(&self) -> Option<i64> {
+        match *self {
+            Expr::Const(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // `None` if the expression contains an unknown value.
+    pub fn eval(&self, values: &[i64]) -> Option<i64> {
+        Some(match self {
+            Expr::Const(value) => *value,
+            Expr::Var(i) => values[*i],
+            Expr::Add(a, b) => a.eval(values)?.wrapping_add(b.eval(values)?),
+            Expr::Mul(a, b) => a.eval(values)?.wrapping_mul(b.eval(values)?),
+            Expr::LessThan(a, b) => (a.eval(values)? < b.eval(values)?) as i64,
+            Expr::Equals(a, b) => (a.eval(values)? == b.eval(values)?) as i64,
+            Expr::Unknown => return None,
+        })
+    }
+
+    fn is_known(&self) -> bool {
+        match self {
+            Expr::Const(_) | Expr::Var(_) => true,
+            Expr::Add(a, b) | Expr::Mul(a, b) | Expr::LessThan(a, b) | Expr::Equals(a, b) => {
+                a.is_known() && b.is_known()
+            }
+            Expr::Unknown => false,
+        }
+    }
+
+    // `None` if the expression contains a comparison or a coefficient
+    // overflows.
+    pub fn to_polynomial(&self) -> Option<Polynomial> {
+        match self {
+            Expr::Const(value) => Some(Polynomial::constant(*value)),
+            Expr::Var(i) => {
+                let mut terms = BTreeMap::new();
+                terms.insert(vec![*i], 1);
+                Some(Polynomial { terms })
+            }
+            Expr::Add(a, b) => a.to_polynomial()?.add(&b.to_polynomial()?),
+            Expr::Mul(a, b) => a.to_polynomial()?.mul(&b.to_polynomial()?),
+            Expr::LessThan(..) | Expr::Equals(..) | Expr::Unknown => None,
+        }
+    }
+}
+
+// A sum of terms, each a coefficient times a product of variables. The
+// variables of a term are kept sorted, so `x * y` and `y * x` are the same
+// term.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polynomial {
+    terms: BTreeMap<Vec<usize>, i64>,
+}
+
+impl Polynomial {
+    fn constant(value: i64) -> Polynomial {
+        let mut terms = BTreeMap::new();
+        if value != 0 {
+            terms.insert(vec![], value);
+        }
+        Polynomial { terms }
+    }
+
+    fn add(&self, other: &Polynomial) -> Option<Polynomial> {
+        let mut terms = self.terms.clone();
+        for (vars, &coefficient) in &other.terms {
+            let sum = terms.get(vars).unwrap_or(&0).checked_add(coefficient)?;
+            if sum == 0 {
+                terms.remove(vars);
+            } else {
+                terms.insert(vars.clone(), sum);
+            }
+        }
+        Some(Polynomial { terms })
+    }
+
+    fn mul(&self, other: &Polynomial) -> Option<Polynomial> {
+        let mut product = Polynomial::constant(0);
+        for (a, &x) in &self.terms {
+            for (b, &y) in &other.terms {
+                let mut vars = [&a[..], &b[..]].concat();
+                vars.sort_unstable();
+                let mut terms = BTreeMap::new();
+                terms.insert(vars, x.checked_mul(y)?);
+                product = product.add(&Polynomial { terms })?;
+            }
+        }
+        Some(product)
+    }
+
+    pub fn degree(&self, var: usize) -> usize {
+        self.terms
+            .keys()
+            .map(|vars| vars.iter().filter(|&&v| v == var).count())
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Coefficients of the powers of `var` once every other variable has been
+    // given its value, lowest power first.
+    fn univariate(&self, var: usize, values: &[i64]) -> Option<Vec<i64>> {
+        let mut coefficients = vec![0i64; self.degree(var) + 1];
+        for (vars, &coefficient) in &self.terms {
+            let mut term = coefficient;
+            for &v in vars.iter().filter(|&&v| v != var) {
+                term = term.checked_mul(values[v])?;
+            }
+            let power = vars.iter().filter(|&&v| v == var).count();
+            coefficients[power] = coefficients[power].checked_add(term)?;
+        }
+        Some(coefficients)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    // The initial value of a memory cell.
+    Cell(i64),
+    // The next value the program reads.
+    Input,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    // A memory cell once the program halts.
+    Cell(i64),
+    // The n-th output, counting from 0.
+    Output(usize),
+}
+
+// Why symbolic execution gave up.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Unsupported {
+    SymbolicInstruction { address: i64 },
+    SymbolicAddress { address: i64 },
+    SymbolicJump { address: i64 },
+    MissingInput { address: i64 },
+    Fault(Fault),
+    StepLimit(u64),
+}
+
+// What symbolic execution found out. If it gave up part way, `memory` is
+// the state at that point and `outputs` are the outputs produced before it.
+pub struct Trace {
+    pub memory: HashMap<i64, Expr>,
+    pub outputs: Vec<Expr>,
+    pub stopped: Option<Unsupported>,
+}
+
+impl Trace {
+    // `None` if the program did not get as far as producing `target`.
+    pub fn get(&self, target: &Target) -> Option<Expr> {
+        match *target {
+            Target::Cell(_) if self.stopped.is_some() => None,
+            Target::Cell(address) => {
+                Some(self.memory.get(&address).cloned().unwrap_or(Expr::Const(0)))
+            }
+            Target::Output(i) => self.outputs.get(i).cloned(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Method {
+    // Solved the polynomial for one variable.
+    Algebraic,
+    // Evaluated the target expression for every combination.
+    Evaluation,
+    // Ran the program for every combination.
+    Execution,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Solution {
+    // In the order the variables were added.
+    pub values: Vec<i64>,
+    pub method: Method,
+}
+
+pub struct Solver {
+    program: Vec<i64>,
+    variables: Vec<(Source, RangeInclusive<i64>)>,
+    inputs: Vec<i64>,
+    max_steps: u64,
+}
+
+impl Solver {
+    pub fn new(program: &[i64]) -> Solver {
+        Solver {
+            program: program.to_vec(),
+            variables: Vec::new(),
+            inputs: Vec::new(),
+            max_steps: DEFAULT_MAX_STEPS,
+        }
+    }
+
+    // Input variables are read in the order they are added.
+    pub fn with_variable(mut self, source: Source, range: RangeInclusive<i64>) -> Solver {
+        self.variables.push((source, range));
+        self
+    }
+
+    // Known inputs, read once the input variables have been used up.
+    pub fn with_inputs(mut self, inputs: &[i64]) -> Solver {
+        self.inputs = inputs.to_vec();
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: u64) -> Solver {
+        self.max_steps = max_steps;
+        self
+    }
+
+    // Runs the program with the variables left unknown.
+    pub fn execute(&self) -> Trace {
+        let mut memory = HashMap::new();
+        let mut outputs = Vec::new();
+        let stopped = self.run_symbolic(&mut memory, &mut outputs).err();
+        Trace {
+            memory,
+            outputs,
+            stopped,
+        }
+    }
+
+    fn run_symbolic(
+        &self,
+        memory: &mut HashMap<i64, Expr>,
+        outputs: &mut Vec<Expr>,
+    ) -> Result<(), Unsupported> {
+        *memory = self
+            .program
+            .iter()
+            .enumerate()
+            .map(|(address, &word)| (address as i64, Expr::Const(word)))
+            .collect();
+        let mut inputs = Vec::new();
+        for (i, (source, _)) in self.variables.iter().enumerate() {
+            match *source {
+                Source::Cell(address) => {
+                    memory.insert(address, Expr::Var(i));
+                }
+                Source::Input => inputs.push(Expr::Var(i)),
+            }
+        }
+        inputs.extend(self.inputs.iter().map(|&value| Expr::Const(value)));
+        let mut inputs = inputs.into_iter();
+
+        let read = |memory: &HashMap<i64, Expr>, address: i64| {
+            if address < 0 {
+                return Err(Unsupported::Fault(Fault::InvalidAddress(address)));
+            }
+            Ok(memory.get(&address).cloned().unwrap_or(Expr::Const(0)))
+        };
+        let (mut counter, mut relative_base) = (0i64, 0i64);
+        for _ in 0..self.max_steps {
+            let word = read(memory, counter)?
+                .constant()
+                .ok_or(Unsupported::SymbolicInstruction { address: counter })?;
+            let instruction =
+                Instruction::new(word).ok_or(Unsupported::Fault(Fault::InvalidInstruction {
+                    address: counter,
+                    word,
+                }))?;
+            let mut addresses = Vec::new();
+            for (i, parameter) in instruction.parameters().into_iter().enumerate() {
+                let cell = counter + 1 + i as i64;
+                let address = match parameter {
+                    Parameter::Immediate => Some(cell),
+                    Parameter::Position | Parameter::Relative => {
+                        let offset = read(memory, cell)?.constant();
+                        if parameter == Parameter::Relative {
+                            offset.map(|offset| relative_base + offset)
+                        } else {
+                            offset
+                        }
+                    }
+                };
+                addresses.push(address);
+            }
+            // Reads through a symbolic address give an unknown value, but a
+            // write could land anywhere.
+            let operand = |i: usize| match addresses[i] {
+                Some(address) => read(memory, address),
+                None => Ok(Expr::Unknown),
+            };
+            let write_address = instruction.write_parameter().map(|i| addresses[i]);
+            let target = write_address
+                .unwrap_or(Some(0))
+                .ok_or(Unsupported::SymbolicAddress { address: counter })?;
+            let mut next = counter + instruction.len();
+            match instruction {
+                Instruction::Addition(..) => {
+                    let value = Expr::add(operand(0)?, operand(1)?);
+                    memory.insert(target, value);
+                }
+                Instruction::Multiplication(..) => {
+                    let value = Expr::mul(operand(0)?, operand(1)?);
+                    memory.insert(target, value);
+                }
+                Instruction::LessThan(..) => {
+                    let value = Expr::less_than(operand(0)?, operand(1)?);
+                    memory.insert(target, value);
+                }
+                Instruction::Equals(..) => {
+                    let value = Expr::equals(operand(0)?, operand(1)?);
+                    memory.insert(target, value);
+                }
+                Instruction::Input(_) => {
+                    let value = inputs
+                        .next()
+                        .ok_or(Unsupported::MissingInput { address: counter })?;
+                    memory.insert(target, value);
+                }
+                Instruction::Output(_) => outputs.push(operand(0)?),
+                Instruction::JumpIfTrue(..) | Instruction::JumpIfFalse(..) => {
+                    let condition = operand(0)?
+                        .constant()
+                        .ok_or(Unsupported::SymbolicJump { address: counter })?;
+                    let jumps_on_true = matches!(instruction, Instruction::JumpIfTrue(..));
+                    if (condition != 0) == jumps_on_true {
+                        next = operand(1)?
+                            .constant()
+                            .ok_or(Unsupported::SymbolicJump { address: counter })?;
+                    }
+                }
+                Instruction::AdjustRelativeBase(_) => {
+                    relative_base += operand(0)?
+                        .constant()
+                        .ok_or(Unsupported::SymbolicAddress { address: counter })?;
+                }
+                Instruction::Stop => return Ok(()),
+            }
+            counter = next;
+        }
+        Err(Unsupported::StepLimit(self.max_steps))
+    }
+
+    // Finds values for the variables, within their ranges, that make `target`
+    // equal `value`.
+    pub fn solve(&self, target: &Target, value: i64) -> Option<Solution> {
+        let trace = self.execute();
+        let expr = match trace.get(target) {
+            Some(expr) => expr,
+            None if trace.stopped.is_some() => return self.search_execution(target, value),
+            None => return None,
+        };
+        if !expr.is_known() {
+            return self.search_execution(target, value);
+        }
+        match expr.to_polynomial() {
+            Some(polynomial) => self.solve_polynomial(&polynomial, value),
+            None => self.search(Method::Evaluation, |values| {
+                expr.eval(values) == Some(value)
+            }),
+        }
+    }
+
+    fn solve_polynomial(&self, polynomial: &Polynomial, target: i64) -> Option<Solution> {
+        let ranges: Vec<&RangeInclusive<i64>> = self.variables.iter().map(|(_, r)| r).collect();
+        if ranges.iter().any(|r| r.is_empty()) {
+            return None;
+        }
+        // Solve for the variable of lowest degree, enumerating the rest.
+        let solved = (0..ranges.len())
+            .filter(|&v| polynomial.degree(v) > 0)
+            .min_by_key(|&v| {
+                (
+                    polynomial.degree(v),
+                    std::cmp::Reverse(range_len(ranges[v])),
+                )
+            });
+        let mut values: Vec<i64> = ranges.iter().map(|r| *r.start()).collect();
+        let solved = match solved {
+            Some(solved) => solved,
+            None => {
+                let constant = polynomial.terms.get(&vec![]).cloned().unwrap_or(0);
+                return if constant == target {
+                    Some(Solution {
+                        values,
+                        method: Method::Algebraic,
+                    })
+                } else {
+                    None
+                };
+            }
+        };
+        let others: Vec<usize> = (0..ranges.len())
+            .filter(|&v| v != solved && polynomial.degree(v) > 0)
+            .collect();
+        loop {
+            if let Some(coefficients) = polynomial.univariate(solved, &values) {
+                if let Some(x) = root(&coefficients, target, ranges[solved]) {
+                    values[solved] = x;
+                    return Some(Solution {
+                        values,
+                        method: Method::Algebraic,
+                    });
+                }
+            }
+            if !advance(&mut values, &others, &ranges) {
+                return None;
+            }
+        }
+    }
+
+    fn search(&self, method: Method, found: impl Fn(&[i64]) -> bool) -> Option<Solution> {
+        let ranges: Vec<&RangeInclusive<i64>> = self.variables.iter().map(|(_, r)| r).collect();
+        if ranges.iter().any(|r| r.is_empty()) {
+            return None;
+        }
+        let all: Vec<usize> = (0..ranges.len()).collect();
+        let mut values: Vec<i64> = ranges.iter().map(|r| *r.start()).collect();
+        loop {
+            if found(&values) {
+                return Some(Solution { values, method });
+            }
+            if !advance(&mut values, &all, &ranges) {
+                return None;
+            }
+        }
+    }
+
+    fn search_execution(&self, target: &Target, value: i64) -> Option<Solution> {
+        self.search(Method::Execution, |values| {
+            let mut program = self.program.clone();
+            let mut inputs = Vec::new();
+            for ((source, _), &v) in self.variables.iter().zip(values) {
+                match *source {
+                    Source::Cell(address) => {
+                        if address < 0 {
+                            return false;
+                        }
+                        if address as usize >= program.len() {
+                            program.resize(address as usize + 1, 0);
+                        }
+                        program[address as usize] = v;
+                    }
+                    Source::Input => inputs.push(v),
+                }
+            }
+            inputs.extend(&self.inputs);
+            let limits = crate::computer::Limits {
+                max_steps: Some(self.max_steps),
+                ..Default::default()
+            };
+            let mut computer = Computer::new(&program).with_limits(limits);
+            for input in inputs {
+                computer.push_input(input);
+            }
+            let mut outputs = Vec::new();
+            loop {
+                match computer.resume() {
+                    Ok(Status::Output(output)) => outputs.push(output),
+                    Ok(Status::Halted) => break,
+                    _ => return false,
+                }
+            }
+            match *target {
+                Target::Cell(address) => computer.memory(address) == value,
+                Target::Output(i) => outputs.get(i) == Some(&value),
+            }
+        })
+    }
+}
+
+fn range_len(range: &RangeInclusive<i64>) -> i128 {
+    *range.end() as i128 - *range.start() as i128 + 1
+}
+
+// Steps `values` at the positions in `vars` to the next combination, like an
+// odometer. Returns false once every combination has been seen.
+fn advance(values: &mut [i64], vars: &[usize], ranges: &[&RangeInclusive<i64>]) -> bool {
+    for &v in vars.iter().rev() {
+        if values[v] < *ranges[v].end() {
+            values[v] += 1;
+            return true;
+        }
+        values[v] = *ranges[v].start();
+    }
+    false
+}
+
+// Smallest x in `range` with sum(coefficients[i] * x^i) == target.
+fn root(coefficients: &[i64], target: i64, range: &RangeInclusive<i64>) -> Option<i64> {
+    let c: Vec<i128> = coefficients.iter().map(|&c| c as i128).collect();
+    let t = target as i128;
+    let candidates = match c.len() {
+        1 => {
+            return if c[0] == t && !range.is_empty() {
+                Some(*range.start())
+            } else {
+                None
+            }
+        }
+        2 => {
+            if c[1] == 0 || (t - c[0]) % c[1] != 0 {
+                return None;
+            }
+            vec![(t - c[0]) / c[1]]
+        }
+        3 if c[2] != 0 => {
+            let (a, b, c) = (c[2], c[1], c[0] - t);
+            let discriminant = b
+                .checked_mul(b)?
+                .checked_sub(a.checked_mul(c)?.checked_mul(4)?)?;
+            if discriminant < 0 {
+                return None;
+            }
+            let s = isqrt(discriminant);
+            if s * s != discriminant {
+                return None;
+            }
+            let mut roots: Vec<i128> = [-b - s, -b + s]
+                .iter()
+                .filter(|&&n| n % (2 * a) == 0)
+                .map(|&n| n / (2 * a))
+                .collect();
+            roots.sort_unstable();
+            roots
+        }
+        _ => {
+            // higher degrees are left to a scan over the range
+            return range.clone().find(|&x| {
+                c.iter()
+                    .rev()
+                    .try_fold(0i128, |acc, &k| acc.checked_mul(x as i128)?.checked_add(k))
+                    == Some(t)
+            });
+        }
+    };
+    candidates
+        .into_iter()
+        .filter(|&x| x >= *range.start() as i128 && x <= *range.end() as i128)
+        .map(|x| x as i64)
+        .next()
+}
+
+fn isqrt(n: i128) -> i128 {
+    let mut x = (n as f64).sqrt() as i128;
+    while x * x > n {
+        x -= 1;
+    }
+    while (x + 1) * (x + 1) <= n {
+        x += 1;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Method, Solution, Solver, Source, Target};
+    use crate::compiler::compile;
+    use crate::computer::{parse_program, Computer};
+
+    #[test]
+    fn test_linear() {
+        // cell 0 = cell 13 * 1000 + cell 14 + 7
+        let program = vec![1002, 13, 1000, 0, 1, 0, 14, 0, 1001, 0, 7, 0, 99, 0, 0];
+        let solver = Solver::new(&program)
+            .with_variable(Source::Cell(13), 0..=99)
+            .with_variable(Source::Cell(14), 0..=99);
+        assert_eq!(
+            solver.solve(&Target::Cell(0), 42_056),
+            Some(Solution {
+                values: vec![42, 49],
+                method: Method::Algebraic
+            })
+        );
+        assert_eq!(solver.solve(&Target::Cell(0), 142_056), None);
+
+        // 42 and 5 would do, but no value is allowed for cell 14
+        let solver = Solver::new(&program)
+            .with_variable(Source::Cell(13), 0..=99)
+            .with_variable(Source::Cell(14), 5..=3);
+        assert_eq!(solver.solve(&Target::Cell(0), 42_012), None);
+    }
+
+    #[test]
+    fn test_day2() {
+        let program = parse_program(include_str!("../input/2019/day2.txt")).unwrap();
+        let solution = Solver::new(&program)
+            .with_variable(Source::Cell(1), 0..=99)
+            .with_variable(Source::Cell(2), 0..=99)
+            .solve(&Target::Cell(0), 19690720)
+            .unwrap();
+        assert_eq!(solution.method, Method::Algebraic);
+    }
+
+    #[test]
+    fn test_polynomial_and_fallbacks() {
+        let source = "
+            fn main() {
+                let x = input();
+                let y = input();
+                output(x * x - 3 * x + y);
+                output(x < y);
+                if (x > 5) {
+                    output(x * y);
+                }
+            }";
+        let program = compile(source).unwrap();
+        let solver = Solver::new(&program)
+            .with_variable(Source::Input, -50..=50)
+            .with_variable(Source::Input, 0..=3);
+        assert_eq!(
+            solver.solve(&Target::Output(0), 1722),
+            Some(Solution {
+                values: vec![-40, 2],
+                method: Method::Algebraic
+            })
+        );
+        let solution = solver.solve(&Target::Output(1), 1).unwrap();
+        assert_eq!(solution.method, Method::Evaluation);
+        assert_eq!(solution.values, vec![-50, 0]);
+
+        // the branch depends on x, so every combination is run
+        let solution = solver.solve(&Target::Output(2), 150).unwrap();
+        assert_eq!(solution.method, Method::Execution);
+        assert_eq!(solution.values, vec![50, 3]);
+        assert_eq!(
+            Computer::new(&program).compute(&[50, 3]),
+            Some(50 * 50 - 150 + 3)
+        );
+    }
+}
@@ -0,0 +1,51 @@
+// Runs the intcode binary on small programs and checks how each run ends.
+
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+
+fn run(name: &str, program: &str, arguments: &[&str]) -> Output {
+    let path = env::temp_dir().join(format!("intcode-{}-{}.txt", name, std::process::id()));
+    fs::write(&path, program).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_intcode"))
+        .args(arguments)
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn test_exit_codes() {
+    let halting = run("halting", "104,42,99", &[]);
+    assert_eq!(halting.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&halting.stdout), "42\n");
+
+    // opcode 42 does not exist
+    let faulting = run("faulting", "104,1,42", &[]);
+    assert_eq!(faulting.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&faulting.stdout), "1\n");
+
+    // overflowing arithmetic is a fault too, not a panic
+    let overflowing = run("overflowing", "1102,9223372036854775807,2,0,99", &[]);
+    assert_eq!(overflowing.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&overflowing.stderr).contains("arithmetic overflow at 0"));
+
+    let looping = run("looping", "1105,1,0", &["--max-steps", "100"]);
+    assert_eq!(looping.status.code(), Some(2));
+}
+
+#[test]
+fn test_ascii() {
+    let output = run(
+        "ascii",
+        "104,72,104,105,104,1000,104,10,104,1000,99",
+        &["--ascii"],
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Hi\n1000\n\n1000\n"
+    );
+}